@@ -0,0 +1,402 @@
+// i32 stack runtime for the C backend
+//
+// exposes the same functions as the LLVM PRELUDE (and runtime/bignum.c), and is
+// pasted in front of the generated code by `befreak-compiler --emit c`, e.g.
+//   befreak-compiler --emit c prog.bf > prog.c && cc -O2 prog.c -o prog
+
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+typedef struct {
+    int32_t *cells;
+    size_t len;
+    size_t cap;
+} stack;
+
+// the cells each stack can hold, STACK_CELLS in src/runtime.rs
+#define STACK_CELLS 16384
+
+static stack primary_stack;
+static stack control_stack;
+
+// where the last operator that could fail is, set by at(). row 0 is nowhere yet
+static int32_t at_row;
+static int32_t at_column;
+static int32_t at_direction;
+static int32_t at_inverse;
+
+// operators run so far, and the most that can be under --max-steps
+static uint64_t steps;
+static uint64_t max_steps;
+
+static const char *const direction_names[] = {"North", "South", "East", "West"};
+
+// name, "a, b, c" then end, bottom of the stack first
+static void dump_stack(const char *name, const stack *s, const char *end) {
+    fprintf(stderr, "%s", name);
+    for (size_t i = 0; i < s->len; i++) {
+        fprintf(stderr, "%s%d", i ? ", " : "", s->cells[i]);
+    }
+    fprintf(stderr, "%s", end);
+}
+
+// "error at row 2, column 5 heading East (inverse): msg" and both stacks
+static void exit_with(const char *msg, int status) {
+    fflush(stdout);
+    if (at_row) {
+        fprintf(stderr, "error at row %d, column %d heading %s%s: %s\n", at_row, at_column,
+                direction_names[at_direction & 3], at_inverse ? " (inverse)" : "", msg);
+    } else {
+        fprintf(stderr, "error: %s\n", msg);
+    }
+    dump_stack("main:    [", &primary_stack, "]\n");
+    dump_stack("control: [", &control_stack, "]\n");
+    exit(status);
+}
+
+static void die(const char *msg) {
+    exit_with(msg, 1);
+}
+
+//// stacks
+
+static void stack_push(stack *s, int32_t val) {
+    if (s->len == STACK_CELLS) {
+        die(s == &primary_stack ? "stack overflow" : "control stack overflow");
+    }
+    if (s->len == s->cap) {
+        s->cap = s->cap ? s->cap * 2 : 64;
+        s->cells = realloc(s->cells, s->cap * sizeof(int32_t));
+        if (!s->cells) {
+            die("out of memory");
+        }
+    }
+    s->cells[s->len++] = val;
+}
+
+static int32_t stack_pop(stack *s) {
+    if (s->len == 0) {
+        die(s == &primary_stack ? "stack underflow" : "control stack underflow");
+    }
+    return s->cells[--s->len];
+}
+
+static int32_t stack_peek(stack *s, size_t depth) {
+    if (s->len <= depth) {
+        die(s == &primary_stack ? "stack underflow" : "control stack underflow");
+    }
+    return s->cells[s->len - 1 - depth];
+}
+
+// signed overflow is UB in C, so wrap through unsigned like LLVM's add/sub/mul
+static int32_t wrap(uint32_t val) {
+    return (int32_t)val;
+}
+
+//// helpers called by the generated code
+
+// op is the operator's character
+void unimplemented(int32_t op) {
+    char msg[] = "inverse '?' is unimplemented";
+    msg[9] = (char)op;
+    die(msg);
+}
+
+// called by the generated code before each operator that can fail, rows and
+// columns are 1 based and directions count North, South, East, West from 0
+void at(int32_t row, int32_t column, int32_t direction, int32_t inverse) {
+    at_row = row;
+    at_column = column;
+    at_direction = direction;
+    at_inverse = inverse;
+}
+
+// called by main under --dump-final-state once the program halts,
+// "main:    [a, b]" and "control: [c]", or {"main": [a, b], "control": [c]}
+void dump_final_state(int32_t json) {
+    fflush(stdout);
+    if (json) {
+        dump_stack("{\"main\": [", &primary_stack, "], ");
+        dump_stack("\"control\": [", &control_stack, "]}\n");
+    } else {
+        dump_stack("main:    [", &primary_stack, "]\n");
+        dump_stack("control: [", &control_stack, "]\n");
+    }
+}
+
+// called by main under --exit-code top once the program halts
+int32_t halt_status(void) {
+    return primary_stack.len ? primary_stack.cells[primary_stack.len - 1] : 0;
+}
+
+// called by main under --max-steps
+void limit_steps(uint64_t max) {
+    max_steps = max;
+}
+
+// called instead of at() before every operator but halt under --max-steps.
+// running out exits with the status timeout(1) uses
+void step(int32_t row, int32_t column, int32_t direction, int32_t inverse) {
+    at(row, column, direction, inverse);
+    if (steps == max_steps) {
+        exit_with("ran out of steps", 124);
+    }
+    steps++;
+}
+
+void push_stack(int32_t val) {
+    stack_push(&primary_stack, val);
+}
+
+void push_control_stack(int32_t val) {
+    stack_push(&control_stack, val);
+}
+
+// zero = zero, everything else = 1
+bool pop_control_stack_i1(void) {
+    return stack_pop(&control_stack) != 0;
+}
+
+void toggle_control_stack(void) {
+    stack_push(&control_stack, stack_pop(&control_stack) == 0);
+}
+
+//// specific befreak operator impls
+
+void bf_Number(int32_t num) {
+    stack_push(&primary_stack, stack_pop(&primary_stack) ^ num);
+}
+
+// simple stack
+void bf_PushZero(void) {
+    stack_push(&primary_stack, 0);
+}
+
+void bf_PopZero(void) {
+    stack_pop(&primary_stack);
+}
+
+void bf_PopMainToControl(void) {
+    stack_push(&control_stack, stack_pop(&primary_stack));
+}
+
+void bf_PopControlToMain(void) {
+    stack_push(&primary_stack, stack_pop(&control_stack));
+}
+
+void bf_SwapStacks(void) {
+    int32_t x = stack_pop(&primary_stack);
+    int32_t y = stack_pop(&control_stack);
+    stack_push(&primary_stack, y);
+    stack_push(&control_stack, x);
+}
+
+// i/o
+void bf_Write(void) {
+    putchar(stack_pop(&primary_stack));
+}
+
+void bf_Read(void) {
+    stack_push(&primary_stack, getchar());
+}
+
+// number
+void bf_Increment(void) {
+    stack_push(&primary_stack, wrap((uint32_t)stack_pop(&primary_stack) + 1));
+}
+
+void bf_Decrement(void) {
+    stack_push(&primary_stack, wrap((uint32_t)stack_pop(&primary_stack) - 1));
+}
+
+// (y x -- y+x x)
+void bf_Add(void) {
+    int32_t x = stack_pop(&primary_stack);
+    int32_t y = stack_pop(&primary_stack);
+    stack_push(&primary_stack, wrap((uint32_t)y + (uint32_t)x));
+    stack_push(&primary_stack, x);
+}
+
+// (y x -- y-x x)
+void bf_Subtract(void) {
+    int32_t x = stack_pop(&primary_stack);
+    int32_t y = stack_pop(&primary_stack);
+    stack_push(&primary_stack, wrap((uint32_t)y - (uint32_t)x));
+    stack_push(&primary_stack, x);
+}
+
+// (y x -- y/x y%x x), rounding down so the remainder takes the divisor's sign
+void bf_Divide(void) {
+    // checked before popping so the dump still shows both operands
+    if (stack_peek(&primary_stack, 0) == 0) {
+        die("division by zero");
+    }
+    int32_t x = stack_pop(&primary_stack);
+    int32_t y = stack_pop(&primary_stack);
+    // INT32_MIN / -1 overflows, and wraps back to INT32_MIN
+    int32_t div = x == -1 ? wrap(0u - (uint32_t)y) : y / x;
+    int32_t rem = x == -1 ? 0 : y % x;
+    if (rem != 0 && (rem < 0) != (x < 0)) {
+        div -= 1;
+        rem += x;
+    }
+    stack_push(&primary_stack, div);
+    stack_push(&primary_stack, rem);
+    stack_push(&primary_stack, x);
+}
+
+// (div rem x -- div*x+rem x)
+void bf_Multiply(void) {
+    int32_t x = stack_pop(&primary_stack);
+    int32_t rem = stack_pop(&primary_stack);
+    int32_t div = stack_pop(&primary_stack);
+    stack_push(&primary_stack, wrap((uint32_t)x * (uint32_t)div + (uint32_t)rem));
+    stack_push(&primary_stack, x);
+}
+
+// bitwise
+void bf_Not(void) {
+    stack_push(&primary_stack, ~stack_pop(&primary_stack));
+}
+
+// (z y x -- z^(y&x) y x)
+void bf_And(void) {
+    int32_t x = stack_pop(&primary_stack);
+    int32_t y = stack_pop(&primary_stack);
+    int32_t z = stack_pop(&primary_stack);
+    stack_push(&primary_stack, z ^ (y & x));
+    stack_push(&primary_stack, y);
+    stack_push(&primary_stack, x);
+}
+
+// (z y x -- z^(y|x) y x)
+void bf_Or(void) {
+    int32_t x = stack_pop(&primary_stack);
+    int32_t y = stack_pop(&primary_stack);
+    int32_t z = stack_pop(&primary_stack);
+    stack_push(&primary_stack, z ^ (y | x));
+    stack_push(&primary_stack, y);
+    stack_push(&primary_stack, x);
+}
+
+// (y x -- y^x x)
+void bf_Xor(void) {
+    int32_t x = stack_pop(&primary_stack);
+    int32_t y = stack_pop(&primary_stack);
+    stack_push(&primary_stack, y ^ x);
+    stack_push(&primary_stack, x);
+}
+
+// rotates are mod 32, like llvm.fshl/llvm.fshr
+void bf_RotateLeft(void) {
+    int32_t x = stack_pop(&primary_stack);
+    uint32_t y = (uint32_t)stack_pop(&primary_stack);
+    uint32_t amount = (uint32_t)x & 31;
+    stack_push(&primary_stack, wrap(amount ? (y << amount) | (y >> (32 - amount)) : y));
+    stack_push(&primary_stack, x);
+}
+
+void bf_RotateRight(void) {
+    int32_t x = stack_pop(&primary_stack);
+    uint32_t y = (uint32_t)stack_pop(&primary_stack);
+    uint32_t amount = (uint32_t)x & 31;
+    stack_push(&primary_stack, wrap(amount ? (y >> amount) | (y << (32 - amount)) : y));
+    stack_push(&primary_stack, x);
+}
+
+// comparisons
+void bf_ToggleControl(void) {
+    toggle_control_stack();
+}
+
+void bf_EqualityCheck(void) {
+    if (stack_peek(&primary_stack, 1) == stack_peek(&primary_stack, 0)) {
+        toggle_control_stack();
+    }
+}
+
+void bf_LessThanCheck(void) {
+    if (stack_peek(&primary_stack, 1) < stack_peek(&primary_stack, 0)) {
+        toggle_control_stack();
+    }
+}
+
+void bf_GreaterThanCheck(void) {
+    if (stack_peek(&primary_stack, 1) > stack_peek(&primary_stack, 0)) {
+        toggle_control_stack();
+    }
+}
+
+// complex stack
+void bf_SwapTop(void) {
+    int32_t x = stack_pop(&primary_stack);
+    int32_t y = stack_pop(&primary_stack);
+    stack_push(&primary_stack, x);
+    stack_push(&primary_stack, y);
+}
+
+// (z y x -- y x z)
+void bf_Dig(void) {
+    int32_t x = stack_pop(&primary_stack);
+    int32_t y = stack_pop(&primary_stack);
+    int32_t z = stack_pop(&primary_stack);
+    stack_push(&primary_stack, y);
+    stack_push(&primary_stack, x);
+    stack_push(&primary_stack, z);
+}
+
+// (z y x -- x z y)
+void bf_Bury(void) {
+    int32_t x = stack_pop(&primary_stack);
+    int32_t y = stack_pop(&primary_stack);
+    int32_t z = stack_pop(&primary_stack);
+    stack_push(&primary_stack, x);
+    stack_push(&primary_stack, z);
+    stack_push(&primary_stack, y);
+}
+
+// (z y x -- x y z)
+void bf_Flip(void) {
+    int32_t x = stack_pop(&primary_stack);
+    int32_t y = stack_pop(&primary_stack);
+    int32_t z = stack_pop(&primary_stack);
+    stack_push(&primary_stack, x);
+    stack_push(&primary_stack, y);
+    stack_push(&primary_stack, z);
+}
+
+// (z y x -- y z x)
+void bf_SwapLower(void) {
+    int32_t x = stack_pop(&primary_stack);
+    int32_t y = stack_pop(&primary_stack);
+    int32_t z = stack_pop(&primary_stack);
+    stack_push(&primary_stack, y);
+    stack_push(&primary_stack, z);
+    stack_push(&primary_stack, x);
+}
+
+// (y x -- y x y)
+void bf_Over(void) {
+    stack_push(&primary_stack, stack_peek(&primary_stack, 1));
+}
+
+// (y x y -- y x), assumes the top and third are the same
+void bf_Under(void) {
+    stack_pop(&primary_stack);
+}
+
+// misc
+void bf_Duplicate(void) {
+    stack_push(&primary_stack, stack_peek(&primary_stack, 0));
+}
+
+// assumes top two are same
+void bf_Unduplicate(void) {
+    stack_pop(&primary_stack);
+}
+
+void bf_Halt(void) {
+    exit(0);
+}
@@ -0,0 +1,463 @@
+  ;; i32 stack runtime for the WebAssembly backend
+  ;;
+  ;; pasted inside the (module ...) emitted by `befreak-compiler --emit wat`.
+  ;; i/o goes through the wasi fd_write/fd_read/proc_exit imports, so modules run
+  ;; under `wasmtime run prog.wat` and a browser only needs to shim those three.
+  ;;
+  ;; memory layout:
+  ;;   0..32       scratch for iovecs and single chars
+  ;;   32..1024    messages, see the data segments below
+  ;;   1024..      primary stack, then control stack, STACK_CELLS (src/runtime.rs)
+  ;;               i32s each
+
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read"
+    (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "proc_exit"
+    (func $proc_exit (param i32)))
+
+  (memory (export "memory") 3)
+
+  (data (i32.const 32) "stack underflow")
+  (data (i32.const 48) "control stack underflow")
+  (data (i32.const 80) "stack overflow")
+  (data (i32.const 96) "division by zero")
+  ;; the operator goes between the quotes, see $unimplemented
+  (data (i32.const 128) "inverse '?' is unimplemented")
+  (data (i32.const 160) "error at row ")
+  (data (i32.const 176) ", column ")
+  (data (i32.const 192) " heading ")
+  (data (i32.const 208) " (inverse)")
+  (data (i32.const 224) ": ")
+  (data (i32.const 232) "error")
+  ;; 8 bytes apart, so $die can index them by direction
+  (data (i32.const 240) "North")
+  (data (i32.const 248) "South")
+  (data (i32.const 256) "East")
+  (data (i32.const 264) "West")
+  (data (i32.const 272) "main:    [")
+  (data (i32.const 288) "control: [")
+  (data (i32.const 304) ", ")
+  (data (i32.const 312) "]\n")
+  (data (i32.const 320) "control stack overflow")
+  (data (i32.const 344) "ran out of steps")
+  (data (i32.const 368) "{\"main\": [")
+  (data (i32.const 384) "\"control\": [")
+  (data (i32.const 400) "], ")
+  (data (i32.const 408) "]}\n")
+
+  ;; offsets point at the most recent value inserted, like the LLVM PRELUDE
+  (global $primary_base i32 (i32.const 1024))
+  (global $control_base i32 (i32.const 66560))
+  (global $stack_cells i32 (i32.const 16384))
+  (global $primary_offset (mut i32) (i32.const -1))
+  (global $control_offset (mut i32) (i32.const -1))
+  ;; where the last operator that could fail is, set by $at. row 0 is nowhere yet
+  (global $at_row (mut i32) (i32.const 0))
+  (global $at_column (mut i32) (i32.const 0))
+  (global $at_direction (mut i32) (i32.const 0))
+  (global $at_inverse (mut i32) (i32.const 0))
+
+  ;; operators run so far, and the most that can be under --max-steps
+  (global $steps (mut i64) (i64.const 0))
+  (global $max_steps (mut i64) (i64.const 0))
+
+  ;;;; general utility functions
+
+  (func $write_bytes (param $fd i32) (param $ptr i32) (param $len i32)
+    (i32.store (i32.const 0) (local.get $ptr))
+    (i32.store (i32.const 4) (local.get $len))
+    (drop (call $fd_write (local.get $fd) (i32.const 0) (i32.const 1) (i32.const 8))))
+
+  (func $put_char (param $char i32)
+    (i32.store8 (i32.const 16) (local.get $char))
+    (call $write_bytes (i32.const 1) (i32.const 16) (i32.const 1)))
+
+  (func $write_int (param $fd i32) (param $val i32)
+    (local $ptr i32)
+    (local $mag i32)
+    ;; digits are written backwards from the end of the scratch area
+    (local.set $ptr (i32.const 32))
+    (local.set $mag
+      (select
+        (i32.sub (i32.const 0) (local.get $val))
+        (local.get $val)
+        (i32.lt_s (local.get $val) (i32.const 0))))
+    (loop $digits
+      (local.set $ptr (i32.sub (local.get $ptr) (i32.const 1)))
+      (i32.store8 (local.get $ptr)
+        (i32.add (i32.const 48) (i32.rem_u (local.get $mag) (i32.const 10))))
+      (local.set $mag (i32.div_u (local.get $mag) (i32.const 10)))
+      (br_if $digits (local.get $mag)))
+    (if (i32.lt_s (local.get $val) (i32.const 0))
+      (then
+        (local.set $ptr (i32.sub (local.get $ptr) (i32.const 1)))
+        (i32.store8 (local.get $ptr) (i32.const 45))))
+    (call $write_bytes (local.get $fd) (local.get $ptr) (i32.sub (i32.const 32) (local.get $ptr))))
+
+  ;; name, "a, b, c" then end on stderr, bottom of the stack first
+  (func $dump_stack (param $name i32) (param $name_len i32) (param $base i32) (param $offset i32)
+    (param $end i32) (param $end_len i32)
+    (local $i i32)
+    (call $write_bytes (i32.const 2) (local.get $name) (local.get $name_len))
+    (block $done
+      (loop $each
+        (br_if $done (i32.gt_s (local.get $i) (local.get $offset)))
+        (if (local.get $i)
+          (then (call $write_bytes (i32.const 2) (i32.const 304) (i32.const 2))))
+        (call $write_int (i32.const 2)
+          (i32.load (i32.add (local.get $base) (i32.shl (local.get $i) (i32.const 2)))))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $each)))
+    (call $write_bytes (i32.const 2) (local.get $end) (local.get $end_len)))
+
+  (func $die (param $ptr i32) (param $len i32)
+    (call $exit_with (local.get $ptr) (local.get $len) (i32.const 1)))
+
+  ;; "error at row 2, column 5 heading East (inverse): msg" and both stacks on stderr
+  (func $exit_with (param $ptr i32) (param $len i32) (param $status i32)
+    (if (global.get $at_row)
+      (then
+        (call $write_bytes (i32.const 2) (i32.const 160) (i32.const 13))
+        (call $write_int (i32.const 2) (global.get $at_row))
+        (call $write_bytes (i32.const 2) (i32.const 176) (i32.const 9))
+        (call $write_int (i32.const 2) (global.get $at_column))
+        (call $write_bytes (i32.const 2) (i32.const 192) (i32.const 9))
+        (call $write_bytes (i32.const 2)
+          (i32.add (i32.const 240) (i32.shl (global.get $at_direction) (i32.const 3)))
+          ;; North and South are 5 letters, East and West 4
+          (select (i32.const 5) (i32.const 4) (i32.lt_u (global.get $at_direction) (i32.const 2))))
+        (if (global.get $at_inverse)
+          (then (call $write_bytes (i32.const 2) (i32.const 208) (i32.const 10)))))
+      (else (call $write_bytes (i32.const 2) (i32.const 232) (i32.const 5))))
+    (call $write_bytes (i32.const 2) (i32.const 224) (i32.const 2))
+    (call $write_bytes (i32.const 2) (local.get $ptr) (local.get $len))
+    ;; the newline after the ]
+    (call $write_bytes (i32.const 2) (i32.const 313) (i32.const 1))
+    (call $dump_stack (i32.const 272) (i32.const 10) (global.get $primary_base) (global.get $primary_offset)
+      (i32.const 312) (i32.const 2))
+    (call $dump_stack (i32.const 288) (i32.const 10) (global.get $control_base) (global.get $control_offset)
+      (i32.const 312) (i32.const 2))
+    (call $proc_exit (local.get $status)))
+
+  ;; called by the generated code before each operator that can fail, rows and
+  ;; columns are 1 based and directions count North, South, East, West from 0
+  (func $at (param $row i32) (param $column i32) (param $direction i32) (param $inverse i32)
+    (global.set $at_row (local.get $row))
+    (global.set $at_column (local.get $column))
+    (global.set $at_direction (local.get $direction))
+    (global.set $at_inverse (local.get $inverse)))
+
+  ;; called by _start under --dump-final-state once the program halts,
+  ;; "main:    [a, b]" and "control: [c]", or {"main": [a, b], "control": [c]}
+  (func $dump_final_state (param $json i32)
+    (if (local.get $json)
+      (then
+        (call $dump_stack (i32.const 368) (i32.const 10) (global.get $primary_base) (global.get $primary_offset)
+          (i32.const 400) (i32.const 3))
+        (call $dump_stack (i32.const 384) (i32.const 12) (global.get $control_base) (global.get $control_offset)
+          (i32.const 408) (i32.const 3)))
+      (else
+        (call $dump_stack (i32.const 272) (i32.const 10) (global.get $primary_base) (global.get $primary_offset)
+          (i32.const 312) (i32.const 2))
+        (call $dump_stack (i32.const 288) (i32.const 10) (global.get $control_base) (global.get $control_offset)
+          (i32.const 312) (i32.const 2)))))
+
+  ;; called by _start under --exit-code top once the program halts
+  (func $halt_status (result i32)
+    (if (result i32) (i32.lt_s (global.get $primary_offset) (i32.const 0))
+      (then (i32.const 0))
+      (else (i32.load (call $primary_addr (global.get $primary_offset))))))
+
+  ;; called by _start under --max-steps
+  (func $limit_steps (param $max i64)
+    (global.set $max_steps (local.get $max)))
+
+  ;; called instead of $at before every operator but halt under --max-steps.
+  ;; running out exits with the status timeout(1) uses
+  (func $step (param $row i32) (param $column i32) (param $direction i32) (param $inverse i32)
+    (call $at (local.get $row) (local.get $column) (local.get $direction) (local.get $inverse))
+    (if (i64.eq (global.get $steps) (global.get $max_steps))
+      (then (call $exit_with (i32.const 344) (i32.const 16) (i32.const 124))))
+    (global.set $steps (i64.add (global.get $steps) (i64.const 1))))
+
+  (func $unimplemented (param $operator i32)
+    (i32.store8 (i32.const 137) (local.get $operator))
+    (call $die (i32.const 128) (i32.const 28)))
+
+  (func $primary_addr (param $offset i32) (result i32)
+    (i32.add (global.get $primary_base) (i32.shl (local.get $offset) (i32.const 2))))
+
+  (func $control_addr (param $offset i32) (result i32)
+    (i32.add (global.get $control_base) (i32.shl (local.get $offset) (i32.const 2))))
+
+  (func $push_stack (param $val i32)
+    (if (i32.ge_s (i32.add (global.get $primary_offset) (i32.const 1)) (global.get $stack_cells))
+      (then (call $die (i32.const 80) (i32.const 14))))
+    (global.set $primary_offset (i32.add (global.get $primary_offset) (i32.const 1)))
+    (i32.store (call $primary_addr (global.get $primary_offset)) (local.get $val)))
+
+  (func $push_control_stack (param $val i32)
+    (if (i32.ge_s (i32.add (global.get $control_offset) (i32.const 1)) (global.get $stack_cells))
+      (then (call $die (i32.const 320) (i32.const 22))))
+    (global.set $control_offset (i32.add (global.get $control_offset) (i32.const 1)))
+    (i32.store (call $control_addr (global.get $control_offset)) (local.get $val)))
+
+  (func $peek_stack (param $depth i32) (result i32)
+    (local $offset i32)
+    (local.set $offset (i32.sub (global.get $primary_offset) (local.get $depth)))
+    (if (i32.lt_s (local.get $offset) (i32.const 0))
+      (then (call $die (i32.const 32) (i32.const 15))))
+    (i32.load (call $primary_addr (local.get $offset))))
+
+  (func $pop_stack (result i32)
+    (local $val i32)
+    (local.set $val (call $peek_stack (i32.const 0)))
+    (global.set $primary_offset (i32.sub (global.get $primary_offset) (i32.const 1)))
+    (local.get $val))
+
+  (func $pop_control_stack (result i32)
+    (local $val i32)
+    (if (i32.lt_s (global.get $control_offset) (i32.const 0))
+      (then (call $die (i32.const 48) (i32.const 23))))
+    (local.set $val (i32.load (call $control_addr (global.get $control_offset))))
+    (global.set $control_offset (i32.sub (global.get $control_offset) (i32.const 1)))
+    (local.get $val))
+
+  ;; zero = zero, everything else = 1
+  (func $pop_control_stack_i1 (result i32)
+    (i32.ne (call $pop_control_stack) (i32.const 0)))
+
+  (func $toggle_control_stack
+    (call $push_control_stack (i32.eqz (call $pop_control_stack))))
+
+  ;;;; specific befreak operator impls
+
+  (func $bf_Number (param $num i32)
+    (call $push_stack (i32.xor (call $pop_stack) (local.get $num))))
+
+  ;; simple stack
+  (func $bf_PushZero
+    (call $push_stack (i32.const 0)))
+
+  (func $bf_PopZero
+    (drop (call $pop_stack)))
+
+  (func $bf_PopMainToControl
+    (call $push_control_stack (call $pop_stack)))
+
+  (func $bf_PopControlToMain
+    (call $push_stack (call $pop_control_stack)))
+
+  (func $bf_SwapStacks
+    (local $x i32)
+    (local $y i32)
+    (local.set $x (call $pop_stack))
+    (local.set $y (call $pop_control_stack))
+    (call $push_stack (local.get $y))
+    (call $push_control_stack (local.get $x)))
+
+  ;; i/o
+  (func $bf_Write
+    (call $put_char (call $pop_stack)))
+
+  (func $bf_Read
+    (i32.store (i32.const 0) (i32.const 16))
+    (i32.store (i32.const 4) (i32.const 1))
+    (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))
+    ;; nothing read means end of input
+    (call $push_stack
+      (select
+        (i32.load8_u (i32.const 16))
+        (i32.const -1)
+        (i32.load (i32.const 8)))))
+
+  ;; number
+  (func $bf_Increment
+    (call $push_stack (i32.add (call $pop_stack) (i32.const 1))))
+
+  (func $bf_Decrement
+    (call $push_stack (i32.sub (call $pop_stack) (i32.const 1))))
+
+  (func $bf_Add
+    (local $x i32)
+    (local.set $x (call $pop_stack))
+    (call $push_stack (i32.add (call $pop_stack) (local.get $x)))
+    (call $push_stack (local.get $x)))
+
+  (func $bf_Subtract
+    (local $x i32)
+    (local.set $x (call $pop_stack))
+    (call $push_stack (i32.sub (call $pop_stack) (local.get $x)))
+    (call $push_stack (local.get $x)))
+
+  ;; rounds down, so the remainder takes the divisor's sign
+  (func $bf_Divide
+    (local $x i32)
+    (local $y i32)
+    (local $div i32)
+    (local $rem i32)
+    ;; checked before popping so the dump still shows both operands
+    (if (i32.eqz (call $peek_stack (i32.const 0)))
+      (then (call $die (i32.const 96) (i32.const 16))))
+    (local.set $x (call $pop_stack))
+    (local.set $y (call $pop_stack))
+    ;; i32.div_s traps on INT32_MIN / -1, which wraps back to INT32_MIN
+    (if (i32.eq (local.get $x) (i32.const -1))
+      (then
+        (local.set $div (i32.sub (i32.const 0) (local.get $y)))
+        (local.set $rem (i32.const 0)))
+      (else
+        (local.set $div (i32.div_s (local.get $y) (local.get $x)))
+        (local.set $rem (i32.rem_s (local.get $y) (local.get $x)))))
+    (if (i32.and
+          (i32.ne (local.get $rem) (i32.const 0))
+          (i32.ne (i32.lt_s (local.get $rem) (i32.const 0))
+                  (i32.lt_s (local.get $x) (i32.const 0))))
+      (then
+        (local.set $div (i32.sub (local.get $div) (i32.const 1)))
+        (local.set $rem (i32.add (local.get $rem) (local.get $x)))))
+    (call $push_stack (local.get $div))
+    (call $push_stack (local.get $rem))
+    (call $push_stack (local.get $x)))
+
+  (func $bf_Multiply
+    (local $x i32)
+    (local $rem i32)
+    (local $div i32)
+    (local.set $x (call $pop_stack))
+    (local.set $rem (call $pop_stack))
+    (local.set $div (call $pop_stack))
+    (call $push_stack
+      (i32.add (i32.mul (local.get $x) (local.get $div)) (local.get $rem)))
+    (call $push_stack (local.get $x)))
+
+  ;; bitwise
+  (func $bf_Not
+    (call $push_stack (i32.xor (call $pop_stack) (i32.const -1))))
+
+  (func $bf_And
+    (local $x i32)
+    (local $y i32)
+    (local.set $x (call $pop_stack))
+    (local.set $y (call $pop_stack))
+    (call $push_stack
+      (i32.xor (call $pop_stack) (i32.and (local.get $y) (local.get $x))))
+    (call $push_stack (local.get $y))
+    (call $push_stack (local.get $x)))
+
+  (func $bf_Or
+    (local $x i32)
+    (local $y i32)
+    (local.set $x (call $pop_stack))
+    (local.set $y (call $pop_stack))
+    (call $push_stack
+      (i32.xor (call $pop_stack) (i32.or (local.get $y) (local.get $x))))
+    (call $push_stack (local.get $y))
+    (call $push_stack (local.get $x)))
+
+  (func $bf_Xor
+    (local $x i32)
+    (local.set $x (call $pop_stack))
+    (call $push_stack (i32.xor (call $pop_stack) (local.get $x)))
+    (call $push_stack (local.get $x)))
+
+  (func $bf_RotateLeft
+    (local $x i32)
+    (local.set $x (call $pop_stack))
+    (call $push_stack (i32.rotl (call $pop_stack) (local.get $x)))
+    (call $push_stack (local.get $x)))
+
+  (func $bf_RotateRight
+    (local $x i32)
+    (local.set $x (call $pop_stack))
+    (call $push_stack (i32.rotr (call $pop_stack) (local.get $x)))
+    (call $push_stack (local.get $x)))
+
+  ;; comparisons
+  (func $bf_ToggleControl
+    (call $toggle_control_stack))
+
+  (func $bf_EqualityCheck
+    (if (i32.eq (call $peek_stack (i32.const 1)) (call $peek_stack (i32.const 0)))
+      (then (call $toggle_control_stack))))
+
+  (func $bf_LessThanCheck
+    (if (i32.lt_s (call $peek_stack (i32.const 1)) (call $peek_stack (i32.const 0)))
+      (then (call $toggle_control_stack))))
+
+  (func $bf_GreaterThanCheck
+    (if (i32.gt_s (call $peek_stack (i32.const 1)) (call $peek_stack (i32.const 0)))
+      (then (call $toggle_control_stack))))
+
+  ;; complex stack
+  (func $bf_SwapTop
+    (local $x i32)
+    (local $y i32)
+    (local.set $x (call $pop_stack))
+    (local.set $y (call $pop_stack))
+    (call $push_stack (local.get $x))
+    (call $push_stack (local.get $y)))
+
+  (func $bf_Dig
+    (local $x i32)
+    (local $y i32)
+    (local $z i32)
+    (local.set $x (call $pop_stack))
+    (local.set $y (call $pop_stack))
+    (local.set $z (call $pop_stack))
+    (call $push_stack (local.get $y))
+    (call $push_stack (local.get $x))
+    (call $push_stack (local.get $z)))
+
+  (func $bf_Bury
+    (local $x i32)
+    (local $y i32)
+    (local $z i32)
+    (local.set $x (call $pop_stack))
+    (local.set $y (call $pop_stack))
+    (local.set $z (call $pop_stack))
+    (call $push_stack (local.get $x))
+    (call $push_stack (local.get $z))
+    (call $push_stack (local.get $y)))
+
+  (func $bf_Flip
+    (local $x i32)
+    (local $y i32)
+    (local $z i32)
+    (local.set $x (call $pop_stack))
+    (local.set $y (call $pop_stack))
+    (local.set $z (call $pop_stack))
+    (call $push_stack (local.get $x))
+    (call $push_stack (local.get $y))
+    (call $push_stack (local.get $z)))
+
+  (func $bf_SwapLower
+    (local $x i32)
+    (local $y i32)
+    (local $z i32)
+    (local.set $x (call $pop_stack))
+    (local.set $y (call $pop_stack))
+    (local.set $z (call $pop_stack))
+    (call $push_stack (local.get $y))
+    (call $push_stack (local.get $z))
+    (call $push_stack (local.get $x)))
+
+  (func $bf_Over
+    (call $push_stack (call $peek_stack (i32.const 1))))
+
+  ;; assumes the top and third are the same
+  (func $bf_Under
+    (drop (call $pop_stack)))
+
+  ;; misc
+  (func $bf_Duplicate
+    (call $push_stack (call $peek_stack (i32.const 0))))
+
+  ;; assumes top two are same
+  (func $bf_Unduplicate
+    (drop (call $pop_stack)))
+
+  (func $bf_Halt
+    (call $proc_exit (i32.const 0)))
@@ -0,0 +1,778 @@
+// arbitrary precision runtime for `--bignum` mode
+//
+// stack cells are unbounded integers, so this replaces the whole PRELUDE:
+// the generated IR only declares these functions and links against them, e.g.
+//   befreak-compiler --bignum prog.bf > prog.ll
+//   llc -filetype=obj prog.ll -o prog.o && cc prog.o runtime/bignum.c -o prog
+// or, without a native link step:
+//   cc -c runtime/bignum.c -o bignum.o && lli --extra-object=bignum.o prog.ll
+
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+// sign-magnitude, little endian 32 bit limbs, no leading zero limbs
+// zero is always sign 0 with len 0
+typedef struct {
+    int sign;
+    size_t len;
+    uint32_t *limbs;
+} big;
+
+typedef struct {
+    big *cells;
+    size_t len;
+    size_t cap;
+} stack;
+
+// the cells each stack can hold, STACK_CELLS in src/runtime.rs
+#define STACK_CELLS 16384
+
+static stack primary_stack;
+static stack control_stack;
+
+static void print_big(FILE *file, const big *x);
+
+// where the last operator that could fail is, set by at(). row 0 is nowhere yet
+static int32_t at_row;
+static int32_t at_column;
+static int32_t at_direction;
+static int32_t at_inverse;
+
+// operators run so far, and the most that can be under --max-steps
+static uint64_t steps;
+static uint64_t max_steps;
+
+static const char *const direction_names[] = {"North", "South", "East", "West"};
+
+// name, "a, b, c" then end, bottom of the stack first
+static void dump_stack(const char *name, const stack *s, const char *end) {
+    fprintf(stderr, "%s", name);
+    for (size_t i = 0; i < s->len; i++) {
+        fprintf(stderr, "%s", i ? ", " : "");
+        print_big(stderr, &s->cells[i]);
+    }
+    fprintf(stderr, "%s", end);
+}
+
+// "error at row 2, column 5 heading East (inverse): msg" and both stacks
+static void exit_with(const char *msg, int status) {
+    fflush(stdout);
+    if (at_row) {
+        fprintf(stderr, "error at row %d, column %d heading %s%s: %s\n", at_row, at_column,
+                direction_names[at_direction & 3], at_inverse ? " (inverse)" : "", msg);
+    } else {
+        fprintf(stderr, "error: %s\n", msg);
+    }
+    dump_stack("main:    [", &primary_stack, "]\n");
+    dump_stack("control: [", &control_stack, "]\n");
+    exit(status);
+}
+
+static void die(const char *msg) {
+    exit_with(msg, 1);
+}
+
+static void *xrealloc(void *ptr, size_t size) {
+    void *res = realloc(ptr, size ? size : 1);
+    if (!res) {
+        die("out of memory");
+    }
+    return res;
+}
+
+//// magnitudes
+
+static big big_alloc(size_t len) {
+    big res = {0, len, xrealloc(NULL, len * sizeof(uint32_t))};
+    memset(res.limbs, 0, len * sizeof(uint32_t));
+    return res;
+}
+
+static void big_free(big *x) {
+    free(x->limbs);
+    x->limbs = NULL;
+    x->len = 0;
+    x->sign = 0;
+}
+
+// strip leading zero limbs and fix up the sign of zero
+static big big_norm(big x) {
+    while (x.len > 0 && x.limbs[x.len - 1] == 0) {
+        x.len--;
+    }
+    if (x.len == 0) {
+        x.sign = 0;
+    }
+    return x;
+}
+
+static big big_copy(const big *x) {
+    big res = big_alloc(x->len);
+    memcpy(res.limbs, x->limbs, x->len * sizeof(uint32_t));
+    res.sign = x->sign;
+    return res;
+}
+
+static big big_from_i64(int64_t val) {
+    uint64_t mag = val < 0 ? (uint64_t)0 - (uint64_t)val : (uint64_t)val;
+    big res = big_alloc(2);
+    res.limbs[0] = (uint32_t)mag;
+    res.limbs[1] = (uint32_t)(mag >> 32);
+    res.sign = val < 0 ? -1 : 1;
+    return big_norm(res);
+}
+
+// low 32 bits in two's complement, used for output and rotate amounts
+static int32_t big_low_i32(const big *x) {
+    uint32_t low = x->len ? x->limbs[0] : 0;
+    if (x->sign < 0) {
+        low = (uint32_t)0 - low;
+    }
+    return (int32_t)low;
+}
+
+static int mag_cmp(const big *a, const big *b) {
+    if (a->len != b->len) {
+        return a->len < b->len ? -1 : 1;
+    }
+    for (size_t i = a->len; i-- > 0;) {
+        if (a->limbs[i] != b->limbs[i]) {
+            return a->limbs[i] < b->limbs[i] ? -1 : 1;
+        }
+    }
+    return 0;
+}
+
+static big mag_add(const big *a, const big *b) {
+    size_t len = (a->len > b->len ? a->len : b->len) + 1;
+    big res = big_alloc(len);
+    uint64_t carry = 0;
+    for (size_t i = 0; i < len; i++) {
+        uint64_t sum = carry;
+        if (i < a->len) sum += a->limbs[i];
+        if (i < b->len) sum += b->limbs[i];
+        res.limbs[i] = (uint32_t)sum;
+        carry = sum >> 32;
+    }
+    res.sign = 1;
+    return big_norm(res);
+}
+
+// requires |a| >= |b|
+static big mag_sub(const big *a, const big *b) {
+    big res = big_alloc(a->len);
+    int64_t borrow = 0;
+    for (size_t i = 0; i < a->len; i++) {
+        int64_t diff = (int64_t)a->limbs[i] - borrow - (i < b->len ? (int64_t)b->limbs[i] : 0);
+        borrow = diff < 0;
+        res.limbs[i] = (uint32_t)(diff + (borrow ? ((int64_t)1 << 32) : 0));
+    }
+    res.sign = 1;
+    return big_norm(res);
+}
+
+static big mag_mul(const big *a, const big *b) {
+    big res = big_alloc(a->len + b->len);
+    for (size_t i = 0; i < a->len; i++) {
+        uint64_t carry = 0;
+        for (size_t j = 0; j < b->len; j++) {
+            uint64_t cur = res.limbs[i + j] + (uint64_t)a->limbs[i] * b->limbs[j] + carry;
+            res.limbs[i + j] = (uint32_t)cur;
+            carry = cur >> 32;
+        }
+        res.limbs[i + b->len] = (uint32_t)carry;
+    }
+    res.sign = 1;
+    return big_norm(res);
+}
+
+static bool mag_bit(const big *x, size_t bit) {
+    return (x->limbs[bit / 32] >> (bit % 32)) & 1;
+}
+
+// shift-subtract long division of magnitudes, b must be non zero
+static void mag_divmod(const big *a, const big *b, big *quot, big *rem) {
+    *quot = big_alloc(a->len);
+    *rem = big_alloc(0);
+    for (size_t bit = a->len * 32; bit-- > 0;) {
+        // rem = rem << 1 | bit
+        big shifted = big_alloc(rem->len + 1);
+        uint32_t carry = mag_bit(a, bit);
+        for (size_t i = 0; i < rem->len; i++) {
+            shifted.limbs[i] = (rem->limbs[i] << 1) | carry;
+            carry = rem->limbs[i] >> 31;
+        }
+        shifted.limbs[rem->len] = carry;
+        shifted.sign = 1;
+        big_free(rem);
+        *rem = big_norm(shifted);
+
+        if (mag_cmp(rem, b) >= 0) {
+            big diff = mag_sub(rem, b);
+            big_free(rem);
+            *rem = diff;
+            quot->limbs[bit / 32] |= (uint32_t)1 << (bit % 32);
+        }
+    }
+    quot->sign = 1;
+    *quot = big_norm(*quot);
+}
+
+//// signed arithmetic
+
+static big big_neg(const big *x) {
+    big res = big_copy(x);
+    res.sign = -res.sign;
+    return res;
+}
+
+static big big_add(const big *a, const big *b) {
+    if (a->sign == 0) return big_copy(b);
+    if (b->sign == 0) return big_copy(a);
+    if (a->sign == b->sign) {
+        big res = mag_add(a, b);
+        res.sign = a->sign;
+        return res;
+    }
+    if (mag_cmp(a, b) >= 0) {
+        big res = mag_sub(a, b);
+        if (res.sign) res.sign = a->sign;
+        return res;
+    }
+    big res = mag_sub(b, a);
+    if (res.sign) res.sign = b->sign;
+    return res;
+}
+
+static big big_sub(const big *a, const big *b) {
+    big neg = big_neg(b);
+    big res = big_add(a, &neg);
+    big_free(&neg);
+    return res;
+}
+
+static big big_mul(const big *a, const big *b) {
+    big res = mag_mul(a, b);
+    if (res.sign) res.sign = a->sign * b->sign;
+    return res;
+}
+
+// floor division, so the remainder takes the divisor's sign like bf_Divide in the i32 prelude
+static void big_divmod(const big *a, const big *b, big *quot, big *rem) {
+    if (b->sign == 0) {
+        die("division by zero");
+    }
+    mag_divmod(a, b, quot, rem);
+    if (quot->sign) quot->sign = a->sign * b->sign;
+    if (rem->sign) rem->sign = a->sign;
+    if (rem->sign != 0 && rem->sign != b->sign) {
+        big one = big_from_i64(1);
+        big floored = big_sub(quot, &one);
+        big adjusted = big_add(rem, b);
+        big_free(&one);
+        big_free(quot);
+        big_free(rem);
+        *quot = floored;
+        *rem = adjusted;
+    }
+}
+
+static int big_cmp(const big *a, const big *b) {
+    if (a->sign != b->sign) {
+        return a->sign < b->sign ? -1 : 1;
+    }
+    int cmp = mag_cmp(a, b);
+    return a->sign < 0 ? -cmp : cmp;
+}
+
+//// bitwise, with infinite two's complement semantics
+
+// two's complement limbs of x, sign extended to len limbs
+static uint32_t *to_twos(const big *x, size_t len) {
+    uint32_t *res = xrealloc(NULL, len * sizeof(uint32_t));
+    memset(res, 0, len * sizeof(uint32_t));
+    memcpy(res, x->limbs, x->len * sizeof(uint32_t));
+    if (x->sign < 0) {
+        // -m == ~(m - 1)
+        for (size_t i = 0; i < len; i++) {
+            uint32_t limb = res[i];
+            res[i] = limb - 1;
+            if (limb != 0) break;
+        }
+        for (size_t i = 0; i < len; i++) {
+            res[i] = ~res[i];
+        }
+    }
+    return res;
+}
+
+// takes ownership of limbs
+static big from_twos(uint32_t *limbs, size_t len) {
+    big res = {1, len, limbs};
+    if (len > 0 && (limbs[len - 1] >> 31)) {
+        // magnitude is ~r + 1
+        for (size_t i = 0; i < len; i++) {
+            limbs[i] = ~limbs[i];
+        }
+        for (size_t i = 0; i < len; i++) {
+            if (++limbs[i] != 0) break;
+        }
+        res.sign = -1;
+    }
+    return big_norm(res);
+}
+
+enum bitop { BIT_AND, BIT_OR, BIT_XOR };
+
+static big big_bitwise(const big *a, const big *b, enum bitop op) {
+    size_t len = (a->len > b->len ? a->len : b->len) + 1;
+    uint32_t *x = to_twos(a, len);
+    uint32_t *y = to_twos(b, len);
+    for (size_t i = 0; i < len; i++) {
+        switch (op) {
+        case BIT_AND: x[i] &= y[i]; break;
+        case BIT_OR: x[i] |= y[i]; break;
+        case BIT_XOR: x[i] ^= y[i]; break;
+        }
+    }
+    free(y);
+    return from_twos(x, len);
+}
+
+// rotates work on a fixed 32 bit width: the low 32 bits are rotated as an
+// i32 and the rest of the value is kept, so any value that fits in an i32
+// rotates like the i32 runtimes and a rotate is always undone by the other one
+static big big_rotate(const big *x, int32_t amount, bool left) {
+    int32_t low = big_low_i32(x);
+    uint32_t bits = (uint32_t)low;
+    uint32_t count = (uint32_t)amount & 31;
+    if (count) {
+        bits = left ? (bits << count) | (bits >> (32 - count))
+                    : (bits >> count) | (bits << (32 - count));
+    }
+    big old_low = big_from_i64(low);
+    big new_low = big_from_i64((int32_t)bits);
+    big high = big_sub(x, &old_low);
+    big res = big_add(&high, &new_low);
+    big_free(&old_low);
+    big_free(&new_low);
+    big_free(&high);
+    return res;
+}
+
+static void print_big(FILE *file, const big *x) {
+    if (x->sign == 0) {
+        fputs("0", file);
+        return;
+    }
+    // peel off base 10^9 chunks, least significant first
+    size_t cap = x->len * 2 + 1;
+    uint32_t *chunks = xrealloc(NULL, cap * sizeof(uint32_t));
+    size_t count = 0;
+    big cur = big_copy(x);
+    cur.sign = 1;
+    while (cur.len > 0) {
+        uint64_t rem = 0;
+        for (size_t i = cur.len; i-- > 0;) {
+            uint64_t acc = (rem << 32) | cur.limbs[i];
+            cur.limbs[i] = (uint32_t)(acc / 1000000000);
+            rem = acc % 1000000000;
+        }
+        cur = big_norm(cur);
+        chunks[count++] = (uint32_t)rem;
+    }
+    big_free(&cur);
+    fprintf(file, "%s%u", x->sign < 0 ? "-" : "", chunks[count - 1]);
+    for (size_t i = count - 1; i-- > 0;) {
+        fprintf(file, "%09u", chunks[i]);
+    }
+    free(chunks);
+}
+
+//// stacks
+
+static void stack_push(stack *s, big val) {
+    if (s->len == STACK_CELLS) {
+        die(s == &primary_stack ? "stack overflow" : "control stack overflow");
+    }
+    if (s->len == s->cap) {
+        s->cap = s->cap ? s->cap * 2 : 64;
+        s->cells = xrealloc(s->cells, s->cap * sizeof(big));
+    }
+    s->cells[s->len++] = val;
+}
+
+static big stack_pop(stack *s) {
+    if (s->len == 0) {
+        die(s == &primary_stack ? "stack underflow" : "control stack underflow");
+    }
+    return s->cells[--s->len];
+}
+
+static big *stack_peek(stack *s, size_t depth) {
+    if (s->len <= depth) {
+        die(s == &primary_stack ? "stack underflow" : "control stack underflow");
+    }
+    return &s->cells[s->len - 1 - depth];
+}
+
+static void toggle(void) {
+    big val = stack_pop(&control_stack);
+    bool zero = val.sign == 0;
+    big_free(&val);
+    stack_push(&control_stack, big_from_i64(zero ? 1 : 0));
+}
+
+//// helpers called by the generated code
+
+// op is the operator's character
+void unimplemented(int32_t op) {
+    char msg[] = "inverse '?' is unimplemented";
+    msg[9] = (char)op;
+    die(msg);
+}
+
+// called by the generated code before each operator that can fail, rows and
+// columns are 1 based and directions count North, South, East, West from 0
+void at(int32_t row, int32_t column, int32_t direction, int32_t inverse) {
+    at_row = row;
+    at_column = column;
+    at_direction = direction;
+    at_inverse = inverse;
+}
+
+// called by main under --dump-final-state once the program halts,
+// "main:    [a, b]" and "control: [c]", or {"main": [a, b], "control": [c]}
+void dump_final_state(int32_t json) {
+    fflush(stdout);
+    if (json) {
+        dump_stack("{\"main\": [", &primary_stack, "], ");
+        dump_stack("\"control\": [", &control_stack, "]}\n");
+    } else {
+        dump_stack("main:    [", &primary_stack, "]\n");
+        dump_stack("control: [", &control_stack, "]\n");
+    }
+}
+
+// called by main under --exit-code top once the program halts. an exit status
+// is only a byte, so this is the whole value mod 256, which is the same as
+// what the i32 runtimes exit with for anything that fits in an i32
+int32_t halt_status(void) {
+    if (primary_stack.len == 0) {
+        return 0;
+    }
+    big byte = big_from_i64(256);
+    big quot, rem;
+    big_divmod(&primary_stack.cells[primary_stack.len - 1], &byte, &quot, &rem);
+    int32_t status = big_low_i32(&rem);
+    big_free(&byte);
+    big_free(&quot);
+    big_free(&rem);
+    return status;
+}
+
+// called by main under --max-steps
+void limit_steps(uint64_t max) {
+    max_steps = max;
+}
+
+// called instead of at() before every operator but halt under --max-steps.
+// running out exits with the status timeout(1) uses
+void step(int32_t row, int32_t column, int32_t direction, int32_t inverse) {
+    at(row, column, direction, inverse);
+    if (steps == max_steps) {
+        exit_with("ran out of steps", 124);
+    }
+    steps++;
+}
+
+void push_stack(int32_t val) {
+    stack_push(&primary_stack, big_from_i64(val));
+}
+
+void push_control_stack(int32_t val) {
+    stack_push(&control_stack, big_from_i64(val));
+}
+
+// zero = zero, everything else = 1
+bool pop_control_stack_i1(void) {
+    big val = stack_pop(&control_stack);
+    bool res = val.sign != 0;
+    big_free(&val);
+    return res;
+}
+
+void toggle_control_stack(void) {
+    toggle();
+}
+
+//// specific befreak operator impls
+
+void bf_Number(int32_t num) {
+    big x = stack_pop(&primary_stack);
+    big n = big_from_i64(num);
+    stack_push(&primary_stack, big_bitwise(&x, &n, BIT_XOR));
+    big_free(&x);
+    big_free(&n);
+}
+
+// simple stack
+void bf_PushZero(void) {
+    push_stack(0);
+}
+
+void bf_PopZero(void) {
+    big x = stack_pop(&primary_stack);
+    big_free(&x);
+}
+
+void bf_PopMainToControl(void) {
+    stack_push(&control_stack, stack_pop(&primary_stack));
+}
+
+void bf_PopControlToMain(void) {
+    stack_push(&primary_stack, stack_pop(&control_stack));
+}
+
+void bf_SwapStacks(void) {
+    big x = stack_pop(&primary_stack);
+    big y = stack_pop(&control_stack);
+    stack_push(&primary_stack, y);
+    stack_push(&control_stack, x);
+}
+
+// i/o
+void bf_Write(void) {
+    big x = stack_pop(&primary_stack);
+    putchar(big_low_i32(&x));
+    big_free(&x);
+}
+
+void bf_Read(void) {
+    push_stack(getchar());
+}
+
+// number
+static void unary(big (*op)(const big *, const big *), int64_t rhs) {
+    big x = stack_pop(&primary_stack);
+    big y = big_from_i64(rhs);
+    stack_push(&primary_stack, op(&x, &y));
+    big_free(&x);
+    big_free(&y);
+}
+
+void bf_Increment(void) {
+    unary(big_add, 1);
+}
+
+void bf_Decrement(void) {
+    unary(big_sub, 1);
+}
+
+// (y x -- op(y, x) x)
+static void binary(big (*op)(const big *, const big *)) {
+    big x = stack_pop(&primary_stack);
+    big y = stack_pop(&primary_stack);
+    stack_push(&primary_stack, op(&y, &x));
+    stack_push(&primary_stack, x);
+    big_free(&y);
+}
+
+void bf_Add(void) {
+    binary(big_add);
+}
+
+void bf_Subtract(void) {
+    binary(big_sub);
+}
+
+// (y x -- y/x y%x x), rounding down so the remainder takes the divisor's sign
+void bf_Divide(void) {
+    // checked before popping so the dump still shows both operands
+    if (stack_peek(&primary_stack, 0)->sign == 0) {
+        die("division by zero");
+    }
+    big x = stack_pop(&primary_stack);
+    big y = stack_pop(&primary_stack);
+    big div, rem;
+    big_divmod(&y, &x, &div, &rem);
+    stack_push(&primary_stack, div);
+    stack_push(&primary_stack, rem);
+    stack_push(&primary_stack, x);
+    big_free(&y);
+}
+
+// (div rem x -- div*x+rem x)
+void bf_Multiply(void) {
+    big x = stack_pop(&primary_stack);
+    big rem = stack_pop(&primary_stack);
+    big div = stack_pop(&primary_stack);
+    big prod = big_mul(&x, &div);
+    stack_push(&primary_stack, big_add(&prod, &rem));
+    stack_push(&primary_stack, x);
+    big_free(&prod);
+    big_free(&rem);
+    big_free(&div);
+}
+
+// bitwise
+void bf_Not(void) {
+    big x = stack_pop(&primary_stack);
+    big one = big_from_i64(1);
+    big neg = big_neg(&x);
+    // ~x == -x - 1
+    stack_push(&primary_stack, big_sub(&neg, &one));
+    big_free(&x);
+    big_free(&one);
+    big_free(&neg);
+}
+
+// (z y x -- z^op(y, x) y x)
+static void bitwise_into_third(enum bitop op) {
+    big x = stack_pop(&primary_stack);
+    big y = stack_pop(&primary_stack);
+    big z = stack_pop(&primary_stack);
+    big res = big_bitwise(&y, &x, op);
+    stack_push(&primary_stack, big_bitwise(&z, &res, BIT_XOR));
+    stack_push(&primary_stack, y);
+    stack_push(&primary_stack, x);
+    big_free(&res);
+    big_free(&z);
+}
+
+void bf_And(void) {
+    bitwise_into_third(BIT_AND);
+}
+
+void bf_Or(void) {
+    bitwise_into_third(BIT_OR);
+}
+
+// (y x -- y^x x)
+void bf_Xor(void) {
+    big x = stack_pop(&primary_stack);
+    big y = stack_pop(&primary_stack);
+    stack_push(&primary_stack, big_bitwise(&y, &x, BIT_XOR));
+    stack_push(&primary_stack, x);
+    big_free(&y);
+}
+
+static void rotate(bool left) {
+    big x = stack_pop(&primary_stack);
+    big y = stack_pop(&primary_stack);
+    stack_push(&primary_stack, big_rotate(&y, big_low_i32(&x), left));
+    stack_push(&primary_stack, x);
+    big_free(&y);
+}
+
+void bf_RotateLeft(void) {
+    rotate(true);
+}
+
+void bf_RotateRight(void) {
+    rotate(false);
+}
+
+// comparisons
+void bf_ToggleControl(void) {
+    toggle();
+}
+
+static void compare(int want) {
+    big *x = stack_peek(&primary_stack, 0);
+    big *y = stack_peek(&primary_stack, 1);
+    if (big_cmp(y, x) == want) {
+        toggle();
+    }
+}
+
+void bf_EqualityCheck(void) {
+    compare(0);
+}
+
+void bf_LessThanCheck(void) {
+    compare(-1);
+}
+
+void bf_GreaterThanCheck(void) {
+    compare(1);
+}
+
+// complex stack
+void bf_SwapTop(void) {
+    big x = stack_pop(&primary_stack);
+    big y = stack_pop(&primary_stack);
+    stack_push(&primary_stack, x);
+    stack_push(&primary_stack, y);
+}
+
+// (z y x -- y x z)
+void bf_Dig(void) {
+    big x = stack_pop(&primary_stack);
+    big y = stack_pop(&primary_stack);
+    big z = stack_pop(&primary_stack);
+    stack_push(&primary_stack, y);
+    stack_push(&primary_stack, x);
+    stack_push(&primary_stack, z);
+}
+
+// (z y x -- x z y)
+void bf_Bury(void) {
+    big x = stack_pop(&primary_stack);
+    big y = stack_pop(&primary_stack);
+    big z = stack_pop(&primary_stack);
+    stack_push(&primary_stack, x);
+    stack_push(&primary_stack, z);
+    stack_push(&primary_stack, y);
+}
+
+// (z y x -- x y z)
+void bf_Flip(void) {
+    big x = stack_pop(&primary_stack);
+    big y = stack_pop(&primary_stack);
+    big z = stack_pop(&primary_stack);
+    stack_push(&primary_stack, x);
+    stack_push(&primary_stack, y);
+    stack_push(&primary_stack, z);
+}
+
+// (z y x -- y z x)
+void bf_SwapLower(void) {
+    big x = stack_pop(&primary_stack);
+    big y = stack_pop(&primary_stack);
+    big z = stack_pop(&primary_stack);
+    stack_push(&primary_stack, y);
+    stack_push(&primary_stack, z);
+    stack_push(&primary_stack, x);
+}
+
+// (y x -- y x y)
+void bf_Over(void) {
+    big *y = stack_peek(&primary_stack, 1);
+    stack_push(&primary_stack, big_copy(y));
+}
+
+// (y x y -- y x), assumes the top and third are the same
+void bf_Under(void) {
+    big y = stack_pop(&primary_stack);
+    big_free(&y);
+}
+
+// misc
+void bf_Duplicate(void) {
+    big *x = stack_peek(&primary_stack, 0);
+    stack_push(&primary_stack, big_copy(x));
+}
+
+// assumes top two are same
+void bf_Unduplicate(void) {
+    big x = stack_pop(&primary_stack);
+    big_free(&x);
+}
+
+void bf_Halt(void) {
+    exit(0);
+}
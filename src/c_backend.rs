@@ -0,0 +1,69 @@
+use std::fmt::Write;
+
+use crate::dispatch::{lower, Next, Target};
+use crate::{CompileOptions, ExpressionTree, HaltStatus};
+
+// the runtimes expose the same functions as the LLVM PRELUDE,
+// so the calls are the ones runtime::operator_to_calls gives the jit
+const RUNTIME: &str = include_str!("../runtime/befreak.c");
+const BIGNUM_RUNTIME: &str = include_str!("../runtime/bignum.c");
+
+pub fn compile_c(data: ExpressionTree, options: &CompileOptions) {
+    let mut c = String::from(if options.bignum {
+        BIGNUM_RUNTIME
+    } else {
+        RUNTIME
+    });
+
+    c.push_str("\n//// actual codegen begin\n");
+    // expressions return the index of the next one, see dispatch. -1 is halting
+    let dispatch = lower(data, options.max_steps.is_some());
+    let mut table = String::new();
+    for expression in dispatch.expressions {
+        let symbol = expression.symbol;
+        write!(c, "\nstatic int {symbol}(void) {{").unwrap();
+        write!(table, "\n    {symbol},").unwrap();
+        for (function, args) in expression.calls {
+            let args: Vec<_> = args.iter().map(ToString::to_string).collect();
+            write!(c, "\n    {function}({});", args.join(", ")).unwrap();
+        }
+        let next = |(index, symbol): Target| format!("{index} /* {symbol} */");
+        match expression.next {
+            Next::Halt => c.push_str("\n    return -1;"),
+            Next::One(id1) => write!(c, "\n    return {};", next(id1)).unwrap(),
+            Next::Two(id1, id2) => write!(
+                c,
+                "\n    return pop_control_stack_i1() ? {} : {};",
+                next(id1),
+                next(id2)
+            )
+            .unwrap(),
+        }
+        c.push_str("\n}\n");
+    }
+    write!(
+        c,
+        "\nstatic int (*const expressions[])(void) = {{{table}\n}};\n"
+    )
+    .unwrap();
+
+    c.push_str("\n//// actual codegen over\n\nint main(void) {");
+    if let Some(max_steps) = options.max_steps {
+        write!(c, "\n    limit_steps({max_steps}u);").unwrap();
+    }
+    write!(
+        c,
+        "\n    int next = {};\n    while (next >= 0) {{\n        next = expressions[next]();\n    }}",
+        dispatch.start
+    )
+    .unwrap();
+    // the loop only ends once the program halts
+    if let Some(format) = options.dump_final_state {
+        write!(c, "\n    dump_final_state({});", format.json()).unwrap();
+    }
+    c.push_str(match options.halt_status {
+        HaltStatus::Zero => "\n    return 0;\n}",
+        HaltStatus::Top => "\n    return halt_status();\n}",
+    });
+    println!("{c}");
+}
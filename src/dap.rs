@@ -0,0 +1,514 @@
+use std::sync::mpsc::{self, TryRecvError};
+
+use serde_json::{json, Value};
+
+use crate::interpreter::Interpreter;
+use crate::runtime::Io;
+use crate::wire::{read_message, write_message};
+use crate::{parse_code, read_string, Position};
+
+// how many operators run between checking for a pause
+const CHUNK: usize = 10_000;
+
+// the variablesReference of each stack in the scopes request
+const MAIN_STACK: u64 = 1;
+const CONTROL_STACK: u64 = 2;
+
+fn event(name: &str, body: Value) -> Value {
+    json!({ "type": "event", "event": name, "body": body })
+}
+
+fn stopped(reason: &str, text: Option<&str>) -> Value {
+    let mut body = json!({ "reason": reason, "threadId": 1, "allThreadsStopped": true });
+    if let Some(text) = text {
+        body["text"] = json!(text);
+    }
+    event("stopped", body)
+}
+
+// the program is over, with the status the compiled program would have exited with
+fn ended(status: i32) -> Vec<Value> {
+    vec![
+        event("exited", json!({ "exitCode": status })),
+        event("terminated", json!({})),
+    ]
+}
+
+// a stack as variables, top first, with the character it would write if it's printable
+fn variables(stack: &[i32]) -> Value {
+    Value::Array(
+        stack
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(depth, val)| {
+                let value = match u8::try_from(*val) {
+                    Ok(byte) if byte.is_ascii_graphic() || byte == b' ' => {
+                        format!("{val} {:?}", char::from(byte))
+                    }
+                    _ => val.to_string(),
+                };
+                json!({ "name": format!("[{depth}]"), "value": value, "type": "i32", "variablesReference": 0 })
+            })
+            .collect(),
+    )
+}
+
+// a breakpoint without a column stops anywhere on its row
+fn at_breakpoint(breakpoints: &[(usize, Option<usize>)], position: Position) -> bool {
+    let Position(x, y) = position;
+    breakpoints
+        .iter()
+        .any(|&(row, column)| row == y && column.is_none_or(|column| column == x))
+}
+
+#[derive(Default)]
+pub struct Adapter {
+    // the adapter debugs one program then exits, so the tree is leaked
+    // rather than tying the interpreter's lifetime to the adapter
+    interpreter: Option<Interpreter<'static>>,
+    path: String,
+    stop_on_entry: bool,
+    configured: bool,
+    // rows, and columns if the editor gave one, counting from 0
+    breakpoints: Vec<(usize, Option<usize>)>,
+    // whether lines and columns count from 1, they do unless the editor says otherwise
+    line_base: usize,
+    column_base: usize,
+    // Some(backwards) while continuing
+    running: Option<bool>,
+    // the last step forward failed, so the program can only go back from here
+    failed: bool,
+    // how much of the program's output has been sent to the editor
+    written: usize,
+    pub exit: bool,
+}
+
+impl Adapter {
+    // whatever the program wrote since last time
+    fn output(&mut self) -> Vec<Value> {
+        let Some(interpreter) = &self.interpreter else {
+            return vec![];
+        };
+        let output = interpreter.output();
+        // stepping back over a write takes it out again
+        self.written = self.written.min(output.len());
+        if self.written == output.len() {
+            return vec![];
+        }
+        let text = String::from_utf8_lossy(&output[self.written..]).into_owned();
+        self.written = output.len();
+        vec![event(
+            "output",
+            json!({ "category": "stdout", "output": text }),
+        )]
+    }
+
+    pub fn run(&mut self, steps: usize) -> Vec<Value> {
+        self.advance(steps, true)
+    }
+
+    // runs or steps back up to `steps` operators, and the events for wherever it stops.
+    // halting ends the session, and so does going on past an error
+    fn advance(&mut self, steps: usize, breakpoints: bool) -> Vec<Value> {
+        let (Some(backwards), Some(interpreter)) = (self.running, self.interpreter.as_mut()) else {
+            return vec![];
+        };
+        if !backwards && self.failed {
+            self.running = None;
+            return ended(1);
+        }
+        let mut stop = vec![];
+        for _ in 0..steps {
+            if backwards && interpreter.steps == 0 {
+                stop.push(stopped("entry", None));
+                self.running = None;
+                break;
+            }
+            let result = if backwards {
+                interpreter.back()
+            } else {
+                interpreter.step()
+            };
+            if backwards && result.is_ok() {
+                self.failed = false;
+            }
+            if let Err(err) = result {
+                self.failed |= !backwards;
+                stop.push(stopped("exception", Some(&err)));
+            } else if interpreter.halted() {
+                // halting exits with 0, as it does without --exit-code
+                stop = ended(0);
+            } else if interpreter.steps == 0 {
+                stop.push(stopped("entry", None));
+            } else if breakpoints && at_breakpoint(&self.breakpoints, interpreter.state.position) {
+                stop.push(stopped("breakpoint", None));
+            }
+            if !stop.is_empty() {
+                self.running = None;
+                break;
+            }
+        }
+        let mut events = self.output();
+        events.extend(stop);
+        events
+    }
+
+    fn launch(&mut self, arguments: &Value) -> Result<Value, String> {
+        let path = arguments["program"]
+            .as_str()
+            .ok_or("launch needs a program")?;
+        let code =
+            std::fs::read_to_string(path).map_err(|err| format!("couldn't read {path}: {err}"))?;
+        if code.lines().next().is_none() {
+            return Err(format!("{path} is empty"));
+        }
+        let wrap = arguments["wrap"].as_bool().unwrap_or(false);
+        let tree = parse_code(&read_string(&code), wrap).map_err(|err| err.to_string())?;
+        let mut interpreter = Interpreter::new(Box::leak(Box::new(tree)));
+        if let (Some(input), Io::Buffered { input: buffer, .. }) =
+            (arguments["input"].as_str(), &mut interpreter.runtime.io)
+        {
+            buffer.extend(input.bytes());
+        }
+        self.interpreter = Some(interpreter);
+        self.path = path.to_string();
+        self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+        Ok(Value::Null)
+    }
+
+    // once the program is launched and the editor has sent its breakpoints
+    fn start(&mut self) -> Vec<Value> {
+        if !self.configured || self.interpreter.is_none() {
+            return vec![];
+        }
+        if self.stop_on_entry {
+            vec![stopped("entry", None)]
+        } else {
+            self.running = Some(false);
+            vec![]
+        }
+    }
+
+    fn set_breakpoints(&mut self, arguments: &Value) -> Value {
+        let requested = arguments["breakpoints"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        self.breakpoints.clear();
+        let mut breakpoints = vec![];
+        for breakpoint in &requested {
+            let line = breakpoint["line"]
+                .as_u64()
+                .and_then(|line| usize::try_from(line).ok());
+            let column = breakpoint["column"]
+                .as_u64()
+                .and_then(|column| usize::try_from(column).ok());
+            let row = line.and_then(|line| line.checked_sub(self.line_base));
+            let column = column.and_then(|column| column.checked_sub(self.column_base));
+            if let Some(row) = row {
+                self.breakpoints.push((row, column));
+            }
+            breakpoints.push(
+                json!({ "verified": row.is_some(), "line": line, "column": breakpoint["column"] }),
+            );
+        }
+        json!({ "breakpoints": breakpoints })
+    }
+
+    fn stack_trace(&self) -> Value {
+        let Some(interpreter) = &self.interpreter else {
+            return json!({ "stackFrames": [], "totalFrames": 0 });
+        };
+        let Position(x, y) = interpreter.state.position;
+        json!({
+            "stackFrames": [{
+                "id": 1,
+                "name": format!("{:?}", interpreter.expression()),
+                "source": { "path": self.path },
+                "line": y + self.line_base,
+                "column": x + self.column_base,
+            }],
+            "totalFrames": 1,
+        })
+    }
+
+    // the response to one request, and any events that go after it
+    pub fn handle(&mut self, request: &Value) -> Vec<Value> {
+        let command = request["command"].as_str().unwrap_or_default();
+        let arguments = &request["arguments"];
+        let mut events = vec![];
+        let body = match command {
+            "initialize" => {
+                let base = |name: &str| usize::from(arguments[name].as_bool().unwrap_or(true));
+                self.line_base = base("linesStartAt1");
+                self.column_base = base("columnsStartAt1");
+                events.push(event("initialized", json!({})));
+                Ok(json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsStepBack": true,
+                }))
+            }
+            "launch" => self.launch(arguments),
+            "setBreakpoints" => Ok(self.set_breakpoints(arguments)),
+            "configurationDone" => {
+                self.configured = true;
+                Ok(Value::Null)
+            }
+            "threads" => Ok(json!({ "threads": [{ "id": 1, "name": "main" }] })),
+            "stackTrace" => Ok(self.stack_trace()),
+            "scopes" => Ok(json!({ "scopes": [
+                { "name": "Main stack", "variablesReference": MAIN_STACK, "expensive": false },
+                { "name": "Control stack", "variablesReference": CONTROL_STACK, "expensive": false },
+            ] })),
+            "variables" => Ok(json!({
+                "variables": match (&self.interpreter, arguments["variablesReference"].as_u64()) {
+                    (Some(interpreter), Some(MAIN_STACK)) => variables(&interpreter.runtime.primary),
+                    (Some(interpreter), Some(CONTROL_STACK)) => variables(&interpreter.runtime.control),
+                    _ => json!([]),
+                }
+            })),
+            "continue" | "reverseContinue" => {
+                self.running = Some(command == "reverseContinue");
+                Ok(json!({ "allThreadsContinued": true }))
+            }
+            "next" | "stepIn" | "stepOut" | "stepBack" => {
+                self.running = Some(command == "stepBack");
+                let mut stop = self.advance(1, false);
+                if self.running.take().is_some() {
+                    stop.push(stopped("step", None));
+                }
+                events.extend(stop);
+                Ok(Value::Null)
+            }
+            "pause" => {
+                if self.running.take().is_some() {
+                    events.extend(self.output());
+                    events.push(stopped("pause", None));
+                }
+                Ok(Value::Null)
+            }
+            "disconnect" | "terminate" => {
+                self.exit = true;
+                Ok(Value::Null)
+            }
+            _ => Err(format!("{command} isn't supported")),
+        };
+        if matches!(command, "launch" | "configurationDone") && body.is_ok() {
+            events.extend(self.start());
+        }
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": command,
+            "success": body.is_ok(),
+        });
+        match body {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        let mut messages = vec![response];
+        messages.extend(events);
+        messages
+    }
+}
+
+// debugs one program for an editor over stdin and stdout until it disconnects
+pub fn serve() -> std::io::Result<()> {
+    // requests are read on their own thread so a pause can interrupt a continue
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut stdin = std::io::stdin().lock();
+        while let Ok(Some(message)) = read_message(&mut stdin) {
+            if sender.send(message).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut adapter = Adapter::default();
+    let mut stdout = std::io::stdout().lock();
+    let mut seq = 0;
+    while !adapter.exit {
+        let messages = if adapter.running.is_some() {
+            match receiver.try_recv() {
+                Ok(request) => adapter.handle(&request),
+                Err(TryRecvError::Empty) => adapter.run(CHUNK),
+                Err(TryRecvError::Disconnected) => break,
+            }
+        } else {
+            match receiver.recv() {
+                Ok(request) => adapter.handle(&request),
+                Err(_) => break,
+            }
+        };
+        for mut message in messages {
+            seq += 1;
+            message["seq"] = json!(seq);
+            write_message(&mut stdout, &message)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(adapter: &mut Adapter, command: &str, arguments: Value) -> Vec<Value> {
+        adapter.handle(
+            &json!({ "seq": 1, "type": "request", "command": command, "arguments": arguments }),
+        )
+    }
+
+    fn events(messages: &[Value]) -> Vec<String> {
+        messages
+            .iter()
+            .filter(|message| message["type"] == "event")
+            .map(|message| match message["event"].as_str().unwrap() {
+                "stopped" => format!("stopped {}", message["body"]["reason"].as_str().unwrap()),
+                "output" => format!("output {}", message["body"]["output"].as_str().unwrap()),
+                event => event.to_string(),
+            })
+            .collect()
+    }
+
+    fn launch(fixture: &str, breakpoints: Value) -> Adapter {
+        let mut adapter = Adapter::default();
+        let messages = request(
+            &mut adapter,
+            "initialize",
+            json!({ "adapterID": "befreak" }),
+        );
+        assert_eq!(events(&messages), ["initialized"]);
+        let program = format!("{}/tests/fixtures/{fixture}", env!("CARGO_MANIFEST_DIR"));
+        request(&mut adapter, "launch", json!({ "program": program }));
+        request(
+            &mut adapter,
+            "setBreakpoints",
+            json!({ "source": { "path": program }, "breakpoints": breakpoints }),
+        );
+        let messages = request(&mut adapter, "configurationDone", json!({}));
+        assert_eq!(messages[0]["success"], true);
+        adapter
+    }
+
+    fn frame(adapter: &mut Adapter) -> Value {
+        request(adapter, "stackTrace", json!({ "threadId": 1 }))[0]["body"]["stackFrames"][0]
+            .clone()
+    }
+
+    fn stack(adapter: &mut Adapter, reference: u64) -> Vec<String> {
+        let messages = request(
+            adapter,
+            "variables",
+            json!({ "variablesReference": reference }),
+        );
+        messages[0]["body"]["variables"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|variable| {
+                format!(
+                    "{} = {}",
+                    variable["name"].as_str().unwrap(),
+                    variable["value"].as_str().unwrap()
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stops_at_breakpoints_and_steps_back() {
+        // the first w, the 1 based column of the breakpoint is the grid's
+        let mut adapter = launch("hello.bf", json!([{ "line": 2, "column": 12 }]));
+        assert_eq!(events(&adapter.run(usize::MAX)), ["stopped breakpoint"]);
+        let frame = frame(&mut adapter);
+        assert_eq!((&frame["line"], &frame["column"]), (&json!(2), &json!(12)));
+        assert_eq!(
+            frame["name"],
+            "ExpressionIdentifier { position: Position(12, 1), direction: West, inverse_mode: false }"
+        );
+        assert_eq!(
+            &stack(&mut adapter, MAIN_STACK)[..2],
+            ["[0] = 72 'H'", "[1] = 13"]
+        );
+        assert_eq!(stack(&mut adapter, CONTROL_STACK), Vec::<String>::new());
+
+        assert_eq!(
+            events(&request(&mut adapter, "next", json!({}))),
+            ["output H", "stopped step"]
+        );
+        // stepping back takes the write back out again
+        assert_eq!(
+            events(&request(&mut adapter, "stepBack", json!({}))),
+            ["stopped step"]
+        );
+        assert_eq!(&stack(&mut adapter, MAIN_STACK)[..1], ["[0] = 72 'H'"]);
+        assert_eq!(
+            events(&request(&mut adapter, "next", json!({}))),
+            ["output H", "stopped step"]
+        );
+
+        request(&mut adapter, "reverseContinue", json!({}));
+        assert_eq!(events(&adapter.run(usize::MAX)), ["stopped breakpoint"]);
+        request(&mut adapter, "reverseContinue", json!({}));
+        assert_eq!(events(&adapter.run(usize::MAX)), ["stopped entry"]);
+        assert_eq!(stack(&mut adapter, MAIN_STACK), Vec::<String>::new());
+    }
+
+    #[test]
+    fn runtime_errors_stop_with_an_exception() {
+        let mut adapter = launch("hello.bf", json!([]));
+        let messages = adapter.run(usize::MAX);
+        assert_eq!(
+            events(&messages),
+            ["output Hello world!\n", "stopped exception"]
+        );
+        assert_eq!(messages[1]["body"]["text"], "stack underflow");
+
+        // it can be stepped back from, but not past
+        assert_eq!(
+            events(&request(&mut adapter, "stepBack", json!({}))),
+            ["stopped step"]
+        );
+        assert_eq!(
+            events(&request(&mut adapter, "next", json!({}))),
+            ["stopped step"]
+        );
+        assert_eq!(
+            events(&request(&mut adapter, "next", json!({}))),
+            ["stopped exception"]
+        );
+        let messages = request(&mut adapter, "continue", json!({}));
+        assert_eq!(events(&messages), Vec::<String>::new());
+        let messages = adapter.run(usize::MAX);
+        assert_eq!(events(&messages), ["exited", "terminated"]);
+        assert_eq!(messages[0]["body"]["exitCode"], 1);
+    }
+
+    #[test]
+    fn halting_ends_the_session() {
+        let mut adapter = launch("inverse.bf", json!([]));
+        let messages = adapter.run(usize::MAX);
+        assert_eq!(events(&messages), ["output /", "exited", "terminated"]);
+        assert_eq!(messages[1]["body"]["exitCode"], 0);
+        assert_eq!(adapter.run(usize::MAX), Vec::<Value>::new());
+    }
+
+    #[test]
+    fn launch_reports_unreadable_programs() {
+        let mut adapter = Adapter::default();
+        request(&mut adapter, "initialize", json!({}));
+        let messages = request(
+            &mut adapter,
+            "launch",
+            json!({ "program": "/does/not/exist.bf" }),
+        );
+        assert_eq!(messages[0]["success"], false);
+        assert!(messages[0]["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("couldn't read /does/not/exist.bf"));
+    }
+}
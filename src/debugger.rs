@@ -0,0 +1,161 @@
+use std::collections::BTreeSet;
+use std::io::{BufRead, Write};
+
+use array2d::Array2D;
+
+use crate::interpreter::Interpreter;
+use crate::runtime::{Io, OUT_OF_STEPS_STATUS};
+use crate::{get_char, location, ExpressionTree, Position};
+
+const HELP: &str = "commands:
+  s, step [N]              run the next N operators
+  b, back [N]              undo the last N operators
+  c, continue              run until a breakpoint or halt
+  rc, reverse-continue     undo until a breakpoint or the start
+  break ROW COLUMN         stop before the operator starting at ROW, COLUMN
+  delete ROW COLUMN        remove a breakpoint
+  input TEXT               queue a line for `r` to read
+  q, quit";
+
+// rows and columns are 1 based, like the ones in error messages
+fn parse_position(args: &[&str]) -> Result<Position, String> {
+    let [row, column] = args else {
+        return Err("expected ROW COLUMN".to_string());
+    };
+    let parse = |arg: &str| match arg.parse::<usize>() {
+        Ok(number) if number > 0 => Ok(number - 1),
+        _ => Err(format!("{arg:?} isn't a row or column")),
+    };
+    Ok(Position(parse(column)?, parse(row)?))
+}
+
+fn parse_count(args: &[&str]) -> Result<usize, String> {
+    match args {
+        [] => Ok(1),
+        [count] => count
+            .parse()
+            .map_err(|_| format!("{count:?} isn't a number of steps")),
+        _ => Err("expected at most one number of steps".to_string()),
+    }
+}
+
+fn show(interpreter: &Interpreter, code: &Array2D<char>) {
+    let state = &interpreter.state;
+    let Position(x, y) = state.position;
+    println!(
+        "{}{}: {:?}",
+        location(state.position, state.direction),
+        if state.inverse_mode { " (inverse)" } else { "" },
+        interpreter.operator().operation
+    );
+    let row: String = (0..code.num_columns())
+        .map(|column| *get_char(code, Position(column, y)).unwrap())
+        .collect();
+    println!("  {}", row.trim_end());
+    println!("  {}^", " ".repeat(x));
+    println!("main:    {:?}", interpreter.runtime.primary);
+    println!("control: {:?}", interpreter.runtime.control);
+    println!(
+        "output:  {:?}",
+        String::from_utf8_lossy(interpreter.output())
+    );
+}
+
+// runs the debugger's command loop on stdin until it's closed or told to quit
+pub fn debug(tree: &ExpressionTree, code: &Array2D<char>, max_steps: Option<u64>) {
+    let mut interpreter = Interpreter::new(tree);
+    interpreter.max_steps = max_steps;
+    let mut breakpoints = BTreeSet::new();
+    show(&interpreter, code);
+
+    let stdin = std::io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("(befreak) ");
+        std::io::stdout().flush().unwrap();
+        let Some(Ok(line)) = lines.next() else {
+            println!();
+            return;
+        };
+        let (command, rest) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+        let args: Vec<_> = rest.split_whitespace().collect();
+
+        let result = match command {
+            "" => continue,
+            "q" | "quit" => return,
+            "h" | "help" => {
+                println!("{HELP}");
+                continue;
+            }
+            "break" | "delete" => {
+                match parse_position(&args) {
+                    Ok(position) if command == "break" => {
+                        breakpoints.insert(position);
+                    }
+                    Ok(position) => {
+                        if !breakpoints.remove(&position) {
+                            println!("no breakpoint at {}", args.join(", "));
+                        }
+                    }
+                    Err(err) => println!("{err}"),
+                }
+                continue;
+            }
+            "input" => {
+                if let Io::Buffered { input, .. } = &mut interpreter.runtime.io {
+                    input.extend(rest.bytes());
+                    input.push_back(b'\n');
+                }
+                continue;
+            }
+            "s" | "step" => parse_count(&args).and_then(|count| {
+                for _ in 0..count {
+                    interpreter.step()?;
+                }
+                Ok(())
+            }),
+            "b" | "back" => parse_count(&args).and_then(|count| {
+                for _ in 0..count {
+                    interpreter.back()?;
+                }
+                Ok(())
+            }),
+            "c" | "continue" => (|| {
+                while !interpreter.halted() {
+                    interpreter.step()?;
+                    if breakpoints.contains(&interpreter.state.position) {
+                        break;
+                    }
+                }
+                Ok(())
+            })(),
+            "rc" | "reverse-continue" => (|| {
+                while interpreter.steps > 0 {
+                    interpreter.back()?;
+                    if breakpoints.contains(&interpreter.state.position) {
+                        break;
+                    }
+                }
+                Ok(())
+            })(),
+            other => {
+                println!("unknown command {other:?}, try help");
+                continue;
+            }
+        };
+        // a runaway session being driven from a script
+        let out_of_steps = result.is_err() && interpreter.out_of_steps();
+        if let Err(err) = result {
+            println!("error: {err}");
+        }
+        if interpreter.halted() {
+            println!("halted");
+        } else if interpreter.steps == 0 {
+            println!("at the start");
+        }
+        show(&interpreter, code);
+        if out_of_steps {
+            std::process::exit(OUT_OF_STEPS_STATUS);
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+
+use crate::runtime::{operator_location, operator_to_calls};
+use crate::{Branches, ExpressionIdentifier, ExpressionTree};
+
+// what the c and wat backends share. expressions return the index of the next
+// one instead of calling it, and a loop in main calls whichever that is, so
+// loops in the program don't grow the native or wasm stack
+pub struct Dispatch {
+    pub expressions: Vec<Lowered>,
+    // the index of the expression main starts with
+    pub start: usize,
+}
+
+pub struct Lowered {
+    pub symbol: String,
+    // the runtime functions it calls in order, with their arguments
+    pub calls: Vec<(&'static str, Vec<i32>)>,
+    pub next: Next,
+}
+
+// the index of the expression to return, and its symbol for a comment
+pub type Target = (usize, String);
+
+pub enum Next {
+    // returns -1
+    Halt,
+    One(Target),
+    // pops the control stack, the first if it was set
+    Two(Target, Target),
+}
+
+pub fn lower(data: ExpressionTree, count_steps: bool) -> Dispatch {
+    let indices: HashMap<_, _> = data
+        .tree
+        .keys()
+        .enumerate()
+        .map(|(index, identifier)| (identifier.clone(), index))
+        .collect();
+    let target = |id: &ExpressionIdentifier| (indices[id], id.to_codegen_symbol());
+    let expressions = data
+        .tree
+        .iter()
+        .map(|(identifier, expression)| {
+            let mut calls = vec![];
+            for operator in &expression.arr {
+                // so runtime errors can say which operator they came from
+                if let Some((function, location)) = operator_location(operator, count_steps) {
+                    calls.push((function, location.to_vec()));
+                }
+                for call in operator_to_calls(operator) {
+                    let (symbol, arg) = call.symbol();
+                    calls.push((symbol, arg.into_iter().collect()));
+                }
+            }
+            let next = match &expression.next {
+                Branches::None => Next::Halt,
+                Branches::One(id1) => Next::One(target(id1)),
+                Branches::Two(id1, id2) => Next::Two(target(id1), target(id2)),
+            };
+            Lowered {
+                symbol: identifier.to_codegen_symbol(),
+                calls,
+                next,
+            }
+        })
+        .collect();
+    Dispatch {
+        expressions,
+        start: indices[&data.start],
+    }
+}
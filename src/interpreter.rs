@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+
+use crate::runtime::{operator_to_calls, Call, Io, Runtime, OUT_OF_STEPS};
+use crate::{Branches, ExpressionIdentifier, ExpressionTree, Operator, OperatorSymbol};
+
+// the state an operator is read in, which is what the tree's owners are keyed by
+const fn state(operator: &Operator) -> ExpressionIdentifier {
+    ExpressionIdentifier::new(operator.inverse, operator.position, operator.in_direction)
+}
+
+// runs the tree one operator at a time. befreak is reversible so stepping
+// back runs each operator's inverse rather than keeping a history
+pub struct Interpreter<'a> {
+    tree: &'a ExpressionTree,
+    // the operators that can run just before each state, and for branches
+    // whether they took the first way
+    predecessors: HashMap<ExpressionIdentifier, Vec<(ExpressionIdentifier, Option<bool>)>>,
+    pub runtime: Runtime,
+    // the state of the operator about to run
+    pub state: ExpressionIdentifier,
+    // the start can be joined by a loop, so this is what says we're back at it
+    pub steps: u64,
+    // --max-steps, stepping past it is an error
+    pub max_steps: Option<u64>,
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new(tree: &'a ExpressionTree) -> Self {
+        let mut predecessors: HashMap<_, Vec<_>> = HashMap::new();
+        for expression in tree.tree.values() {
+            for pair in expression.arr.windows(2) {
+                predecessors
+                    .entry(state(&pair[1]))
+                    .or_default()
+                    .push((state(&pair[0]), None));
+            }
+            let last = state(expression.arr.last().unwrap());
+            match &expression.next {
+                Branches::None => {}
+                Branches::One(next) => predecessors
+                    .entry(next.clone())
+                    .or_default()
+                    .push((last, None)),
+                Branches::Two(first, second) => {
+                    predecessors
+                        .entry(first.clone())
+                        .or_default()
+                        .push((last.clone(), Some(true)));
+                    predecessors
+                        .entry(second.clone())
+                        .or_default()
+                        .push((last, Some(false)));
+                }
+            }
+        }
+        Self {
+            tree,
+            predecessors,
+            runtime: Runtime::buffered(),
+            state: tree.start.clone(),
+            steps: 0,
+            max_steps: None,
+        }
+    }
+
+    fn operator_at(&self, state: &ExpressionIdentifier) -> &'a Operator {
+        let (owner, index) = &self.tree.owners[state];
+        &self.tree.tree[owner].arr[*index]
+    }
+
+    pub fn operator(&self) -> &'a Operator {
+        self.operator_at(&self.state)
+    }
+
+    // the expression the current operator is in
+    pub fn expression(&self) -> &'a ExpressionIdentifier {
+        &self.tree.owners[&self.state].0
+    }
+
+    pub fn halted(&self) -> bool {
+        matches!(self.operator().operation, OperatorSymbol::Halt)
+    }
+
+    pub fn out_of_steps(&self) -> bool {
+        !self.halted() && self.max_steps == Some(self.steps)
+    }
+
+    // runs the current operator. on an error the stacks are left as they were
+    // before it, so it can be stepped back from
+    pub fn step(&mut self) -> Result<(), String> {
+        if self.halted() {
+            return Ok(());
+        }
+        if self.out_of_steps() {
+            return Err(OUT_OF_STEPS.to_string());
+        }
+        let saved = (self.runtime.primary.clone(), self.runtime.control.clone());
+        match self.advance() {
+            Ok(next) => {
+                self.state = next;
+                self.steps += 1;
+                Ok(())
+            }
+            Err(err) => {
+                (self.runtime.primary, self.runtime.control) = saved;
+                Err(err)
+            }
+        }
+    }
+
+    fn advance(&mut self) -> Result<ExpressionIdentifier, String> {
+        let (owner, index) = &self.tree.owners[&self.state];
+        let expression = &self.tree.tree[owner];
+        for call in operator_to_calls(&expression.arr[*index]) {
+            self.runtime.execute(call)?;
+        }
+        if let Some(next) = expression.arr.get(index + 1) {
+            return Ok(state(next));
+        }
+        Ok(match &expression.next {
+            Branches::None => self.state.clone(),
+            Branches::One(next) => next.clone(),
+            Branches::Two(first, second) => {
+                if self.runtime.pop_control_stack_i1()? {
+                    first.clone()
+                } else {
+                    second.clone()
+                }
+            }
+        })
+    }
+
+    // undoes the operator that ran before the current one
+    pub fn back(&mut self) -> Result<(), String> {
+        if self.steps == 0 {
+            return Err("already at the start".to_string());
+        }
+        let candidates = &self.predecessors[&self.state];
+        let (previous, branch) = if let [only] = &candidates[..] {
+            only.clone()
+        } else {
+            // paths only join after a merge, and the control value it pushed says which it was
+            let top = *self
+                .runtime
+                .control
+                .last()
+                .ok_or("control stack underflow")?;
+            let pushed = Call::PushControlStack(i32::from(top != 0));
+            candidates
+                .iter()
+                .find(|(state, _)| operator_to_calls(self.operator_at(state)).contains(&pushed))
+                .ok_or("no merge pushed the value on top of the control stack")?
+                .clone()
+        };
+        let saved = (self.runtime.primary.clone(), self.runtime.control.clone());
+        if let Some(first) = branch {
+            self.runtime.push_control(i32::from(first))?;
+        }
+        match self.undo(self.operator_at(&previous)) {
+            Ok(()) => {
+                self.state = previous;
+                self.steps -= 1;
+                Ok(())
+            }
+            Err(err) => {
+                (self.runtime.primary, self.runtime.control) = saved;
+                Err(err)
+            }
+        }
+    }
+
+    fn undo(&mut self, operator: &Operator) -> Result<(), String> {
+        match operator.operation {
+            // these don't have a working inverse mode, so undo their calls one at a time
+            OperatorSymbol::String(_)
+            | OperatorSymbol::Write
+            | OperatorSymbol::Read
+            | OperatorSymbol::EastBranch
+            | OperatorSymbol::WestBranch
+            | OperatorSymbol::SouthBranch
+            | OperatorSymbol::NorthBranch => {
+                for call in operator_to_calls(operator).into_iter().rev() {
+                    self.undo_call(call)?;
+                }
+            }
+            _ => {
+                let inverse = Operator {
+                    inverse: !operator.inverse,
+                    ..operator.clone()
+                };
+                for call in operator_to_calls(&inverse) {
+                    self.runtime.execute(call)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn undo_call(&mut self, call: Call) -> Result<(), String> {
+        match (call, &mut self.runtime.io) {
+            (Call::PushStack(_), _) => {
+                self.runtime.pop()?;
+            }
+            (Call::PushControlStack(_), _) => {
+                self.runtime.pop_control()?;
+            }
+            (Call::ToggleControlStack, _) => self.runtime.toggle_control()?,
+            (Call::Write, Io::Buffered { output, .. }) => {
+                let byte = output.pop().ok_or("nothing has been written")?;
+                self.runtime.push(i32::from(byte))?;
+            }
+            (Call::Read, Io::Buffered { input, .. }) => {
+                let x = self.runtime.primary.pop().ok_or("stack underflow")?;
+                if let Ok(byte) = u8::try_from(x) {
+                    input.push_front(byte);
+                }
+            }
+            (call, _) => return Err(format!("can't step back over {call:?}")),
+        }
+        Ok(())
+    }
+
+    // everything written so far
+    pub fn output(&self) -> &[u8] {
+        match &self.runtime.io {
+            Io::Buffered { output, .. } => output,
+            Io::Std => &[],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_code, read_string};
+
+    // runs forwards until it halts, hits an error or runs out of steps,
+    // then checks every step back lands on exactly where it was
+    fn round_trip(code: &str, max_steps: usize) -> usize {
+        let tree = parse_code(&read_string(code), false).unwrap();
+        let mut interpreter = Interpreter::new(&tree);
+        let snapshot = |interpreter: &Interpreter| {
+            (
+                interpreter.state.clone(),
+                interpreter.runtime.primary.clone(),
+                interpreter.runtime.control.clone(),
+                interpreter.output().to_vec(),
+            )
+        };
+        let mut history = vec![];
+        while history.len() < max_steps && !interpreter.halted() {
+            let before = snapshot(&interpreter);
+            if interpreter.step().is_err() {
+                assert_eq!(
+                    snapshot(&interpreter),
+                    before,
+                    "a failed step changed the state"
+                );
+                break;
+            }
+            history.push(before);
+        }
+        let steps = history.len();
+        while let Some(expected) = history.pop() {
+            interpreter.back().unwrap();
+            assert_eq!(
+                snapshot(&interpreter),
+                expected,
+                "{} steps in",
+                history.len()
+            );
+        }
+        assert!(interpreter.back().is_err());
+        steps
+    }
+
+    #[test]
+    fn stepping_back_undoes_every_step() {
+        let fixtures = [
+            include_str!("../tests/fixtures/hello.bf"),
+            include_str!("../tests/fixtures/inverse.bf"),
+            include_str!("../tests/fixtures/ladder.bf"),
+            include_str!("../tests/fixtures/loop.bf"),
+            include_str!("../tests/fixtures/primes.bf"),
+        ];
+        for code in fixtures {
+            assert!(round_trip(code, 20_000) > 0);
+        }
+    }
+
+    #[test]
+    fn max_steps_stops_in_front_of_the_next_operator() {
+        let tree = parse_code(
+            &read_string(include_str!("../tests/fixtures/loop.bf")),
+            false,
+        )
+        .unwrap();
+        let mut interpreter = Interpreter::new(&tree);
+        interpreter.max_steps = Some(50);
+        while interpreter.step().is_ok() {}
+        assert_eq!(interpreter.steps, 50);
+        assert!(interpreter.out_of_steps());
+        assert_eq!(interpreter.step(), Err(OUT_OF_STEPS.to_string()));
+        // the same place and stacks the compiled program stops with
+        assert_eq!(interpreter.runtime.control.len(), 13);
+        interpreter.back().unwrap();
+        assert!(!interpreter.out_of_steps());
+    }
+
+    #[test]
+    fn steps_back_through_merges() {
+        // the sieve joins paths all over the place, so 20_000 steps has to go through some
+        let code = include_str!("../tests/fixtures/primes.bf");
+        assert_eq!(round_trip(code, 20_000), 20_000);
+    }
+}
@@ -0,0 +1,339 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use cranelift_codegen::ir::{types, AbiParam, InstBuilder, MemFlags, Signature};
+use cranelift_codegen::isa::CallConv;
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{FuncId, Linkage, Module};
+
+use crate::profile::Profile;
+use crate::runtime::{operator_location, operator_to_calls, Call, Runtime, OUT_OF_STEPS_STATUS};
+use crate::{Branches, CompileOptions, Direction, ExpressionIdentifier, ExpressionTree, Position};
+
+// every runtime function takes the runtime as its first argument,
+// so the generated code doesn't need any globals
+macro_rules! shims {
+    ($($symbol:literal => $name:ident($($arg:ident)?) => $call:expr;)*) => {
+        $(
+            extern "C" fn $name(runtime: *mut Runtime $(, $arg: i32)?) {
+                let runtime = unsafe { &mut *runtime };
+                if let Err(msg) = runtime.execute($call) {
+                    runtime.die(&msg);
+                }
+            }
+        )*
+
+        fn shim_address(symbol: &str) -> Option<*const u8> {
+            match symbol {
+                $($symbol => Some($name as *const u8),)*
+                "pop_control_stack_i1" => Some(pop_control_stack_i1 as *const u8),
+                "at" => Some(at as *const u8),
+                "step" => Some(step as *const u8),
+                _ => None,
+            }
+        }
+    };
+}
+
+shims! {
+    "unimplemented" => unimplemented(operator) => Call::Unimplemented(operator);
+    "push_stack" => push_stack(val) => Call::PushStack(val);
+    "push_control_stack" => push_control_stack(val) => Call::PushControlStack(val);
+    "toggle_control_stack" => toggle_control_stack() => Call::ToggleControlStack;
+
+    "bf_Number" => bf_number(num) => Call::Number(num);
+    "bf_PushZero" => bf_push_zero() => Call::PushZero;
+    "bf_PopZero" => bf_pop_zero() => Call::PopZero;
+    "bf_PopMainToControl" => bf_pop_main_to_control() => Call::PopMainToControl;
+    "bf_PopControlToMain" => bf_pop_control_to_main() => Call::PopControlToMain;
+    "bf_SwapStacks" => bf_swap_stacks() => Call::SwapStacks;
+    "bf_Write" => bf_write() => Call::Write;
+    "bf_Read" => bf_read() => Call::Read;
+    "bf_Increment" => bf_increment() => Call::Increment;
+    "bf_Decrement" => bf_decrement() => Call::Decrement;
+    "bf_Add" => bf_add() => Call::Add;
+    "bf_Subtract" => bf_subtract() => Call::Subtract;
+    "bf_Divide" => bf_divide() => Call::Divide;
+    "bf_Multiply" => bf_multiply() => Call::Multiply;
+    "bf_Not" => bf_not() => Call::Not;
+    "bf_And" => bf_and() => Call::And;
+    "bf_Or" => bf_or() => Call::Or;
+    "bf_Xor" => bf_xor() => Call::Xor;
+    "bf_RotateLeft" => bf_rotate_left() => Call::RotateLeft;
+    "bf_RotateRight" => bf_rotate_right() => Call::RotateRight;
+    "bf_ToggleControl" => bf_toggle_control() => Call::ToggleControl;
+    "bf_EqualityCheck" => bf_equality_check() => Call::EqualityCheck;
+    "bf_LessThanCheck" => bf_less_than_check() => Call::LessThanCheck;
+    "bf_GreaterThanCheck" => bf_greater_than_check() => Call::GreaterThanCheck;
+    "bf_SwapTop" => bf_swap_top() => Call::SwapTop;
+    "bf_Dig" => bf_dig() => Call::Dig;
+    "bf_Bury" => bf_bury() => Call::Bury;
+    "bf_Flip" => bf_flip() => Call::Flip;
+    "bf_SwapLower" => bf_swap_lower() => Call::SwapLower;
+    "bf_Over" => bf_over() => Call::Over;
+    "bf_Under" => bf_under() => Call::Under;
+    "bf_Duplicate" => bf_duplicate() => Call::Duplicate;
+    "bf_Unduplicate" => bf_unduplicate() => Call::Unduplicate;
+}
+
+extern "C" fn at(runtime: *mut Runtime, row: i32, column: i32, direction: i32, inverse: i32) {
+    let runtime = unsafe { &mut *runtime };
+    let direction = match direction {
+        0 => Direction::North,
+        1 => Direction::South,
+        2 => Direction::East,
+        _ => Direction::West,
+    };
+    #[allow(clippy::cast_sign_loss)]
+    let position = Position(column as usize - 1, row as usize - 1);
+    runtime.at = Some((position, direction, inverse != 0));
+}
+
+// at() and counting the operator, under --max-steps
+extern "C" fn step(runtime: *mut Runtime, row: i32, column: i32, direction: i32, inverse: i32) {
+    at(runtime, row, column, direction, inverse);
+    let runtime = unsafe { &mut *runtime };
+    if let Err(msg) = runtime.step() {
+        runtime.exit(&msg, OUT_OF_STEPS_STATUS);
+    }
+}
+
+extern "C" fn pop_control_stack_i1(runtime: *mut Runtime) -> i8 {
+    let runtime = unsafe { &mut *runtime };
+    match runtime.pop_control_stack_i1() {
+        Ok(val) => i8::from(val),
+        Err(msg) => runtime.die(&msg),
+    }
+}
+
+struct Jit {
+    module: JITModule,
+    runtime_functions: HashMap<&'static str, FuncId>,
+    expressions: HashMap<ExpressionIdentifier, FuncId>,
+    // run --profile, one u64 for each expression that it bumps on the way in,
+    // and one that it writes its index to
+    counters: Option<(*mut u64, *mut u64)>,
+    // --max-steps, count every operator
+    count_steps: bool,
+}
+
+impl Jit {
+    fn new(counters: Option<(*mut u64, *mut u64)>, count_steps: bool) -> Self {
+        let mut flag_builder = settings::builder();
+        flag_builder.set("use_colocated_libcalls", "false").unwrap();
+        flag_builder.set("is_pic", "false").unwrap();
+        // cranelift's tail calls rely on frame pointers
+        flag_builder.set("preserve_frame_pointers", "true").unwrap();
+        let isa = cranelift_native::builder()
+            .unwrap_or_else(|msg| panic!("host machine is not supported: {msg}"))
+            .finish(settings::Flags::new(flag_builder))
+            .unwrap();
+        let mut builder = JITBuilder::with_isa(isa, cranelift_module::default_libcall_names());
+        builder.symbol_lookup_fn(Box::new(shim_address));
+        Self {
+            module: JITModule::new(builder),
+            runtime_functions: HashMap::new(),
+            expressions: HashMap::new(),
+            counters,
+            count_steps,
+        }
+    }
+
+    fn pointer(&self) -> types::Type {
+        self.module.target_config().pointer_type()
+    }
+
+    // expressions only ever tail call each other, so loops in the program
+    // don't grow the native stack
+    fn expression_signature(&self) -> Signature {
+        let mut sig = Signature::new(CallConv::Tail);
+        sig.params.push(AbiParam::new(self.pointer()));
+        sig
+    }
+
+    fn runtime_function(&mut self, symbol: &'static str, args: usize) -> FuncId {
+        if let Some(id) = self.runtime_functions.get(symbol) {
+            return *id;
+        }
+        let mut sig = self.module.make_signature();
+        sig.params.push(AbiParam::new(self.pointer()));
+        for _ in 0..args {
+            sig.params.push(AbiParam::new(types::I32));
+        }
+        if symbol == "pop_control_stack_i1" {
+            sig.returns.push(AbiParam::new(types::I8));
+        }
+        let id = self
+            .module
+            .declare_function(symbol, Linkage::Import, &sig)
+            .unwrap();
+        self.runtime_functions.insert(symbol, id);
+        id
+    }
+
+    fn declare_expressions(&mut self, data: &ExpressionTree) {
+        let sig = self.expression_signature();
+        for identifier in data.tree.keys() {
+            let id = self
+                .module
+                .declare_function(&identifier.to_codegen_symbol(), Linkage::Local, &sig)
+                .unwrap();
+            self.expressions.insert(identifier.clone(), id);
+        }
+    }
+
+    fn define_expressions(&mut self, data: ExpressionTree) {
+        let mut ctx = self.module.make_context();
+        let mut builder_ctx = FunctionBuilderContext::new();
+        for (index, (identifier, expression)) in data.tree.into_iter().enumerate() {
+            ctx.func.signature = self.expression_signature();
+
+            // runtime functions are declared lazily, so work out which
+            // ones this expression needs before borrowing the module
+            let mut calls = vec![];
+            for operator in &expression.arr {
+                if let Some((function, location)) = operator_location(operator, self.count_steps) {
+                    calls.push((self.runtime_function(function, 4), location.to_vec()));
+                }
+                for call in operator_to_calls(operator) {
+                    let (symbol, arg) = call.symbol();
+                    let args: Vec<_> = arg.into_iter().collect();
+                    calls.push((self.runtime_function(symbol, args.len()), args));
+                }
+            }
+            let pop_control = self.runtime_function("pop_control_stack_i1", 0);
+
+            let mut builder = FunctionBuilder::new(&mut ctx.func, &mut builder_ctx);
+            let entry = builder.create_block();
+            builder.append_block_params_for_function_params(entry);
+            builder.switch_to_block(entry);
+            builder.seal_block(entry);
+            let runtime = builder.block_params(entry)[0];
+
+            if let Some((counters, last)) = self.counters {
+                let counter = counters.wrapping_add(index) as i64;
+                let address = builder.ins().iconst(self.pointer(), counter);
+                let count = builder
+                    .ins()
+                    .load(types::I64, MemFlags::trusted(), address, 0);
+                let count = builder.ins().iadd_imm(count, 1);
+                builder.ins().store(MemFlags::trusted(), count, address, 0);
+                let address = builder.ins().iconst(self.pointer(), last as i64);
+                let index = builder.ins().iconst(types::I64, index as i64);
+                builder.ins().store(MemFlags::trusted(), index, address, 0);
+            }
+
+            for (func, call_args) in calls {
+                let func = self.module.declare_func_in_func(func, builder.func);
+                let mut args = vec![runtime];
+                for arg in call_args {
+                    args.push(builder.ins().iconst(types::I32, i64::from(arg)));
+                }
+                builder.ins().call(func, &args);
+            }
+
+            match expression.next {
+                Branches::None => {
+                    builder.ins().return_(&[]);
+                }
+                Branches::One(id1) => {
+                    let next = self
+                        .module
+                        .declare_func_in_func(self.expressions[&id1], builder.func);
+                    builder.ins().return_call(next, &[runtime]);
+                }
+                Branches::Two(id1, id2) => {
+                    let pop_control = self.module.declare_func_in_func(pop_control, builder.func);
+                    let call = builder.ins().call(pop_control, &[runtime]);
+                    let cond = builder.inst_results(call)[0];
+
+                    let branch_1 = builder.create_block();
+                    let branch_0 = builder.create_block();
+                    builder.ins().brif(cond, branch_1, &[], branch_0, &[]);
+
+                    for (block, id) in [(branch_1, id1), (branch_0, id2)] {
+                        builder.switch_to_block(block);
+                        builder.seal_block(block);
+                        let next = self
+                            .module
+                            .declare_func_in_func(self.expressions[&id], builder.func);
+                        builder.ins().return_call(next, &[runtime]);
+                    }
+                }
+            }
+            builder.finalize();
+
+            self.module
+                .define_function(self.expressions[&identifier], &mut ctx)
+                .unwrap_or_else(|err| panic!("failed to compile {identifier:?}: {err:?}"));
+            self.module.clear_context(&mut ctx);
+        }
+    }
+
+    // tail call convention functions can't be called from rust directly,
+    // so wrap the start in a function with the native convention
+    fn define_entry(&mut self, start: &ExpressionIdentifier) -> FuncId {
+        let mut ctx = self.module.make_context();
+        let mut builder_ctx = FunctionBuilderContext::new();
+        ctx.func
+            .signature
+            .params
+            .push(AbiParam::new(self.pointer()));
+        let entry_id = self
+            .module
+            .declare_function("bf_main", Linkage::Local, &ctx.func.signature)
+            .unwrap();
+
+        let mut builder = FunctionBuilder::new(&mut ctx.func, &mut builder_ctx);
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+        builder.seal_block(entry);
+        let runtime = builder.block_params(entry)[0];
+        let start = self
+            .module
+            .declare_func_in_func(self.expressions[start], builder.func);
+        builder.ins().call(start, &[runtime]);
+        builder.ins().return_(&[]);
+        builder.finalize();
+
+        self.module.define_function(entry_id, &mut ctx).unwrap();
+        entry_id
+    }
+}
+
+// compile the tree to native code and run it in process, returning the exit code.
+// with a profile, each expression counts how often it runs into its slot of
+// the counters, in the order of the tree, and leaves its index in last
+pub fn run(data: ExpressionTree, profile: Option<Profile>, options: &CompileOptions) -> i32 {
+    let mut runtime = Runtime::new();
+    runtime.profile = profile;
+    runtime.max_steps = options.max_steps;
+    let counters = runtime.profile.as_mut().map(|profile| {
+        assert_eq!(profile.counters.len(), data.tree.len());
+        (
+            profile.counters.as_mut_ptr(),
+            std::ptr::addr_of_mut!(profile.last),
+        )
+    });
+    let mut jit = Jit::new(counters, options.max_steps.is_some());
+    let start = data.start.clone();
+    jit.declare_expressions(&data);
+    jit.define_expressions(data);
+    let entry = jit.define_entry(&start);
+    jit.module.finalize_definitions().unwrap();
+
+    let code = jit.module.get_finalized_function(entry);
+    let main = unsafe { std::mem::transmute::<*const u8, extern "C" fn(*mut Runtime)>(code) };
+    main(&mut runtime);
+    std::io::stdout().flush().unwrap();
+    if let Some(format) = options.dump_final_state {
+        eprintln!("{}", runtime.final_state(format));
+    }
+    if let Some(profile) = &runtime.profile {
+        profile.finish();
+    }
+    runtime.halt_status(options.halt_status)
+}
@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use inkwell::builder::Builder;
+use inkwell::context::Context;
+use inkwell::debug_info::{
+    AsDIScope, DIFlags, DIFlagsConstants, DISubprogram, DWARFEmissionKind, DWARFSourceLanguage,
+    DebugInfoBuilder,
+};
+use inkwell::memory_buffer::MemoryBuffer;
+use inkwell::module::{FlagBehavior, Module};
+use inkwell::values::{BasicMetadataValueEnum, FunctionValue, InstructionOpcode, InstructionValue};
+
+use crate::runtime::{operator_location, operator_to_calls};
+use crate::{
+    next_ir, CompileOptions, ExpressionIdentifier, ExpressionTree, HaltStatus, Position,
+    BIGNUM_PRELUDE, PRELUDE,
+};
+
+fn runtime_function<'ctx>(
+    module: &Module<'ctx>,
+    symbol: &str,
+) -> Result<FunctionValue<'ctx>, String> {
+    module
+        .get_function(symbol)
+        .ok_or_else(|| format!("@{symbol} is missing from the prelude"))
+}
+
+// the rest of a block from `first` on
+fn instructions(first: Option<InstructionValue<'_>>) -> impl Iterator<Item = InstructionValue<'_>> {
+    std::iter::successors(first, |instruction| instruction.get_next_instruction())
+}
+
+// puts a call the builder didn't make back where it was, picking up the
+// builder's debug location on the way
+fn locate_call<'ctx>(builder: &Builder<'ctx>, call: InstructionValue<'ctx>) {
+    // positioning takes the location of the instruction it's put before
+    let location = builder.get_current_debug_location();
+    // calls are never last, there's always a ret or br after them
+    builder.position_before(&call.get_next_instruction().unwrap());
+    if let Some(location) = location {
+        builder.set_current_debug_location(location);
+    }
+    let name = call
+        .get_name()
+        .map(|name| name.to_string_lossy().into_owned());
+    call.remove_from_basic_block();
+    builder.insert_instruction(&call, name.as_deref());
+}
+
+// -g, the same metadata compile() writes out by hand
+struct Debug<'ctx> {
+    builder: DebugInfoBuilder<'ctx>,
+    file: inkwell::debug_info::DIFile<'ctx>,
+    subroutine: inkwell::debug_info::DISubroutineType<'ctx>,
+}
+
+impl<'ctx> Debug<'ctx> {
+    fn new(module: &Module<'ctx>, file: &str) -> Self {
+        let directory = std::env::current_dir().unwrap_or_default();
+        let (builder, unit) = module.create_debug_info_builder(
+            true,
+            DWARFSourceLanguage::C99,
+            file,
+            &directory.to_string_lossy(),
+            "befreak-compiler",
+            false,
+            "",
+            0,
+            "",
+            DWARFEmissionKind::Full,
+            0,
+            false,
+            false,
+            "",
+            "",
+        );
+        let file = unit.get_file();
+        let subroutine = builder.create_subroutine_type(file, None, &[], DIFlags::ZERO);
+        Self {
+            builder,
+            file,
+            subroutine,
+        }
+    }
+
+    fn subprogram(&self, identifier: &ExpressionIdentifier) -> DISubprogram<'ctx> {
+        #[allow(clippy::cast_possible_truncation)]
+        let line = identifier.position.1 as u32 + 1;
+        self.builder.create_function(
+            self.file.as_debug_info_scope(),
+            &identifier.to_codegen_symbol(),
+            None,
+            self.file,
+            line,
+            self.subroutine,
+            false,
+            true,
+            line,
+            DIFlags::ZERO,
+            false,
+        )
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn locate(
+        &self,
+        builder: &Builder<'ctx>,
+        context: &'ctx Context,
+        Position(x, y): Position,
+        scope: DISubprogram<'ctx>,
+    ) {
+        let location = self.builder.create_debug_location(
+            context,
+            y as u32 + 1,
+            x as u32 + 1,
+            scope.as_debug_info_scope(),
+            None,
+        );
+        builder.set_current_debug_location(location);
+    }
+}
+
+// same output as compile(), but built through LLVM so the module can be
+// verified before anything is printed
+pub fn compile_llvm(data: ExpressionTree, options: &CompileOptions) -> Result<String, String> {
+    let context = Context::create();
+    let prelude = if options.bignum {
+        BIGNUM_PRELUDE
+    } else {
+        PRELUDE
+    };
+    // the LLVM 14 C API can only mark calls `tail`, which gets dropped at -O0 and
+    // every expression call would use up stack. so each expression starts out as
+    // the musttail calls compile() ends its expressions with, and the operators
+    // go in front of them
+    let mut ir = String::from(prelude);
+    for (identifier, expression) in &data.tree {
+        write!(
+            ir,
+            "define void {}() {{\nentry:{}\n}}\n\n",
+            identifier.to_codegen_function_name(),
+            next_ir(&expression.next, "")
+        )
+        .unwrap();
+    }
+    let buffer = MemoryBuffer::create_from_memory_range_copy(ir.as_bytes(), "prelude");
+    let module = context
+        .create_module_from_ir(buffer)
+        .map_err(|err| format!("prelude: {err}"))?;
+    let builder = context.create_builder();
+    let i32_type = context.i32_type();
+
+    let functions: HashMap<ExpressionIdentifier, FunctionValue> = data
+        .tree
+        .keys()
+        .map(|identifier| {
+            let function = module.get_function(&identifier.to_codegen_symbol());
+            (identifier.clone(), function.unwrap())
+        })
+        .collect();
+
+    let debug = options.debug_info.as_deref().map(|file| {
+        let debug = Debug::new(&module, file);
+        let flag = |version| i32_type.const_int(version, false);
+        module.add_basic_value_flag("Dwarf Version", FlagBehavior::Warning, flag(4));
+        module.add_basic_value_flag("Debug Info Version", FlagBehavior::Warning, flag(3));
+        debug
+    });
+
+    for (identifier, expression) in data.tree {
+        let function = functions[&identifier];
+        let entry = function.get_first_basic_block().unwrap();
+        let leave = entry.get_first_instruction().unwrap();
+        builder.position_before(&leave);
+        let scope = debug.as_ref().map(|debug| {
+            let scope = debug.subprogram(&identifier);
+            function.set_subprogram(scope);
+            // the calls that leave the expression belong to the operator that ends it,
+            // or the expression's first cell if it doesn't have any
+            debug.locate(&builder, &context, identifier.position, scope);
+            scope
+        });
+
+        for operator in &expression.arr {
+            if let Some((debug, scope)) = debug.as_ref().zip(scope) {
+                debug.locate(&builder, &context, operator.position, scope);
+            }
+            // so runtime errors can say which operator they came from
+            let location = operator_location(operator, options.max_steps.is_some())
+                .map(|(function, location)| (function, location.to_vec()));
+            let calls = operator_to_calls(operator).into_iter().map(|call| {
+                let (symbol, arg) = call.symbol();
+                (symbol, arg.into_iter().collect())
+            });
+            for (symbol, args) in location.into_iter().chain(calls) {
+                let args: Vec<BasicMetadataValueEnum> = args
+                    .into_iter()
+                    .map(|arg| i32_type.const_int(i64::from(arg) as u64, true).into())
+                    .collect();
+                builder
+                    .build_call(runtime_function(&module, symbol)?, &args, "")
+                    .map_err(|err| err.to_string())?;
+            }
+        }
+
+        // and so do the calls next_ir() left behind them
+        if debug.is_some() {
+            let calls: Vec<_> = instructions(Some(leave))
+                .chain(
+                    function
+                        .get_basic_blocks()
+                        .into_iter()
+                        .skip(1)
+                        .flat_map(|block| instructions(block.get_first_instruction())),
+                )
+                .filter(|instruction| instruction.get_opcode() == InstructionOpcode::Call)
+                .collect();
+            for call in calls {
+                locate_call(&builder, call);
+            }
+        }
+    }
+
+    builder.unset_current_debug_location();
+    let main = module.add_function("main", i32_type.fn_type(&[], false), None);
+    builder.position_at_end(context.append_basic_block(main, "entry"));
+    if let Some(max_steps) = options.max_steps {
+        let max_steps = context.i64_type().const_int(max_steps, false);
+        builder
+            .build_call(
+                runtime_function(&module, "limit_steps")?,
+                &[max_steps.into()],
+                "",
+            )
+            .map_err(|err| err.to_string())?;
+    }
+    builder
+        .build_call(functions[&data.start], &[], "")
+        .map_err(|err| err.to_string())?;
+    // the start expression only returns once the program halts
+    if let Some(format) = options.dump_final_state {
+        let json = i32_type.const_int(format.json() as u64, false);
+        builder
+            .build_call(
+                runtime_function(&module, "dump_final_state")?,
+                &[json.into()],
+                "",
+            )
+            .map_err(|err| err.to_string())?;
+    }
+    let status = match options.halt_status {
+        HaltStatus::Zero => i32_type.const_zero(),
+        HaltStatus::Top => builder
+            .build_call(runtime_function(&module, "halt_status")?, &[], "status")
+            .map_err(|err| err.to_string())?
+            .try_as_basic_value()
+            .left()
+            .ok_or("@halt_status doesn't return a value")?
+            .into_int_value(),
+    };
+    builder
+        .build_return(Some(&status))
+        .map_err(|err| err.to_string())?;
+
+    if let Some(debug) = &debug {
+        debug.builder.finalize();
+    }
+    module.verify().map_err(|err| err.to_string())?;
+    Ok(module.print_to_string().to_string())
+}
@@ -0,0 +1,485 @@
+use std::collections::HashMap;
+
+use array2d::Array2D;
+use serde_json::{json, Value};
+
+use crate::runtime::{operator_to_calls, Call};
+use crate::wire::{read_message, write_message};
+use crate::{
+    get_char, parse_code, parse_operator, read_string, Branches, Directions, ExpressionTree,
+    Operator, OperatorSymbol, Position,
+};
+
+// what one call does to the stacks, in the same notation as the runtime
+#[allow(clippy::match_same_arms)]
+fn effect(call: Call) -> Option<String> {
+    Some(match call {
+        Call::Unimplemented(_) => "isn't supported by the compiler yet".to_string(),
+        Call::PushStack(val) => format!("pushes {val}"),
+        Call::PushControlStack(val) => format!("pushes {val} onto the control stack"),
+        Call::ToggleControlStack | Call::ToggleControl => {
+            "toggles the top of the control stack".to_string()
+        }
+
+        Call::Number(num) => format!("xors {num} into the top of the stack"),
+        Call::PushZero => "pushes a zero".to_string(),
+        Call::PopZero => "pops a zero".to_string(),
+        Call::PopMainToControl => "moves the top of the stack onto the control stack".to_string(),
+        Call::PopControlToMain => "moves the top of the control stack onto the stack".to_string(),
+        Call::SwapStacks => "swaps the tops of the stack and the control stack".to_string(),
+        Call::Write => "pops a character and writes it".to_string(),
+        Call::Read => "reads a character and pushes it, -1 at the end of input".to_string(),
+        Call::Increment => "(x -- x+1)".to_string(),
+        Call::Decrement => "(x -- x-1)".to_string(),
+        Call::Add => "(y x -- y+x x)".to_string(),
+        Call::Subtract => "(y x -- y-x x)".to_string(),
+        Call::Divide => "(y x -- y/x y%x x)".to_string(),
+        Call::Multiply => "(div rem x -- div*x+rem x)".to_string(),
+        Call::Not => "(x -- ~x)".to_string(),
+        Call::And => "(z y x -- z^(y&x) y x)".to_string(),
+        Call::Or => "(z y x -- z^(y|x) y x)".to_string(),
+        Call::Xor => "(y x -- y^x x)".to_string(),
+        Call::RotateLeft => "rotates y left by x bits (y x -- y x)".to_string(),
+        Call::RotateRight => "rotates y right by x bits (y x -- y x)".to_string(),
+        Call::EqualityCheck => {
+            "toggles the top of the control stack if y == x (y x -- y x)".to_string()
+        }
+        Call::LessThanCheck => {
+            "toggles the top of the control stack if y < x (y x -- y x)".to_string()
+        }
+        Call::GreaterThanCheck => {
+            "toggles the top of the control stack if y > x (y x -- y x)".to_string()
+        }
+        Call::SwapTop => "(y x -- x y)".to_string(),
+        Call::Dig => "(z y x -- y x z)".to_string(),
+        Call::Bury => "(z y x -- x z y)".to_string(),
+        Call::Flip => "(z y x -- x y z)".to_string(),
+        Call::SwapLower => "(z y x -- y z x)".to_string(),
+        Call::Over => "(y x -- y x y)".to_string(),
+        Call::Under => "(y x y -- y x)".to_string(),
+        Call::Duplicate => "(x -- x x)".to_string(),
+        Call::Unduplicate => "(x x -- x)".to_string(),
+    })
+}
+
+// what an operator does to the stacks and the instruction pointer
+fn describe(operator: &Operator, directions: &Directions) -> String {
+    let mut effects: Vec<_> = match &operator.operation {
+        OperatorSymbol::String(str) if !operator.inverse => {
+            vec![format!("pushes {str:?} a character at a time")]
+        }
+        _ => operator_to_calls(operator)
+            .into_iter()
+            .filter_map(effect)
+            .collect(),
+    };
+    match *directions {
+        Directions::Continue(direction) if direction != operator.in_direction => {
+            effects.push(format!("turns {direction:?}"));
+        }
+        Directions::Continue(_) => {}
+        Directions::ContinueInversed(_) => effects.push("toggles inverse mode".to_string()),
+        // the parser swaps the branches round in inverse mode
+        Directions::Branch(one, two) => {
+            let (nonzero, zero) = if operator.inverse {
+                (two, one)
+            } else {
+                (one, two)
+            };
+            effects.push(format!(
+                "pops the control stack, heading {nonzero:?} if it was nonzero and {zero:?} if it was zero"
+            ));
+        }
+        Directions::Halt => effects.push("halts".to_string()),
+    }
+    if effects.is_empty() {
+        "does nothing".to_string()
+    } else {
+        effects.join(", then ")
+    }
+}
+
+// every operator read from a cell, in each direction and mode it's reached in
+fn operators_at<'a>(
+    tree: &'a ExpressionTree,
+    code: &Array2D<char>,
+    wrap: bool,
+    cell: Position,
+) -> Vec<&'a Operator> {
+    let mut operators: Vec<_> = tree
+        .tree
+        .values()
+        .flat_map(|expression| &expression.arr)
+        .filter(|operator| operator.cells(code, wrap).contains(&cell))
+        .collect();
+    operators.sort_by_key(|operator| (operator.in_direction, operator.inverse));
+    operators
+}
+
+// lsp counts utf-16 code units along the line, the grid counts chars
+fn to_character(text: &str, position: Position) -> usize {
+    let line = text.lines().nth(position.1).unwrap_or_default();
+    let chars = line.chars().count();
+    let within: usize = line.chars().take(position.0).map(char::len_utf16).sum();
+    within + position.0.saturating_sub(chars)
+}
+
+fn to_position(text: &str, line: usize, character: usize) -> Position {
+    let mut units = 0;
+    let mut column = 0;
+    for char in text.lines().nth(line).unwrap_or_default().chars() {
+        if units >= character {
+            return Position(column, line);
+        }
+        units += char.len_utf16();
+        column += 1;
+    }
+    Position(column + character.saturating_sub(units), line)
+}
+
+fn range(text: &str, position: Position) -> Value {
+    let start = to_character(text, position);
+    let end = to_character(text, Position(position.0 + 1, position.1));
+    json!({
+        "start": { "line": position.1, "character": start },
+        "end": { "line": position.1, "character": end },
+    })
+}
+
+fn grid(text: &str) -> Option<Array2D<char>> {
+    // read_string needs at least one line
+    text.lines().next().map(|_| read_string(text))
+}
+
+#[derive(Default)]
+pub struct Server {
+    // the text of every open document, by uri
+    documents: HashMap<String, String>,
+    wrap: bool,
+    exit: bool,
+}
+
+impl Server {
+    fn diagnostics(&self, uri: &str) -> Value {
+        let text = &self.documents[uri];
+        let (error, warnings) = match grid(text) {
+            Some(code) => match parse_code(&code, self.wrap) {
+                Ok(tree) => (None, tree.warnings),
+                Err(error) => (Some(error), vec![]),
+            },
+            None => (Some("no @ to start from".into()), vec![]),
+        };
+        // severity 1 is an error, 2 a warning
+        let diagnostics: Vec<_> = error
+            .into_iter()
+            .map(|error| (1, error))
+            .chain(warnings.into_iter().map(|warning| (2, warning)))
+            .map(|(severity, diagnostic)| {
+                json!({
+                    "range": range(text, diagnostic.position.unwrap_or(Position(0, 0))),
+                    "severity": severity,
+                    "source": "befreak",
+                    "message": diagnostic.message,
+                })
+            })
+            .collect();
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": diagnostics },
+        })
+    }
+
+    // the parsed document and the cell a request points at
+    fn locate(&self, params: &Value) -> Option<(&str, Array2D<char>, ExpressionTree, Position)> {
+        let uri = params["textDocument"]["uri"].as_str()?;
+        let text = self.documents.get(uri)?;
+        let code = grid(text)?;
+        let tree = parse_code(&code, self.wrap).ok()?;
+        let line = usize::try_from(params["position"]["line"].as_u64()?).ok()?;
+        let character = usize::try_from(params["position"]["character"].as_u64()?).ok()?;
+        let cell = to_position(text, line, character);
+        get_char(&code, cell)?;
+        Some((text, code, tree, cell))
+    }
+
+    fn hover(&self, params: &Value) -> Option<Value> {
+        let (text, code, tree, cell) = self.locate(params)?;
+        let char = get_char(&code, cell)?;
+        let lines: Vec<_> = operators_at(&tree, &code, self.wrap, cell)
+            .into_iter()
+            .map(|operator| {
+                let mut position = operator.position;
+                let directions =
+                    parse_operator(&mut position, operator.in_direction, &code, self.wrap)
+                        .map_or(Directions::Halt, |(_, directions)| directions);
+                format!(
+                    "heading {:?}, {}: {}",
+                    operator.in_direction,
+                    if operator.inverse {
+                        "inverse"
+                    } else {
+                        "normal"
+                    },
+                    describe(operator, &directions)
+                )
+            })
+            .collect();
+        let value = if lines.is_empty() {
+            format!("`{char}` is never reached from the @")
+        } else {
+            format!("`{char}`\n\n{}", lines.join("\n\n"))
+        };
+        Some(json!({
+            "contents": { "kind": "markdown", "value": value },
+            "range": range(text, cell),
+        }))
+    }
+
+    // where each branch out of the cell goes
+    fn definition(&self, params: &Value) -> Option<Value> {
+        let (text, _, tree, cell) = self.locate(params)?;
+        let uri = params["textDocument"]["uri"].as_str()?;
+        let mut targets = vec![];
+        for expression in tree.tree.values() {
+            if let (Some(last), Branches::Two(first, second)) =
+                (expression.arr.last(), &expression.next)
+            {
+                if last.position == cell {
+                    targets.push(first.position);
+                    targets.push(second.position);
+                }
+            }
+        }
+        targets.sort();
+        targets.dedup();
+        if targets.is_empty() {
+            return None;
+        }
+        Some(Value::Array(
+            targets
+                .into_iter()
+                .map(|target| json!({ "uri": uri, "range": range(text, target) }))
+                .collect(),
+        ))
+    }
+
+    // the replies and notifications one message from the editor needs
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let params = &message["params"];
+        let method = message["method"].as_str().unwrap_or_default();
+        let result = match method {
+            "initialize" => {
+                self.wrap = params["initializationOptions"]["wrap"]
+                    .as_bool()
+                    .unwrap_or(false);
+                json!({
+                    "capabilities": {
+                        // the whole document is sent on every change
+                        "textDocumentSync": 1,
+                        "hoverProvider": true,
+                        "definitionProvider": true,
+                    },
+                    "serverInfo": { "name": "befreak-compiler" },
+                })
+            }
+            "textDocument/didOpen" | "textDocument/didChange" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                let text = if method == "textDocument/didOpen" {
+                    params["textDocument"]["text"].as_str()
+                } else {
+                    params["contentChanges"]
+                        .as_array()
+                        .and_then(|changes| changes.last())
+                        .and_then(|change| change["text"].as_str())
+                };
+                self.documents
+                    .insert(uri.to_string(), text.unwrap_or_default().to_string());
+                return vec![self.diagnostics(uri)];
+            }
+            "textDocument/didClose" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                self.documents.remove(uri);
+                return vec![json!({
+                    "jsonrpc": "2.0",
+                    "method": "textDocument/publishDiagnostics",
+                    "params": { "uri": uri, "diagnostics": [] },
+                })];
+            }
+            "textDocument/hover" => self.hover(params).unwrap_or(Value::Null),
+            "textDocument/definition" => self.definition(params).unwrap_or(Value::Null),
+            "shutdown" => Value::Null,
+            "exit" => {
+                self.exit = true;
+                return vec![];
+            }
+            _ if message.get("id").is_some() => {
+                return vec![json!({
+                    "jsonrpc": "2.0",
+                    "id": message["id"],
+                    "error": { "code": -32601, "message": format!("unknown method {method}") },
+                })];
+            }
+            // notifications we don't need
+            _ => return vec![],
+        };
+        match message.get("id") {
+            Some(id) => vec![json!({ "jsonrpc": "2.0", "id": id, "result": result })],
+            None => vec![],
+        }
+    }
+}
+
+// serves one editor over stdin and stdout until it says to exit
+pub fn serve() -> std::io::Result<()> {
+    let mut server = Server::default();
+    let mut stdin = std::io::stdin().lock();
+    let mut stdout = std::io::stdout().lock();
+    while let Some(message) = read_message(&mut stdin)? {
+        for reply in server.handle(&message) {
+            write_message(&mut stdout, &reply)?;
+        }
+        if server.exit {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URI: &str = "file:///test.bf";
+
+    fn open(text: &str) -> (Server, Vec<Value>) {
+        let mut server = Server::default();
+        server.handle(&json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }));
+        let replies = server.handle(&json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": URI, "languageId": "befreak", "version": 1, "text": text } },
+        }));
+        (server, replies)
+    }
+
+    fn request(server: &mut Server, method: &str, line: usize, character: usize) -> Value {
+        let replies = server.handle(&json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": method,
+            "params": { "textDocument": { "uri": URI }, "position": { "line": line, "character": character } },
+        }));
+        replies[0]["result"].clone()
+    }
+
+    #[test]
+    fn diagnostics_point_at_the_cell() {
+        for (text, message, line, character) in [
+            (
+                "@(x",
+                "invalid character 'x' at row 1, column 3 heading East",
+                0,
+                2,
+            ),
+            // the clef is two utf-16 code units
+            (
+                "\"𝄞\"@\"a",
+                "unterminated string at row 1, column 5 heading East",
+                0,
+                5,
+            ),
+            (
+                "@99999999999 @",
+                "number too large at row 1, column 2 heading East",
+                0,
+                1,
+            ),
+            (
+                "@\\\n /",
+                "walked off the grid leaving row 2, column 1 heading West",
+                1,
+                0,
+            ),
+            ("((", "no @ to start from", 0, 0),
+            ("", "no @ to start from", 0, 0),
+        ] {
+            let (_, replies) = open(text);
+            let diagnostic = &replies[0]["params"]["diagnostics"][0];
+            assert_eq!(diagnostic["message"], message, "{text:?}");
+            assert_eq!(
+                diagnostic["range"]["start"],
+                json!({ "line": line, "character": character })
+            );
+        }
+        let (_, replies) = open(include_str!("../tests/fixtures/primes.bf"));
+        assert_eq!(replies[0]["params"]["diagnostics"], json!([]));
+    }
+
+    #[test]
+    fn infinite_loops_are_warnings() {
+        let (_, replies) = open(include_str!("../tests/fixtures/loop.bf"));
+        assert_eq!(
+            replies[0]["params"]["diagnostics"],
+            json!([{
+                "range": {
+                    "start": { "line": 1, "character": 2 },
+                    "end": { "line": 1, "character": 3 },
+                },
+                "severity": 2,
+                "source": "befreak",
+                "message": "unconditional infinite loop at row 2, column 3 heading South",
+            }])
+        );
+    }
+
+    #[test]
+    fn hover_describes_each_way_through_the_cell() {
+        let (mut server, _) = open("@(1+?+@");
+        let hover = request(&mut server, "textDocument/hover", 0, 3);
+        assert_eq!(
+            hover["contents"]["value"],
+            "`+`\n\nheading East, normal: (y x -- y+x x)"
+        );
+        let hover = request(&mut server, "textDocument/hover", 0, 5);
+        assert_eq!(
+            hover["contents"]["value"],
+            "`+`\n\nheading East, inverse: (y x -- y-x x)"
+        );
+
+        let (mut server, _) = open(include_str!("../tests/fixtures/ladder.bf"));
+        let hover = request(&mut server, "textDocument/hover", 1, 1);
+        assert_eq!(
+            hover["contents"]["value"],
+            "`^`\n\nheading South, normal: pops the control stack, heading West if it was nonzero and East if it was zero"
+        );
+        let hover = request(&mut server, "textDocument/hover", 0, 0);
+        assert_eq!(
+            hover["contents"]["value"],
+            "`@` is never reached from the @"
+        );
+    }
+
+    #[test]
+    fn definition_goes_to_both_branch_targets() {
+        let (mut server, _) = open(include_str!("../tests/fixtures/ladder.bf"));
+        let targets = request(&mut server, "textDocument/definition", 1, 1);
+        let starts: Vec<_> = targets
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|target| target["range"]["start"].clone())
+            .collect();
+        assert_eq!(
+            starts,
+            [
+                json!({ "line": 1, "character": 0 }),
+                json!({ "line": 1, "character": 2 })
+            ]
+        );
+        assert_eq!(
+            request(&mut server, "textDocument/definition", 0, 1),
+            Value::Null
+        );
+    }
+}
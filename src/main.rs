@@ -1,8 +1,28 @@
 #![allow(dead_code, clippy::needless_raw_string_hashes)]
 use array2d::Array2D;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::Write;
 
+mod c_backend;
+#[cfg(feature = "dap")]
+mod dap;
+mod debugger;
+mod dispatch;
+mod interpreter;
+#[cfg(feature = "jit")]
+mod jit;
+#[cfg(feature = "llvm")]
+mod llvm_builder;
+#[cfg(feature = "lsp")]
+mod lsp;
+mod profile;
+mod runtime;
+#[cfg(feature = "tui")]
+mod tui;
+mod wat_backend;
+#[cfg(any(feature = "lsp", feature = "dap"))]
+mod wire;
+
 // TODO:
 // implement all of the bf_ functions
 // fix num/string going over edges
@@ -10,7 +30,7 @@ use std::fmt::Write;
 // implement inverted string (just pop n elements off the stack cuz who needs validating?)
 // automatically pipe the output into `clang -O3 -x "ir" -`
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum Direction {
     North,
     South,
@@ -18,21 +38,86 @@ pub enum Direction {
     West,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+impl Direction {
+    // running backwards heads the other way
+    const fn reverse(self) -> Self {
+        match self {
+            Self::North => Self::South,
+            Self::South => Self::North,
+            Self::East => Self::West,
+            Self::West => Self::East,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 struct Position(usize, usize);
 
 impl Position {
-    const fn step(&self, dir: Direction) -> Self {
-        match dir {
-            Direction::North => Self(self.0, self.1 - 1),
+    // the next cell over, None if that's off the grid.
+    // with wrap the grid is a torus and there's always a next cell
+    fn step(&self, dir: Direction, code: &Array2D<char>, wrap: bool) -> Option<Self> {
+        let (columns, rows) = (code.num_columns(), code.num_rows());
+        if wrap {
+            return Some(match dir {
+                Direction::North => Self(self.0, (self.1 + rows - 1) % rows),
+                Direction::South => Self(self.0, (self.1 + 1) % rows),
+                Direction::East => Self((self.0 + 1) % columns, self.1),
+                Direction::West => Self((self.0 + columns - 1) % columns, self.1),
+            });
+        }
+        let next = match dir {
+            Direction::North => Self(self.0, self.1.checked_sub(1)?),
             Direction::South => Self(self.0, self.1 + 1),
             Direction::East => Self(self.0 + 1, self.1),
-            Direction::West => Self(self.0 - 1, self.1),
+            Direction::West => Self(self.0.checked_sub(1)?, self.1),
+        };
+        (next.0 < columns && next.1 < rows).then_some(next)
+    }
+}
+
+// for diagnostics, rows and columns count from 1 like an editor
+fn location(position: Position, direction: Direction) -> String {
+    format!(
+        "row {}, column {} heading {:?}",
+        position.1 + 1,
+        position.0 + 1,
+        direction
+    )
+}
+
+// a parse error, and the cell it happened at if there is one
+#[derive(Debug, PartialEq, Eq)]
+struct ParseError {
+    message: String,
+    position: Option<Position>,
+}
+
+impl ParseError {
+    const fn at(position: Position, message: String) -> Self {
+        Self {
+            message,
+            position: Some(position),
         }
     }
 }
 
-#[derive(Debug)]
+impl From<&str> for ParseError {
+    fn from(message: &str) -> Self {
+        Self {
+            message: message.to_string(),
+            position: None,
+        }
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+#[derive(Clone, Debug)]
 enum OperatorSymbol {
     Blank,
 
@@ -97,11 +182,31 @@ enum OperatorSymbol {
     NorthBranch, // ^
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 struct Operator {
     operation: OperatorSymbol,
     in_direction: Direction,
     inverse: bool,
+    // where the operator starts on the grid, numbers and strings span several cells
+    position: Position,
+}
+
+impl Operator {
+    // the cells it was read from, numbers and strings take up more than one
+    fn cells(&self, code: &Array2D<char>, wrap: bool) -> Vec<Position> {
+        let mut end = self.position;
+        let _ = parse_operator(&mut end, self.in_direction, code, wrap);
+        let mut cells = vec![self.position];
+        let mut position = self.position;
+        while position != end {
+            let Some(next) = position.step(self.in_direction, code, wrap) else {
+                break;
+            };
+            position = next;
+            cells.push(position);
+        }
+        cells
+    }
 }
 
 #[derive(Debug)]
@@ -110,7 +215,7 @@ struct Expression {
     next: Branches,
 }
 
-#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq, PartialOrd, Ord)]
 struct ExpressionIdentifier {
     position: Position,
     direction: Direction,
@@ -127,13 +232,17 @@ impl ExpressionIdentifier {
     }
 
     fn to_codegen_function_name(&self) -> String {
+        format!("@{}", self.to_codegen_symbol())
+    }
+
+    fn to_codegen_symbol(&self) -> String {
         let Self {
             position,
             direction,
             inverse_mode,
         } = *self;
         format!(
-            "@bf_cg_{}_{}_{}_{}",
+            "bf_cg_{}_{}_{}_{}",
             position.0,
             position.1,
             match direction {
@@ -161,9 +270,48 @@ enum Branches {
     One(ExpressionIdentifier),
     Two(ExpressionIdentifier, ExpressionIdentifier),
 }
+#[derive(Debug)]
 struct ExpressionTree {
-    tree: HashMap<ExpressionIdentifier, Expression>,
+    // ordered so the generated code comes out the same every time
+    tree: BTreeMap<ExpressionIdentifier, Expression>,
     start: ExpressionIdentifier,
+    // the expression, and index into it, each operator was parsed into.
+    // keyed by the state the operator was read in
+    owners: HashMap<ExpressionIdentifier, (ExpressionIdentifier, usize)>,
+    // code that parses but can't work the way it looks, the cli prints these
+    // and the language server shows them as warnings
+    warnings: Vec<ParseError>,
+}
+
+impl ExpressionTree {
+    fn insert(&mut self, identifier: ExpressionIdentifier, expression: Expression) {
+        for (index, operator) in expression.arr.iter().enumerate() {
+            self.owners.insert(
+                ExpressionIdentifier::new(
+                    operator.inverse,
+                    operator.position,
+                    operator.in_direction,
+                ),
+                (identifier.clone(), index),
+            );
+        }
+        self.tree.insert(identifier, expression);
+    }
+
+    // if another path already reached this state, make sure an expression
+    // starts there (splitting the one that contains it) so both can jump to it
+    fn join_at(&mut self, state: &ExpressionIdentifier) -> bool {
+        let Some((owner, index)) = self.owners.get(state).cloned() else {
+            return false;
+        };
+        if index != 0 {
+            let expression = self.tree.get_mut(&owner).unwrap();
+            let arr = expression.arr.split_off(index);
+            let next = std::mem::replace(&mut expression.next, Branches::One(state.clone()));
+            self.insert(state.clone(), Expression { arr, next });
+        }
+        true
+    }
 }
 
 fn get_char(code: &Array2D<char>, position: Position) -> Option<&char> {
@@ -175,36 +323,54 @@ fn parse_operator(
     position: &mut Position, // modifies position for reading strings/numbers
     direction: Direction,
     code: &Array2D<char>,
-) -> (OperatorSymbol, Directions) {
-    let Some(char) = get_char(code, *position) else {
-        return (OperatorSymbol::Halt, Directions::Halt);
-    };
-    match char {
+    wrap: bool,
+) -> Result<(OperatorSymbol, Directions), ParseError> {
+    // positions are checked as they're stepped to
+    let char = get_char(code, *position).unwrap();
+    Ok(match char {
         '0' | '1' | '2' | '3' | '4' | '5' | '6' | '7' | '8' | '9' => {
+            let start = *position;
             let mut x = char.to_digit(10).unwrap();
-            loop {
-                let next_char = get_char(code, position.step(direction)).unwrap();
-                if matches!(
-                    next_char,
-                    '0' | '1' | '2' | '3' | '4' | '5' | '6' | '7' | '8' | '9'
-                ) {
-                    x = x * 10 + next_char.to_digit(10).unwrap();
-                    *position = position.step(direction);
-                } else {
-                    return (
-                        OperatorSymbol::Number(x as usize),
-                        Directions::Continue(direction),
-                    );
-                }
+            // a number ends at the edge, or where it started if it wrapped all the way round
+            while let Some(next) = position
+                .step(direction, code, wrap)
+                .filter(|next| *next != start)
+            {
+                let Some(digit) = get_char(code, next).and_then(|char| char.to_digit(10)) else {
+                    break;
+                };
+                // it has to fit in a cell
+                x = x
+                    .checked_mul(10)
+                    .and_then(|x| x.checked_add(digit))
+                    .filter(|x| i32::try_from(*x).is_ok())
+                    .ok_or_else(|| {
+                        ParseError::at(
+                            start,
+                            format!("number too large at {}", location(start, direction)),
+                        )
+                    })?;
+                *position = next;
             }
+            (
+                OperatorSymbol::Number(x as usize),
+                Directions::Continue(direction),
+            )
         }
         '"' => {
+            let start = *position;
             let mut str = String::new();
             loop {
-                let char = get_char(code, position.step(direction)).unwrap();
-                *position = position.step(direction);
+                let Some(next) = position.step(direction, code, wrap) else {
+                    return Err(ParseError::at(
+                        start,
+                        format!("unterminated string at {}", location(start, direction)),
+                    ));
+                };
+                *position = next;
+                let char = get_char(code, next).unwrap();
                 if *char == '"' {
-                    return (OperatorSymbol::String(str), Directions::Continue(direction));
+                    break (OperatorSymbol::String(str), Directions::Continue(direction));
                 }
                 str.push(*char);
             }
@@ -327,8 +493,16 @@ fn parse_operator(
             OperatorSymbol::SwapStacks,
             Directions::Branch(Direction::South, Direction::East),
         ),
-        _ => panic!("{char}: invalid char!!"),
-    }
+        _ => {
+            return Err(ParseError::at(
+                *position,
+                format!(
+                    "invalid character {char:?} at {}",
+                    location(*position, direction)
+                ),
+            ))
+        }
+    })
 }
 
 fn parse_expression(
@@ -337,54 +511,99 @@ fn parse_expression(
     mut direction: Direction,
     mut inverse_mode: bool,
     data: &mut ExpressionTree,
-) {
+    worklist: &mut Vec<ExpressionIdentifier>,
+    wrap: bool,
+) -> Result<(), ParseError> {
     let mut expression = vec![];
     let initial_identifier = ExpressionIdentifier::new(inverse_mode, position, direction);
+    let mut seen = HashSet::new();
     loop {
+        let state = ExpressionIdentifier::new(inverse_mode, position, direction);
+        if !seen.insert(state.clone()) {
+            // a cycle with no branch in it, the only way out is a runtime error
+            data.warnings.push(ParseError::at(
+                position,
+                format!(
+                    "unconditional infinite loop at {}",
+                    location(position, direction)
+                ),
+            ));
+            data.insert(
+                initial_identifier,
+                Expression {
+                    arr: expression,
+                    next: Branches::One(state.clone()),
+                },
+            );
+            // loop back to where the cycle starts rather than the whole walk
+            data.join_at(&state);
+            return Ok(());
+        }
+        if data.join_at(&state) {
+            // an empty expression would just be the one we joined
+            if !expression.is_empty() {
+                data.insert(
+                    initial_identifier,
+                    Expression {
+                        arr: expression,
+                        next: Branches::One(state),
+                    },
+                );
+            }
+            return Ok(());
+        }
+
+        let operator_position = position;
         // position is skipped forwards if reading a string/number
-        let (operator, directions) = parse_operator(&mut position, direction, code);
+        let (operator, directions) = parse_operator(&mut position, direction, code, wrap)?;
 
         expression.push(Operator {
             operation: operator,
             in_direction: direction,
             inverse: inverse_mode,
+            position: operator_position,
         });
 
+        let step = |dir| {
+            position
+                .step(dir, code, wrap)
+                .ok_or_else(|| {
+                    ParseError::at(
+                        position,
+                        format!("walked off the grid leaving {}", location(position, dir)),
+                    )
+                })
+        };
         match directions {
             Directions::Continue(dir1) => {
+                position = step(dir1)?;
                 direction = dir1;
-                position = position.step(direction);
                 continue;
             }
             Directions::ContinueInversed(dir1) => {
+                position = step(dir1)?;
                 direction = dir1;
-                position = position.step(direction);
                 inverse_mode = !inverse_mode;
                 continue;
             }
             Directions::Halt => {
-                data.tree.insert(
+                data.insert(
                     initial_identifier,
                     Expression {
                         arr: expression,
                         next: Branches::None,
                     },
                 );
-                return;
+                return Ok(());
             }
             Directions::Branch(dir1, dir2) => {
-                let one = ExpressionIdentifier {
-                    position: position.step(dir1),
-                    direction: dir1,
-                    inverse_mode,
-                };
-                let two = ExpressionIdentifier {
-                    position: position.step(dir2),
-                    direction: dir2,
-                    inverse_mode,
-                };
+                let one = ExpressionIdentifier::new(inverse_mode, step(dir1)?, dir1);
+                let two = ExpressionIdentifier::new(inverse_mode, step(dir2)?, dir2);
+                // parsed later by parse_code, so deep branching doesn't grow the stack
+                worklist.push(one.clone());
+                worklist.push(two.clone());
                 if inverse_mode {
-                    data.tree.insert(
+                    data.insert(
                         initial_identifier,
                         Expression {
                             arr: expression,
@@ -392,7 +611,7 @@ fn parse_expression(
                         },
                     );
                 } else {
-                    data.tree.insert(
+                    data.insert(
                         initial_identifier,
                         Expression {
                             arr: expression,
@@ -400,22 +619,7 @@ fn parse_expression(
                         },
                     );
                 }
-
-                if !data.tree.contains_key(&ExpressionIdentifier::new(
-                    inverse_mode,
-                    position.step(dir1),
-                    dir1,
-                )) {
-                    parse_expression(code, position.step(dir1), dir1, inverse_mode, data);
-                };
-                if !data.tree.contains_key(&ExpressionIdentifier::new(
-                    inverse_mode,
-                    position.step(dir2),
-                    dir2,
-                )) {
-                    parse_expression(code, position.step(dir2), dir2, inverse_mode, data);
-                };
-                return;
+                return Ok(());
             }
         }
     }
@@ -434,43 +638,21 @@ fn get_start_pos(code: &Array2D<char>) -> Option<Position> {
 
 // COMPILING
 
-fn string_to_i32_arr(str: &str) -> String {
-    let mut res = str.chars().fold(String::new(), |mut acc, char| {
-        write!(acc, "i32 {}, ", char as u64).unwrap();
+// push each char in turn, last char ends up on top
+fn string_llvm_ir(str: &str) -> String {
+    str.chars().fold(String::new(), |mut acc, char| {
+        write!(acc, "\n    call void @push_stack(i32 {})", char as u64).unwrap();
         acc
-    });
-    res.pop();
-    res.pop();
-    res
-}
-
-fn string_llvm_ir(str: &str, string_name: &str) -> String {
-    format!(
-        "
-    ; STRING CODE BEGIN
-    call void @increment_stack(i32 1)
-
-    ; paste string onto the stack
-    %offset = load i32, i32* @primary_offset
-    %ptr = getelementptr [40 x i32], i32* @primary_stack, i32 0, i32 %offset
-    %str = load [{length} x i32], i32* @{var}
-    store [{length} x i32] %str, ptr %ptr
-
-    call void @increment_stack(i32 {length_minus_one}) ; len - 1
-    ; STRING CODE END
-",
-        var = string_name,
-        length = str.len(),
-        length_minus_one = str.len() - 1
-    )
+    })
 }
 
 #[allow(clippy::match_same_arms, clippy::too_many_lines)]
-fn operator_to_llvm_ir(str: &mut String, epilogue: &mut String, operator_data: Operator) {
+fn operator_to_llvm_ir(str: &mut String, operator_data: Operator) {
     let Operator {
         operation: operator,
         inverse,
         in_direction: direction,
+        ..
     } = operator_data;
 
     let tmp;
@@ -484,22 +666,13 @@ fn operator_to_llvm_ir(str: &mut String, epilogue: &mut String, operator_data: O
             &tmp
         }
         (false, OperatorSymbol::String(str)) => {
-            let string_name = "wasd";
-            // add string data to prologue of function
-            write!(
-                epilogue,
-                "\n@{var} = private unnamed_addr constant [{length} x i32] [{arr}], align 4\n",
-                var = string_name,
-                length = str.len(),
-                arr = string_to_i32_arr(&str)
-            )
-            .unwrap();
-
-            // insert the string code inline
-            tmp = string_llvm_ir(&str, string_name);
-            &tmp
+            tmp = string_llvm_ir(&str);
+            tmp.trim_start()
         } // stuff between speech marks
-        (true, OperatorSymbol::String(_)) => "call void @print_int(i32 40300)\ncall void @unimplemented()",
+        (true, OperatorSymbol::String(_)) => {
+            tmp = format!("call void @unimplemented(i32 {})", '"' as i32);
+            &tmp
+        }
 
         // stack
         (false, OperatorSymbol::PushZero) => "call void @bf_PushZero()",
@@ -518,10 +691,16 @@ fn operator_to_llvm_ir(str: &mut String, epilogue: &mut String, operator_data: O
 
         // i/o
         (false, OperatorSymbol::Write) => "call void @bf_Write()",
-        (true, OperatorSymbol::Write) => "call void @print_int(i32 40400)\ncall void @unimplemented()",
+        (true, OperatorSymbol::Write) => {
+            tmp = format!("call void @unimplemented(i32 {})", 'w' as i32);
+            &tmp
+        }
 
         (false, OperatorSymbol::Read) => "call void @bf_Read()",
-        (true, OperatorSymbol::Read) => "call void @print_int(i32 40500)\ncall void @unimplemented()",
+        (true, OperatorSymbol::Read) => {
+            tmp = format!("call void @unimplemented(i32 {})", 'r' as i32);
+            &tmp
+        }
 
         // number
         (false, OperatorSymbol::Increment) => "call void @bf_Increment()",
@@ -658,76 +837,207 @@ fn operator_to_llvm_ir(str: &mut String, epilogue: &mut String, operator_data: O
 
 const PRELUDE: &str = r#"
 ;; globals
-@int_str = private unnamed_addr constant [4 x i8] c"%d\0A\00", align 1
 @char_str = private unnamed_addr constant [3 x i8] c"%c\00", align 1
-@stack_str = private unnamed_addr constant [8 x i8] c"stack:\0A\00", align 1
-@newline_str = private unnamed_addr constant [3 x i8] c"\0A\0A\00", align 1
-@unimplemented_str = private unnamed_addr constant [15 x i8] c"unimplemented!\00", align 1
+; the operator goes between the quotes, see @unimplemented
+@unimplemented_str = private unnamed_addr global [29 x i8] c"inverse '?' is unimplemented\00", align 1
+
+;; runtime errors, see @die
+@error_str = private unnamed_addr constant [11 x i8] c"error: %s\0A\00", align 1
+@error_at_str = private unnamed_addr constant [45 x i8] c"error at row %d, column %d heading %s%s: %s\0A\00", align 1
+@inverse_str = private unnamed_addr constant [11 x i8] c" (inverse)\00", align 1
+@empty_str = private unnamed_addr constant [1 x i8] c"\00", align 1
+@north_str = private unnamed_addr constant [6 x i8] c"North\00", align 1
+@south_str = private unnamed_addr constant [6 x i8] c"South\00", align 1
+@east_str = private unnamed_addr constant [5 x i8] c"East\00", align 1
+@west_str = private unnamed_addr constant [5 x i8] c"West\00", align 1
+@main_str = private unnamed_addr constant [11 x i8] c"main:    [\00", align 1
+@control_str = private unnamed_addr constant [11 x i8] c"control: [\00", align 1
+@item_str = private unnamed_addr constant [5 x i8] c"%s%d\00", align 1
+@separator_str = private unnamed_addr constant [3 x i8] c", \00", align 1
+@end_str = private unnamed_addr constant [3 x i8] c"]\0A\00", align 1
+@underflow_str = private unnamed_addr constant [16 x i8] c"stack underflow\00", align 1
+@control_underflow_str = private unnamed_addr constant [24 x i8] c"control stack underflow\00", align 1
+@overflow_str = private unnamed_addr constant [15 x i8] c"stack overflow\00", align 1
+@control_overflow_str = private unnamed_addr constant [23 x i8] c"control stack overflow\00", align 1
+@division_str = private unnamed_addr constant [17 x i8] c"division by zero\00", align 1
+@out_of_steps_str = private unnamed_addr constant [17 x i8] c"ran out of steps\00", align 1
+@json_main_str = private unnamed_addr constant [11 x i8] c"{\22main\22: [\00", align 1
+@json_control_str = private unnamed_addr constant [13 x i8] c"\22control\22: [\00", align 1
+@json_separator_str = private unnamed_addr constant [4 x i8] c"], \00", align 1
+@json_end_str = private unnamed_addr constant [4 x i8] c"]}\0A\00", align 1
+@direction_names = private unnamed_addr constant [4 x i8*] [i8* getelementptr inbounds ([6 x i8], [6 x i8]* @north_str, i64 0, i64 0), i8* getelementptr inbounds ([6 x i8], [6 x i8]* @south_str, i64 0, i64 0), i8* getelementptr inbounds ([5 x i8], [5 x i8]* @east_str, i64 0, i64 0), i8* getelementptr inbounds ([5 x i8], [5 x i8]* @west_str, i64 0, i64 0)]
 
 declare dso_local i32 @printf(i8*, ...) #1
 ;declare dso_local i32 @sleep(i32) #1
 declare dso_local void @exit(i32) #1
+declare dso_local i32 @getchar() #1
+declare dso_local i32 @dprintf(i32, i8*, ...) #1
+declare dso_local i32 @fflush(i8*) #1
 
 declare dso_local i32 @llvm.fshl.i32(i32, i32, i32) #1
 declare dso_local i32 @llvm.fshr.i32(i32, i32, i32) #1
 
+; both stacks hold STACK_CELLS (src/runtime.rs) cells
 ; offsets point at the most recent value inserted
 ; so must be incremented if you want to add
 ; but can be used directly for peek
-@primary_stack = global [40 x i32]  zeroinitializer, align 4
+@primary_stack = global [16384 x i32]  zeroinitializer, align 4
 @primary_offset = global i32 -1
 
-@control_stack = global [40 x i32]  zeroinitializer, align 4
+@control_stack = global [16384 x i32]  zeroinitializer, align 4
 @control_offset = global i32 -1
 
+; where the last operator that could fail is, set by @at. row 0 is nowhere yet
+@at_row = global i32 0
+@at_column = global i32 0
+@at_direction = global i32 0
+@at_inverse = global i32 0
+
+; operators run so far, and the most that can be under --max-steps
+@steps = global i64 0
+@max_steps = global i64 0
+
 ;; general utility functions
 
-define void @print_int(i32 %val) {
-    call i32 (i8*, ...) @printf(i8* getelementptr inbounds ([4 x i8], [4 x i8]* @int_str, i64 0, i64 0), i32 %val)
+; called by the generated code before each operator that can fail,
+; rows and columns are 1 based and directions count North, South, East, West from 0
+define void @at(i32 %row, i32 %column, i32 %direction, i32 %inverse) {
+    store i32 %row, i32* @at_row
+    store i32 %column, i32* @at_column
+    store i32 %direction, i32* @at_direction
+    store i32 %inverse, i32* @at_inverse
     ret void
 }
 
-define void @print_stack() {
-    call i32 (i8*, ...) @printf(i8* getelementptr inbounds ([8 x i8], [8 x i8]* @stack_str, i64 0, i64 0))
-    %arr = alloca [40 x i32], align 16
-    %i = alloca i32, align 4
-    store i32 0, i32* %i, align 4
-    %stack_offset = load i32, i32* @primary_offset
-    %stack_size = add i32 %stack_offset, 1
-    br label %for.cond
-
-for.cond:
-  %x = load i32, i32* %i, align 4
-  %cmp = icmp slt i32 %x, %stack_size ; 40 is length of stack
-  br i1 %cmp, label %for.body, label %for.end
-
-for.body:
-    ; print stack value at i
-    %i. = load i32, i32* %i, align 4
-    %ptr = getelementptr [40 x i32], i32* @primary_stack, i32 0, i32 %i.
-    %val = load i32, i32* %ptr
-    call void @print_int(i32 %val)
+; called by main under --max-steps
+define void @limit_steps(i64 %max) {
+    store i64 %max, i64* @max_steps
+    ret void
+}
+
+; called instead of @at before every operator but halt under --max-steps
+define void @step(i32 %row, i32 %column, i32 %direction, i32 %inverse) {
+entry:
+    call void @at(i32 %row, i32 %column, i32 %direction, i32 %inverse)
+    %steps = load i64, i64* @steps
+    %max = load i64, i64* @max_steps
+    %out = icmp eq i64 %steps, %max
+    br i1 %out, label %out_of_steps, label %count
 
-    ; increment i
-    %i.0 = load i32, i32* %i, align 4
-    %i.1 = add nsw i32 %i.0, 1
-    store i32 %i.1, i32* %i, align 4
-    br label %for.cond
+out_of_steps:
+    call void @exit_with(i8* getelementptr inbounds ([17 x i8], [17 x i8]* @out_of_steps_str, i64 0, i64 0), i32 124)
+    unreachable
 
-for.end:
-    call i32 (i8*, ...) @printf(i8* getelementptr inbounds ([3 x i8], [3 x i8]* @newline_str, i64 0, i64 0))
+count:
+    %steps.1 = add i64 %steps, 1
+    store i64 %steps.1, i64* @steps
     ret void
 }
 
-define void @debug(i32 %x) {
-    call void @print_stack()
-    call void @print_int(i32 %x)
+; name, "a, b, c" then close on stderr, bottom of the stack first
+define void @dump_stack(i8* %name, [16384 x i32]* %stack, i32 %offset, i8* %close) {
+entry:
+    call i32 (i32, i8*, ...) @dprintf(i32 2, i8* %name)
+    br label %cond
+
+cond:
+    %i = phi i32 [0, %entry], [%i.1, %body]
+    %more = icmp sle i32 %i, %offset
+    br i1 %more, label %body, label %end
+
+body:
+    %first = icmp eq i32 %i, 0
+    %separator = select i1 %first, i8* getelementptr inbounds ([1 x i8], [1 x i8]* @empty_str, i64 0, i64 0), i8* getelementptr inbounds ([3 x i8], [3 x i8]* @separator_str, i64 0, i64 0)
+    %ptr = getelementptr [16384 x i32], [16384 x i32]* %stack, i32 0, i32 %i
+    %val = load i32, i32* %ptr
+    call i32 (i32, i8*, ...) @dprintf(i32 2, i8* getelementptr inbounds ([5 x i8], [5 x i8]* @item_str, i64 0, i64 0), i8* %separator, i32 %val)
+    %i.1 = add i32 %i, 1
+    br label %cond
+
+end:
+    call i32 (i32, i8*, ...) @dprintf(i32 2, i8* %close)
+    ret void
+}
+
+; called by main under --dump-final-state once the program halts,
+; "main:    [a, b]" and "control: [c]", or {"main": [a, b], "control": [c]}
+define void @dump_final_state(i32 %json) {
+entry:
+    call i32 @fflush(i8* null)
+    %primary_offset = load i32, i32* @primary_offset
+    %control_offset = load i32, i32* @control_offset
+    %is_json = icmp ne i32 %json, 0
+    br i1 %is_json, label %as_json, label %as_text
+
+as_text:
+    call void @dump_stack(i8* getelementptr inbounds ([11 x i8], [11 x i8]* @main_str, i64 0, i64 0), [16384 x i32]* @primary_stack, i32 %primary_offset, i8* getelementptr inbounds ([3 x i8], [3 x i8]* @end_str, i64 0, i64 0))
+    call void @dump_stack(i8* getelementptr inbounds ([11 x i8], [11 x i8]* @control_str, i64 0, i64 0), [16384 x i32]* @control_stack, i32 %control_offset, i8* getelementptr inbounds ([3 x i8], [3 x i8]* @end_str, i64 0, i64 0))
+    ret void
+
+as_json:
+    call void @dump_stack(i8* getelementptr inbounds ([11 x i8], [11 x i8]* @json_main_str, i64 0, i64 0), [16384 x i32]* @primary_stack, i32 %primary_offset, i8* getelementptr inbounds ([4 x i8], [4 x i8]* @json_separator_str, i64 0, i64 0))
+    call void @dump_stack(i8* getelementptr inbounds ([13 x i8], [13 x i8]* @json_control_str, i64 0, i64 0), [16384 x i32]* @control_stack, i32 %control_offset, i8* getelementptr inbounds ([4 x i8], [4 x i8]* @json_end_str, i64 0, i64 0))
     ret void
 }
 
-define void @unimplemented() {
-    call i32 (i8*, ...) @printf(i8* getelementptr inbounds ([15 x i8], [15 x i8]* @unimplemented_str, i64 0, i64 0))
-    call void @exit(i32 1)
+; called by main under --exit-code top once the program halts
+define i32 @halt_status() {
+entry:
+    %offset = load i32, i32* @primary_offset
+    %empty = icmp slt i32 %offset, 0
+    br i1 %empty, label %zero, label %top
+
+zero:
+    ret i32 0
+
+top:
+    %ptr = getelementptr [16384 x i32], [16384 x i32]* @primary_stack, i32 0, i32 %offset
+    %val = load i32, i32* %ptr
+    ret i32 %val
+}
+
+define void @die(i8* %msg) {
+    call void @exit_with(i8* %msg, i32 1)
+    unreachable
+}
+
+; "error at row 2, column 5 heading East (inverse): msg" and both stacks on stderr
+define void @exit_with(i8* %msg, i32 %status) {
+entry:
+    call i32 @fflush(i8* null)
+    %row = load i32, i32* @at_row
+    %known = icmp ne i32 %row, 0
+    br i1 %known, label %at, label %nowhere
+
+nowhere:
+    call i32 (i32, i8*, ...) @dprintf(i32 2, i8* getelementptr inbounds ([11 x i8], [11 x i8]* @error_str, i64 0, i64 0), i8* %msg)
+    br label %stacks
+
+at:
+    %column = load i32, i32* @at_column
+    %direction = load i32, i32* @at_direction
+    %name_ptr = getelementptr [4 x i8*], [4 x i8*]* @direction_names, i32 0, i32 %direction
+    %name = load i8*, i8** %name_ptr
+    %inverse = load i32, i32* @at_inverse
+    %is_inverse = icmp ne i32 %inverse, 0
+    %mode = select i1 %is_inverse, i8* getelementptr inbounds ([11 x i8], [11 x i8]* @inverse_str, i64 0, i64 0), i8* getelementptr inbounds ([1 x i8], [1 x i8]* @empty_str, i64 0, i64 0)
+    call i32 (i32, i8*, ...) @dprintf(i32 2, i8* getelementptr inbounds ([45 x i8], [45 x i8]* @error_at_str, i64 0, i64 0), i32 %row, i32 %column, i8* %name, i8* %mode, i8* %msg)
+    br label %stacks
+
+stacks:
+    %primary_offset = load i32, i32* @primary_offset
+    call void @dump_stack(i8* getelementptr inbounds ([11 x i8], [11 x i8]* @main_str, i64 0, i64 0), [16384 x i32]* @primary_stack, i32 %primary_offset, i8* getelementptr inbounds ([3 x i8], [3 x i8]* @end_str, i64 0, i64 0))
+    %control_offset = load i32, i32* @control_offset
+    call void @dump_stack(i8* getelementptr inbounds ([11 x i8], [11 x i8]* @control_str, i64 0, i64 0), [16384 x i32]* @control_stack, i32 %control_offset, i8* getelementptr inbounds ([3 x i8], [3 x i8]* @end_str, i64 0, i64 0))
+    call void @exit(i32 %status)
+    unreachable
+}
+
+define void @unimplemented(i32 %operator) {
+    %char = trunc i32 %operator to i8
+    %quoted = getelementptr inbounds [29 x i8], [29 x i8]* @unimplemented_str, i64 0, i64 9
+    store i8 %char, i8* %quoted
+    call void @die(i8* getelementptr inbounds ([29 x i8], [29 x i8]* @unimplemented_str, i64 0, i64 0))
     unreachable
 }
 
@@ -746,25 +1056,41 @@ define void @increment_control_stack(i32 %amount) {
 }
 
 define void @push_stack(i32 %val) {
-    ; increment pointer by one
-    call void @increment_stack(i32 1)
+    ; the last cell is STACK_CELLS - 1 (src/runtime.rs)
+    %top = load i32, i32* @primary_offset
+    %full = icmp sge i32 %top, 16383
+    br i1 %full, label %overflow, label %store
 
-    ; put val onto the stack at pointer
+overflow:
+    call void @die(i8* getelementptr inbounds ([15 x i8], [15 x i8]* @overflow_str, i64 0, i64 0))
+    unreachable
+
+store:
+    ; increment pointer by one, and put val onto the stack at it
+    call void @increment_stack(i32 1)
     %offset = load i32, i32* @primary_offset
-    %ptr = getelementptr [40 x i32], i32* @primary_stack, i32 0, i32 %offset
-    store i32 %val, ptr %ptr
+    %ptr = getelementptr [16384 x i32], [16384 x i32]* @primary_stack, i32 0, i32 %offset
+    store i32 %val, i32* %ptr
 
     ret void
 }
 
 define void @push_control_stack(i32 %val) {
-    ; increment pointer by one
-    call void @increment_control_stack(i32 1)
+    ; the last cell is STACK_CELLS - 1 (src/runtime.rs)
+    %top = load i32, i32* @control_offset
+    %full = icmp sge i32 %top, 16383
+    br i1 %full, label %overflow, label %store
 
-    ; put val onto the stack at pointer
+overflow:
+    call void @die(i8* getelementptr inbounds ([23 x i8], [23 x i8]* @control_overflow_str, i64 0, i64 0))
+    unreachable
+
+store:
+    ; increment pointer by one, and put val onto the stack at it
+    call void @increment_control_stack(i32 1)
     %offset = load i32, i32* @control_offset
-    %ptr = getelementptr [40 x i32], i32* @control_stack, i32 0, i32 %offset
-    store i32 %val, ptr %ptr
+    %ptr = getelementptr [16384 x i32], [16384 x i32]* @control_stack, i32 0, i32 %offset
+    store i32 %val, i32* %ptr
 
     ret void
 }
@@ -772,7 +1098,15 @@ define void @push_control_stack(i32 %val) {
 define i32 @peek_stack(i32 %depth) {
     %offset.0 = load i32, i32* @primary_offset
     %offset.1 = sub i32 %offset.0, %depth
-    %ptr = getelementptr [40 x i32], i32* @primary_stack, i32 0, i32 %offset.1
+    %empty = icmp slt i32 %offset.1, 0
+    br i1 %empty, label %underflow, label %load
+
+underflow:
+    call void @die(i8* getelementptr inbounds ([16 x i8], [16 x i8]* @underflow_str, i64 0, i64 0))
+    unreachable
+
+load:
+    %ptr = getelementptr [16384 x i32], [16384 x i32]* @primary_stack, i32 0, i32 %offset.1
     %val = load i32, i32* %ptr
 
     ret i32 %val
@@ -781,7 +1115,15 @@ define i32 @peek_stack(i32 %depth) {
 define i32 @pop_stack() {
     ; get val from the stack at pointer
     %offset = load i32, i32* @primary_offset
-    %ptr = getelementptr [40 x i32], i32* @primary_stack, i32 0, i32 %offset
+    %empty = icmp slt i32 %offset, 0
+    br i1 %empty, label %underflow, label %load
+
+underflow:
+    call void @die(i8* getelementptr inbounds ([16 x i8], [16 x i8]* @underflow_str, i64 0, i64 0))
+    unreachable
+
+load:
+    %ptr = getelementptr [16384 x i32], [16384 x i32]* @primary_stack, i32 0, i32 %offset
     %val = load i32, i32* %ptr
 
     ; decrement pointer by one
@@ -793,7 +1135,15 @@ define i32 @pop_stack() {
 define i32 @pop_control_stack() {
     ; get val from the stack at pointer
     %offset = load i32, i32* @control_offset
-    %ptr = getelementptr [40 x i32], i32* @control_stack, i32 0, i32 %offset
+    %empty = icmp slt i32 %offset, 0
+    br i1 %empty, label %underflow, label %load
+
+underflow:
+    call void @die(i8* getelementptr inbounds ([24 x i8], [24 x i8]* @control_underflow_str, i64 0, i64 0))
+    unreachable
+
+load:
+    %ptr = getelementptr [16384 x i32], [16384 x i32]* @control_stack, i32 0, i32 %offset
     %val = load i32, i32* %ptr
 
     ; decrement pointer by one
@@ -826,7 +1176,6 @@ not_zero:
 ;; specific befreak operator impls
 
 define void @bf_Number(i32 %num) {
-    call void @debug(i32 13)
     %val.0 = call i32 @pop_stack()
     %val.1 = xor i32 %val.0, %num
     call void @push_stack(i32 %val.1)
@@ -835,33 +1184,28 @@ define void @bf_Number(i32 %num) {
 
 ; simple stack
 define void @bf_PushZero() {
-    call void @debug(i32 14)
     call void @push_stack(i32 0)
     ret void
 }
 
 define void @bf_PopZero() {
-    call void @debug(i32 15)
-    call void @pop_stack()
+    call i32 @pop_stack()
     ret void
 }
 
 define void @bf_PopMainToControl() {
-    call void @debug(i32 16)
     %1 = call i32 @pop_stack()
     call void @push_control_stack(i32 %1)
     ret void
 }
 
 define void @bf_PopControlToMain() {
-    call void @debug(i32 17)
     %1 = call i32 @pop_control_stack()
     call void @push_stack(i32 %1)
     ret void
 }
 
 define void @bf_SwapStacks() {
-    call void @debug(i32 18)
     %1 = call i32 @pop_stack()
     %2 = call i32 @pop_control_stack()
     call void @push_stack(i32 %2)
@@ -871,21 +1215,20 @@ define void @bf_SwapStacks() {
 
 ; i/o
 define void @bf_Write() {
-    call void @debug(i32 19)
     %1 = call i32 @pop_stack()
     call i32 (i8*, ...) @printf(i8* getelementptr inbounds ([3 x i8], [3 x i8]* @char_str, i64 0, i64 0), i32 %1)
     ret void
 }
 
 define void @bf_Read() {
-    call void @debug(i32 20)
-    call void @unimplemented()
+    ; -1 at the end of input
+    %1 = call i32 @getchar()
+    call void @push_stack(i32 %1)
     ret void
 }
 
 ; number
 define void @bf_Increment() {
-    call void @debug(i32 21)
     %1 = call i32 @pop_stack()
     %2 = add i32 %1, 1
     call void @push_stack(i32 %2)
@@ -893,7 +1236,6 @@ define void @bf_Increment() {
 }
 
 define void @bf_Decrement() {
-    call void @debug(i32 22)
     %1 = call i32 @pop_stack()
     %2 = sub i32 %1, 1
     call void @push_stack(i32 %2)
@@ -901,7 +1243,6 @@ define void @bf_Decrement() {
 }
 
 define void @bf_Add() {
-    call void @debug(i32 23)
     %1 = call i32 @pop_stack()
     %2 = call i32 @pop_stack()
     %sum = add i32 %2, %1
@@ -911,7 +1252,6 @@ define void @bf_Add() {
 }
 
 define void @bf_Subtract() {
-    call void @debug(i32 23)
     %1 = call i32 @pop_stack()
     %2 = call i32 @pop_stack()
     %sum = sub i32 %2, %1
@@ -920,12 +1260,37 @@ define void @bf_Subtract() {
     ret void
 }
 
+; rounds down, so the remainder takes the divisor's sign
 define void @bf_Divide() {
-    call void @debug(i32 24)
+    ; checked before popping so the dump still shows both operands
+    %divisor = call i32 @peek_stack(i32 0)
+    %zero = icmp eq i32 %divisor, 0
+    br i1 %zero, label %by_zero, label %divide
+
+by_zero:
+    call void @die(i8* getelementptr inbounds ([17 x i8], [17 x i8]* @division_str, i64 0, i64 0))
+    unreachable
+
+divide:
     %x = call i32 @pop_stack()
     %y = call i32 @pop_stack()
-    %div = sdiv i32 %y, %x
-    %rem = srem i32 %y, %x
+    ; sdiv of INT32_MIN by -1 is undefined, dividing by 1 and negating wraps back to INT32_MIN
+    %minus_one = icmp eq i32 %x, -1
+    %safe_x = select i1 %minus_one, i32 1, i32 %x
+    %quot = sdiv i32 %y, %safe_x
+    %neg_quot = sub i32 0, %quot
+    %trunc_div = select i1 %minus_one, i32 %neg_quot, i32 %quot
+    %trunc_rem = srem i32 %y, %safe_x
+    ; sdiv rounds toward zero, step down when the remainder's sign is wrong
+    %inexact = icmp ne i32 %trunc_rem, 0
+    %rem_negative = icmp slt i32 %trunc_rem, 0
+    %x_negative = icmp slt i32 %x, 0
+    %signs_differ = xor i1 %rem_negative, %x_negative
+    %floor = and i1 %inexact, %signs_differ
+    %step = zext i1 %floor to i32
+    %div = sub i32 %trunc_div, %step
+    %floor_rem = add i32 %trunc_rem, %x
+    %rem = select i1 %floor, i32 %floor_rem, i32 %trunc_rem
     call void @push_stack(i32 %div)
     call void @push_stack(i32 %rem)
     call void @push_stack(i32 %x)
@@ -933,7 +1298,6 @@ define void @bf_Divide() {
 }
 
 define void @bf_Multiply() {
-    call void @debug(i32 25)
     %x = call i32 @pop_stack()
     %rem = call i32 @pop_stack()
     %div = call i32 @pop_stack()
@@ -946,7 +1310,6 @@ define void @bf_Multiply() {
 
 ; bitwise
 define void @bf_Not() {
-    call void @debug(i32 26)
     %1 = call i32 @pop_stack()
     %2 = xor i32 %1, -1 ; the docs say this is okay :)
     call void @push_stack(i32 %2)
@@ -954,25 +1317,39 @@ define void @bf_Not() {
 }
 
 define void @bf_And() {
-    call void @debug(i32 27)
-    call void @unimplemented()
+    %x = call i32 @pop_stack()
+    %y = call i32 @pop_stack()
+    %z = call i32 @pop_stack()
+    %and = and i32 %y, %x
+    %z.1 = xor i32 %z, %and
+    call void @push_stack(i32 %z.1)
+    call void @push_stack(i32 %y)
+    call void @push_stack(i32 %x)
     ret void
 }
 
 define void @bf_Or() {
-    call void @debug(i32 28)
-    call void @unimplemented()
+    %x = call i32 @pop_stack()
+    %y = call i32 @pop_stack()
+    %z = call i32 @pop_stack()
+    %or = or i32 %y, %x
+    %z.1 = xor i32 %z, %or
+    call void @push_stack(i32 %z.1)
+    call void @push_stack(i32 %y)
+    call void @push_stack(i32 %x)
     ret void
 }
 
 define void @bf_Xor() {
-    call void @debug(i32 29)
-    call void @unimplemented()
+    %x = call i32 @pop_stack()
+    %y = call i32 @pop_stack()
+    %y.1 = xor i32 %y, %x
+    call void @push_stack(i32 %y.1)
+    call void @push_stack(i32 %x)
     ret void
 }
 
 define void @bf_RotateLeft() {
-    call void @debug(i32 30)
     %x = call i32 @pop_stack()
     %y = call i32 @pop_stack()
     %y.1 = call i32 @llvm.fshl.i32(i32 %y, i32 %y, i32 %x)
@@ -982,7 +1359,6 @@ define void @bf_RotateLeft() {
 }
 
 define void @bf_RotateRight() {
-    call void @debug(i32 31)
     %x = call i32 @pop_stack()
     %y = call i32 @pop_stack()
     %y.1 = call i32 @llvm.fshr.i32(i32 %y, i32 %y, i32 %x)
@@ -993,13 +1369,11 @@ define void @bf_RotateRight() {
 
 ; comparisons
 define void @bf_ToggleControl() {
-    call void @debug(i32 32)
-    call void @unimplemented()
+    call void @toggle_control_stack()
     ret void
 }
 
 define void @bf_EqualityCheck() {
-    call void @debug(i32 33)
     %x = call i32 @peek_stack(i32 0)
     %y = call i32 @peek_stack(i32 1)
     %cond = icmp eq i32 %y, %x
@@ -1012,7 +1386,6 @@ not_equal:
 }
 
 define void @bf_LessThanCheck() {
-    call void @debug(i32 34)
     %x = call i32 @peek_stack(i32 0)
     %y = call i32 @peek_stack(i32 1)
     %cond = icmp slt i32 %y, %x
@@ -1025,7 +1398,6 @@ not_equal:
 }
 
 define void @bf_GreaterThanCheck() {
-    call void @debug(i32 35)
     %x = call i32 @peek_stack(i32 0)
     %y = call i32 @peek_stack(i32 1)
     %cond = icmp sgt i32 %y, %x
@@ -1039,7 +1411,6 @@ not_equal:
 
 ; complex stack
 define void @bf_SwapTop() {
-    call void @debug(i32 36)
     %1 = call i32 @pop_stack()
     %2 = call i32 @pop_stack()
     call void @push_stack(i32 %1)
@@ -1048,7 +1419,6 @@ define void @bf_SwapTop() {
 }
 
 define void @bf_Dig() {
-    call void @debug(i32 37)
     %x = call i32 @pop_stack();
     %y = call i32 @pop_stack();
     %z = call i32 @pop_stack();
@@ -1059,7 +1429,6 @@ define void @bf_Dig() {
 }
 
 define void @bf_Bury() {
-    call void @debug(i32 38)
     %x = call i32 @pop_stack();
     %y = call i32 @pop_stack();
     %z = call i32 @pop_stack();
@@ -1070,13 +1439,16 @@ define void @bf_Bury() {
 }
 
 define void @bf_Flip() {
-    call void @debug(i32 39)
-    call void @unimplemented()
+    %x = call i32 @pop_stack();
+    %y = call i32 @pop_stack();
+    %z = call i32 @pop_stack();
+    call void @push_stack(i32 %x)
+    call void @push_stack(i32 %y)
+    call void @push_stack(i32 %z)
     ret void
 }
 
 define void @bf_SwapLower() {
-    call void @debug(i32 40)
     %x = call i32 @pop_stack();
     %y = call i32 @pop_stack();
     %z = call i32 @pop_stack();
@@ -1087,7 +1459,6 @@ define void @bf_SwapLower() {
 }
 
 define void @bf_Over() {
-    call void @debug(i32 41)
     %x = call i32 @pop_stack();
     %y = call i32 @pop_stack();
     call void @push_stack(i32 %y)
@@ -1097,7 +1468,6 @@ define void @bf_Over() {
 }
 
 define void @bf_Under() {
-    call void @debug(i32 42)
     %y.0 = call i32 @pop_stack();
     %x = call i32 @pop_stack();
     ; assumes y.1 = y
@@ -1109,7 +1479,6 @@ define void @bf_Under() {
 
 ; misc
 define void @bf_Duplicate() {
-    call void @debug(i32 43)
     ; assumes top two are same
     %x = call i32 @pop_stack()
     call void @push_stack(i32 %x)
@@ -1118,13 +1487,11 @@ define void @bf_Duplicate() {
 }
 
 define void @bf_Unduplicate() {
-    call void @debug(i32 44)
-    call void @pop_stack()
+    call i32 @pop_stack()
     ret void
 }
 
 define void @bf_Halt() {
-    call void @debug(i32 45)
     call void @exit(i32 0)
     unreachable
 }
@@ -1133,68 +1500,268 @@ define void @bf_Halt() {
 
 "#;
 
-fn compile(data: ExpressionTree) {
-    let mut llvm_ir = String::from(PRELUDE);
+// the stack is owned by runtime/bignum.c in bignum mode, so every helper the
+// codegen calls is external
+const BIGNUM_PRELUDE: &str = r#"
+;; runtime, see runtime/bignum.c
+declare void @unimplemented(i32)
+declare void @at(i32, i32, i32, i32)
+declare void @step(i32, i32, i32, i32)
+declare void @limit_steps(i64)
+declare void @dump_final_state(i32)
+declare i32 @halt_status()
+declare void @push_stack(i32)
+declare void @push_control_stack(i32)
+declare i1 @pop_control_stack_i1()
+declare void @toggle_control_stack()
+
+declare void @bf_Number(i32)
+declare void @bf_PushZero()
+declare void @bf_PopZero()
+declare void @bf_PopMainToControl()
+declare void @bf_PopControlToMain()
+declare void @bf_SwapStacks()
+declare void @bf_Write()
+declare void @bf_Read()
+declare void @bf_Increment()
+declare void @bf_Decrement()
+declare void @bf_Add()
+declare void @bf_Subtract()
+declare void @bf_Divide()
+declare void @bf_Multiply()
+declare void @bf_Not()
+declare void @bf_And()
+declare void @bf_Or()
+declare void @bf_Xor()
+declare void @bf_RotateLeft()
+declare void @bf_RotateRight()
+declare void @bf_ToggleControl()
+declare void @bf_EqualityCheck()
+declare void @bf_LessThanCheck()
+declare void @bf_GreaterThanCheck()
+declare void @bf_SwapTop()
+declare void @bf_Dig()
+declare void @bf_Bury()
+declare void @bf_Flip()
+declare void @bf_SwapLower()
+declare void @bf_Over()
+declare void @bf_Under()
+declare void @bf_Duplicate()
+declare void @bf_Unduplicate()
+declare void @bf_Halt()
+
+;; actual codegen begin
+
+"#;
+
+#[derive(Default)]
+struct CompileOptions {
+    // use unbounded integer cells from runtime/bignum.c instead of the i32 PRELUDE
+    bignum: bool,
+    // walking off an edge of the grid comes back on the opposite one, instead of being an error
+    wrap: bool,
+    // -g, the source file the dwarf line table points back to
+    debug_info: Option<String>,
+    // --max-steps, how many operators can run before the program is stopped
+    max_steps: Option<u64>,
+    // --exit-code
+    halt_status: HaltStatus,
+    // --dump-final-state
+    dump_final_state: Option<StateFormat>,
+}
+
+// strings in llvm metadata escape anything unprintable, and quotes, as \XX
+fn metadata_string(str: &str) -> String {
+    str.bytes().fold(String::new(), |mut acc, byte| {
+        if byte.is_ascii_graphic() && byte != b'"' && byte != b'\\' || byte == b' ' {
+            acc.push(char::from(byte));
+        } else {
+            write!(acc, "\\{byte:02X}").unwrap();
+        }
+        acc
+    })
+}
+
+// the metadata nodes for -g, a subprogram for each expression and a location for
+// each operator in it, with the grid's rows as lines and its columns as columns
+struct DebugMetadata {
+    nodes: Vec<String>,
+}
+
+impl DebugMetadata {
+    fn new(file: &str) -> Self {
+        let directory = std::env::current_dir().unwrap_or_default();
+        Self {
+            nodes: vec![
+                "distinct !DICompileUnit(language: DW_LANG_C99, file: !1, producer: \"befreak-compiler\", isOptimized: false, runtimeVersion: 0, emissionKind: FullDebug)".to_string(),
+                format!(
+                    "!DIFile(filename: \"{}\", directory: \"{}\")",
+                    metadata_string(file),
+                    metadata_string(&directory.to_string_lossy())
+                ),
+                "!DISubroutineType(types: !3)".to_string(),
+                "!{null}".to_string(),
+                "!{i32 7, !\"Dwarf Version\", i32 4}".to_string(),
+                "!{i32 2, !\"Debug Info Version\", i32 3}".to_string(),
+            ],
+        }
+    }
+
+    fn add(&mut self, node: String) -> usize {
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    fn subprogram(&mut self, identifier: &ExpressionIdentifier) -> usize {
+        let line = identifier.position.1 + 1;
+        self.add(format!(
+            "distinct !DISubprogram(name: \"{}\", scope: !1, file: !1, line: {line}, type: !2, scopeLine: {line}, spFlags: DISPFlagDefinition, unit: !0)",
+            identifier.to_codegen_symbol()
+        ))
+    }
+
+    fn location(&mut self, Position(x, y): Position, scope: usize) -> usize {
+        self.add(format!(
+            "!DILocation(line: {}, column: {}, scope: !{scope})",
+            y + 1,
+            x + 1
+        ))
+    }
+
+    fn finish(self, llvm_ir: &mut String) {
+        llvm_ir.push_str("\n\n!llvm.dbg.cu = !{!0}\n!llvm.module.flags = !{!4, !5}\n");
+        for (index, node) in self.nodes.iter().enumerate() {
+            write!(llvm_ir, "\n!{index} = {node}").unwrap();
+        }
+    }
+}
+
+// how an expression hands over to the next one, the same for the llvm builder.
+// musttail so moving between expressions doesn't grow the stack, even at -O0
+fn next_ir(next: &Branches, dbg: &str) -> String {
+    let mut ir = String::new();
+    match next {
+        Branches::None => ir.push_str("\n      ret void"),
+        Branches::One(id1) => {
+            write!(
+                ir,
+                "\n    musttail call void {}(){dbg}",
+                id1.to_codegen_function_name()
+            )
+            .unwrap();
+            ir.push_str("\n    ret void");
+        }
+        Branches::Two(id1, id2) => {
+            write!(
+                ir,
+                "\n
+    %cond = call i1 @pop_control_stack_i1(){dbg}
+    br i1 %cond, label %branch_1, label %branch_0\n",
+            )
+            .unwrap();
+            write!(
+                ir,
+                "branch_1:\n    musttail call void {}(){dbg}\n    ret void\n",
+                id1.to_codegen_function_name()
+            )
+            .unwrap();
+            write!(
+                ir,
+                "branch_0:\n    musttail call void {}(){dbg}\n    ret void",
+                id2.to_codegen_function_name()
+            )
+            .unwrap();
+        }
+    }
+    ir
+}
+
+fn compile(data: ExpressionTree, options: &CompileOptions) -> String {
+    let mut llvm_ir = String::from(if options.bignum {
+        BIGNUM_PRELUDE
+    } else {
+        PRELUDE
+    });
+    let mut debug = options.debug_info.as_deref().map(DebugMetadata::new);
     for (identifier, expression) in data.tree {
-        let mut epilogue = String::new();
         write!(
             llvm_ir,
-            "define void {}() {{",
+            "define void {}()",
             identifier.to_codegen_function_name()
         )
         .unwrap();
-        for operator in expression.arr {
-            operator_to_llvm_ir(&mut llvm_ir, &mut epilogue, operator);
+        let scope = debug.as_mut().map(|debug| debug.subprogram(&identifier));
+        if let Some(scope) = scope {
+            write!(llvm_ir, " !dbg !{scope}").unwrap();
         }
-        match expression.next {
-            Branches::None => llvm_ir.push_str("\n      ret void"),
-            Branches::One(id1) => {
+        llvm_ir.push_str(" {");
+        let mut dbg = String::new();
+        for operator in expression.arr {
+            // so runtime errors can say which operator they came from
+            let mut calls = String::new();
+            if let Some((function, [row, column, direction, inverse])) =
+                runtime::operator_location(&operator, options.max_steps.is_some())
+            {
                 write!(
-                    llvm_ir,
-                    "    call void {}()",
-                    id1.to_codegen_function_name()
+                    calls,
+                    "\n    call void @{function}(i32 {row}, i32 {column}, i32 {direction}, i32 {inverse})"
                 )
                 .unwrap();
-                llvm_ir.push_str("\n    ret void");
             }
-            Branches::Two(id1, id2) => {
-                llvm_ir.push_str(
-                    "\n
-    %cond = call i1 @pop_control_stack_i1()
-    br i1 %cond, label %branch_1, label %branch_0\n",
-                );
-                write!(
-                    llvm_ir,
-                    "branch_1:\n    call void {}()\n    ret void\n",
-                    id1.to_codegen_function_name()
-                )
-                .unwrap();
-                write!(
-                    llvm_ir,
-                    "branch_0:\n    call void {}()\n    ret void",
-                    id2.to_codegen_function_name()
-                )
-                .unwrap();
+            let Some((debug, scope)) = debug.as_mut().zip(scope) else {
+                operator_to_llvm_ir(&mut calls, operator);
+                llvm_ir.push_str(&calls);
+                continue;
+            };
+            dbg = format!(", !dbg !{}", debug.location(operator.position, scope));
+            operator_to_llvm_ir(&mut calls, operator);
+            // every call the operator makes points back at its cell
+            for (index, line) in calls.split('\n').enumerate() {
+                if index > 0 {
+                    llvm_ir.push('\n');
+                }
+                llvm_ir.push_str(line);
+                if line.trim_start().starts_with("call ") {
+                    llvm_ir.push_str(&dbg);
+                }
             }
         }
-        llvm_ir.push_str("\n}\n");
-        llvm_ir.push_str(&epilogue);
-        llvm_ir.push('\n');
+        // the calls that leave the expression belong to the operator that ends it,
+        // or the expression's first cell if it doesn't have any
+        if let Some((debug, scope)) = debug.as_mut().zip(scope).filter(|_| dbg.is_empty()) {
+            dbg = format!(", !dbg !{}", debug.location(identifier.position, scope));
+        }
+        llvm_ir.push_str(&next_ir(&expression.next, &dbg));
+        llvm_ir.push_str("\n}\n\n");
     }
 
+    llvm_ir.push_str("\n;; actual codegen over\n\ndefine i32 @main() {");
+    if let Some(max_steps) = options.max_steps {
+        write!(llvm_ir, "\n    call void @limit_steps(i64 {max_steps})").unwrap();
+    }
     write!(
         llvm_ir,
-        "
-;; actual codegen over
-
-define void @main() {{
-    call void {}()
-    ret void
-}}",
+        "\n    call void {}()",
         data.start.to_codegen_function_name()
     )
     .unwrap();
-    println!("{llvm_ir}");
+    // the start expression only returns once the program halts
+    if let Some(format) = options.dump_final_state {
+        write!(
+            llvm_ir,
+            "\n    call void @dump_final_state(i32 {})",
+            format.json()
+        )
+        .unwrap();
+    }
+    llvm_ir.push_str(match options.halt_status {
+        HaltStatus::Zero => "\n    ret i32 0\n}",
+        HaltStatus::Top => "\n    %status = call i32 @halt_status()\n    ret i32 %status\n}",
+    });
+    if let Some(debug) = debug {
+        debug.finish(&mut llvm_ir);
+    }
+    llvm_ir
 }
 
 fn read_string(data: &str) -> Array2D<char> {
@@ -1208,18 +1775,36 @@ fn read_string(data: &str) -> Array2D<char> {
     Array2D::from_rows(&lines).unwrap()
 }
 
-fn parse_code(code: &Array2D<char>) -> ExpressionTree {
-    let start_pos = get_start_pos(code).unwrap().step(Direction::East);
+fn parse_code(code: &Array2D<char>, wrap: bool) -> Result<ExpressionTree, ParseError> {
+    let start = get_start_pos(code).ok_or("no @ to start from")?;
+    let start_pos = start
+        .step(Direction::East, code, wrap)
+        .ok_or("the @ to start from is on the right edge of the grid")?;
     let mut data = ExpressionTree {
-        tree: HashMap::new(),
+        tree: BTreeMap::new(),
         start: ExpressionIdentifier {
             position: start_pos,
             direction: Direction::East,
             inverse_mode: false,
         },
+        owners: HashMap::new(),
+        warnings: vec![],
     };
-    parse_expression(code, start_pos, Direction::East, false, &mut data);
-    data
+    let mut worklist = vec![data.start.clone()];
+    while let Some(identifier) = worklist.pop() {
+        if !data.tree.contains_key(&identifier) {
+            parse_expression(
+                code,
+                identifier.position,
+                identifier.direction,
+                identifier.inverse_mode,
+                &mut data,
+                &mut worklist,
+                wrap,
+            )?;
+        }
+    }
+    Ok(data)
 }
 
 fn print_tree(tree: &ExpressionTree) {
@@ -1230,8 +1815,275 @@ fn print_tree(tree: &ExpressionTree) {
     println!("starts at {:?}", tree.start);
 }
 
+#[derive(Default)]
+enum Emit {
+    #[default]
+    Llvm,
+    // skip the llvm feature's builder even if it's available
+    LlvmText,
+    C,
+    Wat,
+    // the parsed ExpressionTree, for debugging the parser
+    Tree,
+}
+
+// --exit-code, what a program that halts exits with
+#[derive(Clone, Copy, Default)]
+enum HaltStatus {
+    #[default]
+    Zero,
+    // the top of the main stack, or 0 if it's empty
+    Top,
+}
+
+// --dump-final-state, how both stacks are written to stderr when the program halts
+#[derive(Clone, Copy)]
+enum StateFormat {
+    // "main:    [1, 2]" then "control: []", like the dump after an error
+    Text,
+    // {"main": [1, 2], "control": []}
+    Json,
+}
+
+impl StateFormat {
+    // the argument the generated code passes to dump_final_state
+    const fn json(self) -> i32 {
+        match self {
+            Self::Text => 0,
+            Self::Json => 1,
+        }
+    }
+}
+
+#[derive(Default)]
+enum Command {
+    #[default]
+    Compile,
+    // jit compile and execute immediately
+    Run,
+    // step through the program forwards and backwards
+    Debug,
+    // animate the program running over the grid
+    Tui,
+    // language server for editors, on stdin and stdout
+    Lsp,
+    // debug adapter for editors, on stdin and stdout
+    Dap,
+}
+
+struct Args {
+    command: Command,
+    options: CompileOptions,
+    emit: Emit,
+    file: Option<String>,
+    // run --profile, report how often each cell ran when the program halts
+    profile: bool,
+    // and also draw it as a page to this file
+    profile_html: Option<String>,
+}
+
+const USAGE: &str = "\
+usage: befreak-compiler [--bignum] [--wrap] [-g] [--max-steps N] [--exit-code zero|top]
+                        [--dump-final-state text|json] [--emit llvm|llvm-text|c|wat|tree] [FILE]
+       befreak-compiler run [--wrap] [--profile] [--profile-html OUT] [--max-steps N]
+                            [--exit-code zero|top] [--dump-final-state text|json] [FILE]
+       befreak-compiler debug [--wrap] [--max-steps N] [FILE]
+       befreak-compiler tui [--wrap] [FILE]
+       befreak-compiler lsp
+       befreak-compiler dap";
+
+fn usage_error(msg: &str) -> ! {
+    eprintln!("{msg}");
+    eprintln!("{USAGE}");
+    std::process::exit(2);
+}
+
+fn parse_args() -> Args {
+    let mut args = Args {
+        command: Command::default(),
+        options: CompileOptions::default(),
+        emit: Emit::default(),
+        file: None,
+        profile: false,
+        profile_html: None,
+    };
+    let mut debug_info = false;
+    let mut halt_options = false;
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--bignum" => args.options.bignum = true,
+            "--wrap" => args.options.wrap = true,
+            "-g" => debug_info = true,
+            "--emit" => {
+                args.emit = match iter.next().as_deref() {
+                    Some("llvm") => Emit::Llvm,
+                    Some("llvm-text") => Emit::LlvmText,
+                    Some("c") => Emit::C,
+                    Some("wat") => Emit::Wat,
+                    Some("tree") => Emit::Tree,
+                    Some(other) => usage_error(&format!("unknown --emit target {other}")),
+                    None => usage_error("--emit needs a target"),
+                }
+            }
+            "--profile" => args.profile = true,
+            "--exit-code" => {
+                halt_options = true;
+                args.options.halt_status = match iter.next().as_deref() {
+                    Some("zero") => HaltStatus::Zero,
+                    Some("top") => HaltStatus::Top,
+                    Some(other) => usage_error(&format!("unknown --exit-code {other}")),
+                    None => usage_error("--exit-code needs zero or top"),
+                }
+            }
+            "--dump-final-state" => {
+                halt_options = true;
+                args.options.dump_final_state = match iter.next().as_deref() {
+                    Some("text") => Some(StateFormat::Text),
+                    Some("json") => Some(StateFormat::Json),
+                    Some(other) => usage_error(&format!("unknown --dump-final-state {other}")),
+                    None => usage_error("--dump-final-state needs text or json"),
+                }
+            }
+            "--max-steps" => {
+                args.options.max_steps = match iter.next().map(|max| max.parse()) {
+                    Some(Ok(max)) => Some(max),
+                    Some(Err(_)) => usage_error("--max-steps needs a number of operators"),
+                    None => usage_error("--max-steps needs a number of operators"),
+                }
+            }
+            "--profile-html" => match iter.next() {
+                Some(out) => {
+                    args.profile = true;
+                    args.profile_html = Some(out);
+                }
+                None => usage_error("--profile-html needs a file to write to"),
+            },
+            flag if flag.starts_with("--") => usage_error(&format!("unknown option {flag}")),
+            "run" if args.file.is_none() => args.command = Command::Run,
+            "debug" if args.file.is_none() => args.command = Command::Debug,
+            "tui" if args.file.is_none() => args.command = Command::Tui,
+            "lsp" if args.file.is_none() => args.command = Command::Lsp,
+            "dap" if args.file.is_none() => args.command = Command::Dap,
+            _ => args.file = Some(arg),
+        }
+    }
+    if args.profile && !matches!(args.command, Command::Run) {
+        usage_error("--profile only applies to run");
+    }
+    if args.options.max_steps.is_some()
+        && matches!(
+            (&args.command, &args.emit),
+            (Command::Tui | Command::Lsp | Command::Dap, _) | (Command::Compile, Emit::Tree)
+        )
+    {
+        usage_error("--max-steps only applies to compiled programs, run and debug");
+    }
+    if halt_options
+        && matches!(
+            (&args.command, &args.emit),
+            (Command::Compile, Emit::Tree)
+                | (Command::Debug | Command::Tui, _)
+                | (Command::Lsp | Command::Dap, _)
+        )
+    {
+        usage_error("--exit-code and --dump-final-state only apply to compiled programs and run");
+    }
+    if debug_info {
+        if !matches!(
+            (&args.command, &args.emit),
+            (Command::Compile, Emit::Llvm | Emit::LlvmText)
+        ) {
+            usage_error("-g only applies to llvm output");
+        }
+        let file = args.file.as_deref().unwrap_or("<builtin>");
+        args.options.debug_info = Some(file.to_string());
+    }
+    args
+}
+
+#[cfg(feature = "llvm")]
+fn emit_llvm(tree: ExpressionTree, options: &CompileOptions) -> String {
+    llvm_builder::compile_llvm(tree, options).unwrap_or_else(|err| {
+        eprintln!("generated invalid llvm ir: {err}");
+        std::process::exit(1);
+    })
+}
+
+#[cfg(not(feature = "llvm"))]
+fn emit_llvm(tree: ExpressionTree, options: &CompileOptions) -> String {
+    compile(tree, options)
+}
+
+#[cfg(feature = "jit")]
+fn run(tree: ExpressionTree, code: &Array2D<char>, args: &Args) -> ! {
+    if args.options.bignum {
+        usage_error("--bignum is not supported by run");
+    }
+    let profile = args
+        .profile
+        .then(|| profile::Profile::new(&tree, code, args.options.wrap, args.profile_html.clone()));
+    std::process::exit(jit::run(tree, profile, &args.options));
+}
+
+#[cfg(not(feature = "jit"))]
+fn run(_tree: ExpressionTree, _code: &Array2D<char>, _args: &Args) -> ! {
+    eprintln!("run needs befreak-compiler to be built with the `jit` feature");
+    std::process::exit(2);
+}
+
+#[cfg(feature = "tui")]
+fn tui(tree: &ExpressionTree, code: &Array2D<char>) {
+    if let Err(err) = tui::visualize(tree, code) {
+        eprintln!("couldn't draw to the terminal: {err}");
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(feature = "tui"))]
+fn tui(_tree: &ExpressionTree, _code: &Array2D<char>) {
+    eprintln!("tui needs befreak-compiler to be built with the `tui` feature");
+    std::process::exit(2);
+}
+
+#[cfg(feature = "lsp")]
+fn lsp() {
+    if let Err(err) = lsp::serve() {
+        eprintln!("language server failed: {err}");
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(feature = "lsp"))]
+fn lsp() {
+    eprintln!("lsp needs befreak-compiler to be built with the `lsp` feature");
+    std::process::exit(2);
+}
+
+#[cfg(feature = "dap")]
+fn dap() {
+    if let Err(err) = dap::serve() {
+        eprintln!("debug adapter failed: {err}");
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(feature = "dap"))]
+fn dap() {
+    eprintln!("dap needs befreak-compiler to be built with the `dap` feature");
+    std::process::exit(2);
+}
+
 #[allow(unused_variables)]
 fn main() {
+    let args = parse_args();
+    // documents and programs come from the editor rather than a file
+    match args.command {
+        Command::Lsp => return lsp(),
+        Command::Dap => return dap(),
+        _ => {}
+    }
+
     let data = r#"
     /1)@(1\         
     >)1=1(<         
@@ -1286,12 +2138,316 @@ fn main() {
 0   c      c       8
 1   =      =       )
 %   )      (       w
-\01(^      ^)01*01(/"#;
+\01(^      ^)01*01(/
+       @"#;
     //let data = "@((123(512/";
 
+    let file;
+    let data = match &args.file {
+        Some(path) => {
+            file = std::fs::read_to_string(path).unwrap_or_else(|err| {
+                eprintln!("couldn't read {path}: {err}");
+                std::process::exit(1);
+            });
+            &file
+        }
+        None => data,
+    };
+
     let code = read_string(data);
     //println!("{code:?}");
-    let tree = parse_code(&code);
+    let tree = parse_code(&code, args.options.wrap).unwrap_or_else(|err| {
+        eprintln!("error: {err}");
+        std::process::exit(1);
+    });
+    for warning in &tree.warnings {
+        eprintln!("warning: {warning}");
+    }
     //print_tree(&tree);
-    compile(tree);
+    match args.command {
+        Command::Compile => match args.emit {
+            Emit::Llvm => println!("{}", emit_llvm(tree, &args.options)),
+            Emit::LlvmText => println!("{}", compile(tree, &args.options)),
+            Emit::C => c_backend::compile_c(tree, &args.options),
+            Emit::Wat => wat_backend::compile_wat(tree, &args.options),
+            Emit::Tree => print_tree(&tree),
+        },
+        Command::Run => run(tree, &code, &args),
+        Command::Debug => {
+            if args.options.bignum {
+                usage_error("--bignum is not supported by debug");
+            }
+            debugger::debug(&tree, &code, args.options.max_steps);
+        }
+        Command::Tui => {
+            if args.options.bignum {
+                usage_error("--bignum is not supported by tui");
+            }
+            tui(&tree, &code);
+        }
+        Command::Lsp | Command::Dap => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a column of `^` branches, each sends one side to a halt and the
+    // other down to the next branch
+    fn branch_ladder(branches: usize) -> String {
+        let mut code = String::from("@\\\n");
+        for _ in 0..branches {
+            code.push_str("@^\\\n //\n");
+        }
+        code.push_str(" @\n");
+        code
+    }
+
+    #[test]
+    fn parses_deeply_nested_branches() {
+        let branches = 50_000;
+        let data = parse_code(&read_string(&branch_ladder(branches)), false).unwrap();
+        assert_eq!(data.tree.len(), 2 * branches + 1);
+    }
+
+    #[test]
+    fn output_is_deterministic() {
+        let code = read_string(&branch_ladder(100));
+        let compile_once = || {
+            compile(
+                parse_code(&code, false).unwrap(),
+                &CompileOptions::default(),
+            )
+        };
+        assert_eq!(compile_once(), compile_once());
+    }
+
+    #[test]
+    fn walking_off_any_edge_is_an_error() {
+        for (code, edge) in [
+            ("@/", "row 1, column 2 heading North"),
+            ("@\\\n /", "row 2, column 1 heading West"),
+            ("@\\", "row 1, column 2 heading South"),
+            ("@ ", "row 1, column 2 heading East"),
+        ] {
+            let err = parse_code(&read_string(code), false).unwrap_err();
+            assert_eq!(err.to_string(), format!("walked off the grid leaving {edge}"));
+        }
+    }
+
+    #[test]
+    fn wrap_comes_back_on_the_opposite_edge() {
+        for code in ["@/", "@\\\n /", "@\\", "@ "] {
+            parse_code(&read_string(code), true).unwrap();
+        }
+    }
+
+    // what the spec says a branch operator does when entered heading a direction
+    enum Edge {
+        // which way it goes if the control stack is one or zero, in normal mode
+        Branch { one: Direction, zero: Direction },
+        // paths joining, pushes the control value that leads back here when reversed
+        Merge { exit: Direction, push: i32 },
+        // straight into the back of it, toggles the control stack and turns around inversed
+        Bounce,
+    }
+
+    fn branch_spec(branch: char, entry: Direction) -> Edge {
+        use Direction::{East, North, South, West};
+        match (branch, entry) {
+            ('>', West) => Edge::Branch {
+                one: North,
+                zero: South,
+            },
+            ('>', North) => Edge::Merge {
+                exit: East,
+                push: 1,
+            },
+            ('>', South) => Edge::Merge {
+                exit: East,
+                push: 0,
+            },
+            ('<', East) => Edge::Branch {
+                one: South,
+                zero: North,
+            },
+            ('<', North) => Edge::Merge {
+                exit: West,
+                push: 0,
+            },
+            ('<', South) => Edge::Merge {
+                exit: West,
+                push: 1,
+            },
+            ('v', North) => Edge::Branch {
+                one: East,
+                zero: West,
+            },
+            ('v', East) => Edge::Merge {
+                exit: South,
+                push: 1,
+            },
+            ('v', West) => Edge::Merge {
+                exit: South,
+                push: 0,
+            },
+            ('^', South) => Edge::Branch {
+                one: West,
+                zero: East,
+            },
+            ('^', East) => Edge::Merge {
+                exit: North,
+                push: 0,
+            },
+            ('^', West) => Edge::Merge {
+                exit: North,
+                push: 1,
+            },
+            _ => Edge::Bounce,
+        }
+    }
+
+    const BRANCHES: [char; 4] = ['>', '<', 'v', '^'];
+    const DIRECTIONS: [Direction; 4] = [
+        Direction::North,
+        Direction::South,
+        Direction::East,
+        Direction::West,
+    ];
+    const CENTER: Position = Position(2, 2);
+
+    // 5x5 with the branch in the middle, mirrors bring the ip in from @ heading
+    // entry, through a `?` if inverse. every other neighbour of the branch halts
+    fn branch_grid(branch: char, entry: Direction, inverse: bool) -> Array2D<char> {
+        let (mirrors, last_cell): (&[(usize, usize, char)], Position) = match entry {
+            Direction::East => (&[(1, 0, '\\'), (1, 2, '\\')], Position(1, 1)),
+            Direction::South => (&[(2, 0, '\\')], Position(2, 1)),
+            Direction::West => (&[(4, 0, '\\'), (4, 2, '/')], Position(3, 2)),
+            Direction::North => (&[(4, 0, '\\'), (4, 4, '/'), (2, 4, '\\')], Position(2, 3)),
+        };
+        let mut grid = Array2D::filled_with(' ', 5, 5);
+        grid[(0, 0)] = '@';
+        for dir in DIRECTIONS {
+            if dir != entry.reverse() {
+                let Position(x, y) = CENTER.step(dir, &grid, false).unwrap();
+                grid[(y, x)] = '@';
+            }
+        }
+        for &(x, y, mirror) in mirrors {
+            grid[(y, x)] = mirror;
+        }
+        if inverse {
+            grid[(last_cell.1, last_cell.0)] = '?';
+        }
+        grid[(CENTER.1, CENTER.0)] = branch;
+        grid
+    }
+
+    // the branch operator, and the state the ip is in straight after it
+    // (None if it's a real branch)
+    fn parse_branch(
+        branch: char,
+        entry: Direction,
+        inverse: bool,
+    ) -> (Operator, Option<ExpressionIdentifier>, Branches) {
+        let tree = parse_code(&branch_grid(branch, entry, inverse), false).unwrap();
+        for expression in tree.tree.into_values() {
+            let Some(index) = expression
+                .arr
+                .iter()
+                .position(|op| op.position == CENTER && op.in_direction == entry)
+            else {
+                continue;
+            };
+            let after = expression
+                .arr
+                .get(index + 1)
+                .map(|op| ExpressionIdentifier::new(op.inverse, op.position, op.in_direction));
+            let operator = expression.arr[index].clone();
+            return (operator, after, expression.next);
+        }
+        panic!("{branch} heading {entry:?} never reached the branch");
+    }
+
+    fn control_after(operator: &Operator, control: &[i32]) -> Vec<i32> {
+        let mut runtime = runtime::Runtime::new();
+        runtime.control = control.to_vec();
+        for call in runtime::operator_to_calls(operator) {
+            runtime.execute(call).unwrap();
+        }
+        runtime.control
+    }
+
+    #[test]
+    fn branch_edges() {
+        for branch in BRANCHES {
+            for entry in DIRECTIONS {
+                for inverse in [false, true] {
+                    let case = format!("{branch} heading {entry:?}, inverse {inverse}");
+                    let (operator, after, next) = parse_branch(branch, entry, inverse);
+                    assert_eq!(operator.inverse, inverse, "{case}");
+                    let target = |dir: Direction, inverse_mode| {
+                        let position =
+                            CENTER.step(dir, &branch_grid(branch, entry, inverse), false);
+                        ExpressionIdentifier::new(inverse_mode, position.unwrap(), dir)
+                    };
+                    match branch_spec(branch, entry) {
+                        Edge::Branch { one, zero } => {
+                            // inverse mode swaps which way a one goes
+                            let (one, zero) = if inverse { (zero, one) } else { (one, zero) };
+                            assert!(after.is_none(), "{case}");
+                            let Branches::Two(taken_on_one, taken_on_zero) = next else {
+                                panic!("{case} doesn't branch: {next:?}");
+                            };
+                            assert_eq!(taken_on_one, target(one, inverse), "{case}");
+                            assert_eq!(taken_on_zero, target(zero, inverse), "{case}");
+                            assert!(control_after(&operator, &[]).is_empty(), "{case}");
+                        }
+                        Edge::Merge { exit, push } => {
+                            assert_eq!(after, Some(target(exit, inverse)), "{case}");
+                            let push = if inverse { 1 - push } else { push };
+                            assert_eq!(control_after(&operator, &[]), [push], "{case}");
+                        }
+                        Edge::Bounce => {
+                            assert_eq!(after, Some(target(entry.reverse(), !inverse)), "{case}");
+                            assert_eq!(control_after(&operator, &[0]), [1], "{case}");
+                            assert_eq!(control_after(&operator, &[1]), [0], "{case}");
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // reversibility: after a merge, running backwards into the same branch
+    // with the value it pushed has to lead back out the way the ip came in
+    #[test]
+    fn merges_reverse_into_branches() {
+        for branch in BRANCHES {
+            for entry in DIRECTIONS {
+                for inverse in [false, true] {
+                    let Edge::Merge { exit, .. } = branch_spec(branch, entry) else {
+                        continue;
+                    };
+                    let case = format!("{branch} heading {entry:?}, inverse {inverse}");
+                    let (operator, ..) = parse_branch(branch, entry, inverse);
+                    let [pushed] = control_after(&operator, &[])[..] else {
+                        panic!("{case} didn't push one value");
+                    };
+
+                    let (.., next) = parse_branch(branch, exit.reverse(), !inverse);
+                    let Branches::Two(taken_on_one, taken_on_zero) = next else {
+                        panic!("{case} reversed doesn't branch: {next:?}");
+                    };
+                    let taken = if pushed != 0 {
+                        taken_on_one
+                    } else {
+                        taken_on_zero
+                    };
+                    assert_eq!(taken.direction, entry.reverse(), "{case}");
+                }
+            }
+        }
+    }
 }
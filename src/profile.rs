@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use array2d::Array2D;
+
+use crate::runtime::operator_location;
+use crate::{get_char, Direction, ExpressionTree, Operator, OperatorSymbol, Position};
+
+// how many of the hottest cells the report lists
+const HOTTEST: usize = 10;
+// 256 colour backgrounds from cold to hot
+const PALETTE: [u8; 8] = [21, 27, 33, 39, 48, 226, 208, 196];
+
+// counters for run --profile, one per expression, bumped every time it's entered.
+// every operator in an expression runs as often as the expression does, apart
+// from the end of the last one if the program stopped partway through it
+#[derive(Debug)]
+pub struct Profile {
+    pub counters: Vec<u64>,
+    // the index of the expression entered last, written on the way in too
+    pub last: u64,
+    // the operators of each expression, in the tree's order, and the cells each one covers
+    expressions: Vec<Vec<(Operator, Vec<Position>)>>,
+    // how many operators of the last expression ran before the program stopped, see stop
+    stopped: Option<usize>,
+    code: Array2D<char>,
+    // --profile-html
+    html: Option<String>,
+}
+
+impl Profile {
+    pub fn new(
+        tree: &ExpressionTree,
+        code: &Array2D<char>,
+        wrap: bool,
+        html: Option<String>,
+    ) -> Self {
+        let expressions: Vec<_> = tree
+            .tree
+            .values()
+            .map(|expression| {
+                expression
+                    .arr
+                    .iter()
+                    .map(|operator| (operator.clone(), operator.cells(code, wrap)))
+                    .collect()
+            })
+            .collect();
+        Self {
+            counters: vec![0; expressions.len()],
+            last: 0,
+            expressions,
+            stopped: None,
+            code: code.clone(),
+            html,
+        }
+    }
+
+    // the report and heatmap on stderr, after anything the program printed
+    pub fn finish(&self) {
+        eprint!("{}{}", self.report(), self.ansi());
+        if let Some(out) = &self.html {
+            if let Err(err) = std::fs::write(out, self.html()) {
+                eprintln!("couldn't write {out}: {err}");
+            }
+        }
+    }
+
+    // the program died or ran out of steps at `at`, the last operator that
+    // could fail, so neither it nor the rest of the last expression ran that time
+    pub fn stop(&mut self, at: Option<(Position, Direction, bool)>) {
+        let Some(at) = at else {
+            return;
+        };
+        let operators = &self.expressions[usize::try_from(self.last).unwrap()];
+        self.stopped = operators.iter().position(|(operator, _)| {
+            (operator.position, operator.in_direction, operator.inverse) == at
+        });
+    }
+
+    // each operator that does something, the cells it covers and how many
+    // times it ran. blanks, mirrors, ? and halts are left out
+    fn runs(&self) -> impl Iterator<Item = (&OperatorSymbol, &[Position], u64)> {
+        let last = usize::try_from(self.last).unwrap();
+        self.counters
+            .iter()
+            .zip(&self.expressions)
+            .enumerate()
+            .flat_map(move |(index, (count, operators))| {
+                operators
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, (operator, _))| operator_location(operator, false).is_some())
+                    .map(move |(position, (operator, cells))| {
+                        let unfinished = index == last
+                            && self.stopped.is_some_and(|stopped| position >= stopped);
+                        (
+                            &operator.operation,
+                            &cells[..],
+                            count - u64::from(unfinished),
+                        )
+                    })
+            })
+    }
+
+    // operators executed in total
+    pub fn executed(&self) -> u64 {
+        self.runs().map(|(_, _, count)| count).sum()
+    }
+
+    // how many times each cell was executed, whichever way it was crossed
+    pub fn heat(&self) -> HashMap<Position, u64> {
+        let mut heat = HashMap::new();
+        for (_, cells, count) in self.runs() {
+            for cell in cells {
+                *heat.entry(*cell).or_default() += count;
+            }
+        }
+        heat
+    }
+
+    pub fn report(&self) -> String {
+        let entered = self.counters.iter().filter(|count| **count > 0).count();
+        let mut report = format!(
+            "executed {} operators, {entered} of {} expressions were reached\n",
+            self.executed(),
+            self.counters.len()
+        );
+        // the operator that starts in a cell, rather than the middle of a string
+        let mut operations = HashMap::new();
+        for (operation, cells, _) in self.runs() {
+            operations.entry(cells[0]).or_insert(operation);
+        }
+        let mut hottest: Vec<_> = self
+            .heat()
+            .into_iter()
+            .filter(|(cell, count)| *count > 0 && operations.contains_key(cell))
+            .collect();
+        hottest.sort_by_key(|&(Position(x, y), count)| (std::cmp::Reverse(count), y, x));
+        report.push_str("hottest cells:\n");
+        for (cell, count) in hottest.into_iter().take(HOTTEST) {
+            writeln!(
+                report,
+                "  row {}, column {}: {count} times, {:?} {:?}",
+                cell.1 + 1,
+                cell.0 + 1,
+                get_char(&self.code, cell).unwrap(),
+                operations[&cell]
+            )
+            .unwrap();
+        }
+        report
+    }
+
+    // the grid with each cell's background coloured by how often it ran
+    pub fn ansi(&self) -> String {
+        let heat = self.heat();
+        let max = heat.values().copied().max().unwrap_or(0);
+        let mut grid = String::new();
+        for (y, row) in self.code.rows_iter().enumerate() {
+            for (x, char) in row.enumerate() {
+                match heat.get(&Position(x, y)) {
+                    Some(&count) if count > 0 => {
+                        let colour = PALETTE[shade(count, max, PALETTE.len())];
+                        write!(grid, "\x1b[30;48;5;{colour}m{char}\x1b[0m").unwrap();
+                    }
+                    // never ran, dimmed
+                    _ => write!(grid, "\x1b[2m{char}\x1b[0m").unwrap(),
+                }
+            }
+            grid.push('\n');
+        }
+        grid
+    }
+
+    // the same, as a standalone page with the counts in each cell's tooltip
+    pub fn html(&self) -> String {
+        let heat = self.heat();
+        let max = heat.values().copied().max().unwrap_or(0);
+        let mut page = String::from(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>befreak profile</title>\n\
+             <style>pre { line-height: 1.2; } span.cold { color: #999; }</style>\n</head>\n<body>\n<pre>",
+        );
+        for (y, row) in self.code.rows_iter().enumerate() {
+            for (x, char) in row.enumerate() {
+                let char = match char {
+                    '<' => "&lt;".to_string(),
+                    '>' => "&gt;".to_string(),
+                    '&' => "&amp;".to_string(),
+                    char => char.to_string(),
+                };
+                match heat.get(&Position(x, y)) {
+                    Some(&count) if count > 0 => {
+                        // blue through to red
+                        let hue = 240 - 240 * shade(count, max, 241) / 240;
+                        write!(
+                            page,
+                            "<span style=\"background: hsl({hue}, 100%, 60%)\" title=\"row {}, column {}: {count}\">{char}</span>",
+                            y + 1,
+                            x + 1
+                        )
+                        .unwrap();
+                    }
+                    _ => write!(page, "<span class=\"cold\">{char}</span>").unwrap(),
+                }
+            }
+            page.push('\n');
+        }
+        writeln!(
+            page,
+            "</pre>\n<p>{} operators executed</p>\n</body>\n</html>",
+            self.executed()
+        )
+        .unwrap();
+        page
+    }
+}
+
+// which of `shades` steps a count falls on, on a log scale since loops
+// run orders of magnitude more often than the code around them
+#[allow(
+    clippy::cast_precision_loss,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss
+)]
+fn shade(count: u64, max: u64, shades: usize) -> usize {
+    if max <= 1 {
+        return shades - 1;
+    }
+    let fraction = (count as f64).ln() / (max as f64).ln();
+    (fraction * (shades - 1) as f64).round() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_code, read_string};
+
+    #[test]
+    fn counts_every_cell_an_expression_covers() {
+        let code = read_string(include_str!("../tests/fixtures/hello.bf"));
+        let tree = parse_code(&code, false).unwrap();
+        let mut profile = Profile::new(&tree, &code, false, None);
+        let start = tree.tree.keys().position(|id| *id == tree.start).unwrap();
+        profile.counters[start] = 3;
+
+        let heat = profile.heat();
+        // the @ is skipped, the first cell after it runs as often as its expression
+        assert_eq!(heat[&tree.start.position], 3);
+        // the string is one operator and the mirrors on the way don't count
+        assert_eq!(profile.executed(), 3 * 6);
+        assert!(!heat.contains_key(&Position(17, 0)));
+        let report = profile.report();
+        assert!(report.starts_with(&format!(
+            "executed {} operators, 1 of {} expressions were reached\n",
+            profile.executed(),
+            tree.tree.len()
+        )));
+    }
+
+    #[test]
+    fn stopping_drops_the_rest_of_the_last_expression() {
+        let code = read_string(include_str!("../tests/fixtures/hello.bf"));
+        let tree = parse_code(&code, false).unwrap();
+        let mut profile = Profile::new(&tree, &code, false, None);
+        let start = tree.tree.keys().position(|id| *id == tree.start).unwrap();
+        profile.counters[start] = 3;
+        profile.last = start as u64;
+        // died at the ( after the string, the third time round
+        profile.stop(Some((Position(1, 1), Direction::East, false)));
+        assert_eq!(profile.executed(), 3 * 6 - 3);
+        assert_eq!(profile.heat()[&Position(1, 1)], 2);
+        assert_eq!(profile.heat()[&tree.start.position], 3);
+    }
+
+    #[test]
+    fn shades_on_a_log_scale() {
+        assert_eq!(shade(1, 1000, 8), 0);
+        assert_eq!(shade(1000, 1000, 8), 7);
+        assert_eq!(shade(31, 1000, 8), 3);
+        assert_eq!(shade(1, 1, 8), 7);
+    }
+}
@@ -0,0 +1,1304 @@
+use std::collections::VecDeque;
+use std::io::{ErrorKind, Read, Write};
+
+use crate::profile::Profile;
+use crate::{location, Direction, HaltStatus, Operator, OperatorSymbol, Position, StateFormat};
+
+// a call into the runtime, one per function the generated code can call.
+// mirrors the LLVM PRELUDE and runtime/befreak.c
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Call {
+    // an operator with no inverse yet, by its character
+    Unimplemented(i32),
+    PushStack(i32),
+    PushControlStack(i32),
+    ToggleControlStack,
+
+    Number(i32),
+    PushZero,
+    PopZero,
+    PopMainToControl,
+    PopControlToMain,
+    SwapStacks,
+    Write,
+    Read,
+    Increment,
+    Decrement,
+    Add,
+    Subtract,
+    Divide,
+    Multiply,
+    Not,
+    And,
+    Or,
+    Xor,
+    RotateLeft,
+    RotateRight,
+    ToggleControl,
+    EqualityCheck,
+    LessThanCheck,
+    GreaterThanCheck,
+    SwapTop,
+    Dig,
+    Bury,
+    Flip,
+    SwapLower,
+    Over,
+    Under,
+    Duplicate,
+    Unduplicate,
+}
+
+impl Call {
+    // the runtime function implementing this call, and its argument if it takes one
+    pub const fn symbol(self) -> (&'static str, Option<i32>) {
+        match self {
+            Call::Unimplemented(operator) => ("unimplemented", Some(operator)),
+            Call::PushStack(val) => ("push_stack", Some(val)),
+            Call::PushControlStack(val) => ("push_control_stack", Some(val)),
+            Call::ToggleControlStack => ("toggle_control_stack", None),
+
+            Call::Number(num) => ("bf_Number", Some(num)),
+            Call::PushZero => ("bf_PushZero", None),
+            Call::PopZero => ("bf_PopZero", None),
+            Call::PopMainToControl => ("bf_PopMainToControl", None),
+            Call::PopControlToMain => ("bf_PopControlToMain", None),
+            Call::SwapStacks => ("bf_SwapStacks", None),
+            Call::Write => ("bf_Write", None),
+            Call::Read => ("bf_Read", None),
+            Call::Increment => ("bf_Increment", None),
+            Call::Decrement => ("bf_Decrement", None),
+            Call::Add => ("bf_Add", None),
+            Call::Subtract => ("bf_Subtract", None),
+            Call::Divide => ("bf_Divide", None),
+            Call::Multiply => ("bf_Multiply", None),
+            Call::Not => ("bf_Not", None),
+            Call::And => ("bf_And", None),
+            Call::Or => ("bf_Or", None),
+            Call::Xor => ("bf_Xor", None),
+            Call::RotateLeft => ("bf_RotateLeft", None),
+            Call::RotateRight => ("bf_RotateRight", None),
+            Call::ToggleControl => ("bf_ToggleControl", None),
+            Call::EqualityCheck => ("bf_EqualityCheck", None),
+            Call::LessThanCheck => ("bf_LessThanCheck", None),
+            Call::GreaterThanCheck => ("bf_GreaterThanCheck", None),
+            Call::SwapTop => ("bf_SwapTop", None),
+            Call::Dig => ("bf_Dig", None),
+            Call::Bury => ("bf_Bury", None),
+            Call::Flip => ("bf_Flip", None),
+            Call::SwapLower => ("bf_SwapLower", None),
+            Call::Over => ("bf_Over", None),
+            Call::Under => ("bf_Under", None),
+            Call::Duplicate => ("bf_Duplicate", None),
+            Call::Unduplicate => ("bf_Unduplicate", None),
+        }
+    }
+}
+
+// --max-steps ran out. exits with the status timeout(1) uses, so it can't be
+// mistaken for the program failing or halting
+pub const OUT_OF_STEPS: &str = "ran out of steps";
+pub const OUT_OF_STEPS_STATUS: i32 = 124;
+
+// stdout was closed, e.g. `run prog.bf | head`. the compiled backends are
+// killed by SIGPIPE then, and a shell reports that as 128 + 13
+const BROKEN_PIPE_STATUS: i32 = 141;
+
+// how many cells each stack holds in every backend, pushing past it is a
+// "stack overflow" or "control stack overflow" error. the runtimes under
+// runtime/ and the PRELUDE write the same number out
+pub const STACK_CELLS: usize = 16384;
+
+// where Write and Read go. the interpreter keeps them in memory so it can
+// step back over them
+#[derive(Debug)]
+pub enum Io {
+    Std,
+    Buffered {
+        input: VecDeque<u8>,
+        output: Vec<u8>,
+    },
+}
+
+#[derive(Debug)]
+pub struct Runtime {
+    pub primary: Vec<i32>,
+    pub control: Vec<i32>,
+    pub io: Io,
+    // run --profile, written out however the program exits
+    pub profile: Option<Profile>,
+    // the last operator that could fail, set by at() in the generated code
+    pub at: Option<(Position, Direction, bool)>,
+    // operators run so far and --max-steps, only counted when there's a limit
+    pub steps: u64,
+    pub max_steps: Option<u64>,
+}
+
+impl Runtime {
+    pub const fn new() -> Self {
+        Self {
+            primary: vec![],
+            control: vec![],
+            io: Io::Std,
+            profile: None,
+            at: None,
+            steps: 0,
+            max_steps: None,
+        }
+    }
+
+    pub const fn buffered() -> Self {
+        Self {
+            primary: vec![],
+            control: vec![],
+            io: Io::Buffered {
+                input: VecDeque::new(),
+                output: vec![],
+            },
+            profile: None,
+            at: None,
+            steps: 0,
+            max_steps: None,
+        }
+    }
+
+    // what the generated code prints when it fails: where, what, and both stacks
+    pub fn error(&self, msg: &str) -> String {
+        let at = match self.at {
+            Some((position, direction, inverse)) => format!(
+                " at {}{}",
+                location(position, direction),
+                if inverse { " (inverse)" } else { "" }
+            ),
+            None => String::new(),
+        };
+        format!(
+            "error{at}: {msg}\nmain:    {:?}\ncontrol: {:?}",
+            self.primary, self.control
+        )
+    }
+
+    pub fn die(&mut self, msg: &str) -> ! {
+        self.exit(msg, 1)
+    }
+
+    pub fn exit(&mut self, msg: &str, status: i32) -> ! {
+        // the error still has to be printed if stdout is gone
+        let _ = std::io::stdout().flush();
+        eprintln!("{}", self.error(msg));
+        self.quit(status)
+    }
+
+    fn quit(&mut self, status: i32) -> ! {
+        if let Some(profile) = &mut self.profile {
+            profile.stop(self.at);
+            profile.finish();
+        }
+        std::process::exit(status);
+    }
+
+    // output is flushed straight away so it interleaves with reads
+    fn print(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let mut stdout = std::io::stdout();
+        match stdout.write_all(bytes).and_then(|()| stdout.flush()) {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == ErrorKind::BrokenPipe => self.quit(BROKEN_PIPE_STATUS),
+            Err(err) => Err(format!("couldn't write to stdout: {err}")),
+        }
+    }
+
+    // --dump-final-state, both stacks once the program halts
+    pub fn final_state(&self, format: StateFormat) -> String {
+        match format {
+            StateFormat::Text => {
+                format!("main:    {:?}\ncontrol: {:?}", self.primary, self.control)
+            }
+            StateFormat::Json => format!(
+                "{{\"main\": {:?}, \"control\": {:?}}}",
+                self.primary, self.control
+            ),
+        }
+    }
+
+    // what the program exits with once it halts
+    pub fn halt_status(&self, status: HaltStatus) -> i32 {
+        match status {
+            HaltStatus::Zero => 0,
+            HaltStatus::Top => self.primary.last().copied().unwrap_or(0),
+        }
+    }
+
+    // called before every operator under --max-steps
+    pub fn step(&mut self) -> Result<(), String> {
+        if self.max_steps == Some(self.steps) {
+            return Err(OUT_OF_STEPS.to_string());
+        }
+        self.steps += 1;
+        Ok(())
+    }
+
+    pub fn push(&mut self, val: i32) -> Result<(), String> {
+        if self.primary.len() == STACK_CELLS {
+            return Err("stack overflow".to_string());
+        }
+        self.primary.push(val);
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Result<i32, String> {
+        self.primary
+            .pop()
+            .ok_or_else(|| "stack underflow".to_string())
+    }
+
+    fn peek(&self, depth: usize) -> Result<i32, String> {
+        match self.primary.len().checked_sub(depth + 1) {
+            Some(index) => Ok(self.primary[index]),
+            None => Err("stack underflow".to_string()),
+        }
+    }
+
+    pub fn push_control(&mut self, val: i32) -> Result<(), String> {
+        if self.control.len() == STACK_CELLS {
+            return Err("control stack overflow".to_string());
+        }
+        self.control.push(val);
+        Ok(())
+    }
+
+    pub fn pop_control(&mut self) -> Result<i32, String> {
+        self.control
+            .pop()
+            .ok_or_else(|| "control stack underflow".to_string())
+    }
+
+    // zero = zero, everything else = 1
+    pub fn pop_control_stack_i1(&mut self) -> Result<bool, String> {
+        Ok(self.pop_control()? != 0)
+    }
+
+    pub fn toggle_control(&mut self) -> Result<(), String> {
+        let val = self.pop_control()?;
+        self.push_control(i32::from(val == 0))?;
+        Ok(())
+    }
+
+    fn write(&mut self, val: i32) -> Result<(), String> {
+        #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+        let byte = val as u8;
+        match &mut self.io {
+            Io::Std => self.print(&[byte])?,
+            Io::Buffered { output, .. } => output.push(byte),
+        }
+        Ok(())
+    }
+
+    fn read(&mut self) -> i32 {
+        match &mut self.io {
+            Io::Std => {
+                let mut buf = [0];
+                match std::io::stdin().read(&mut buf) {
+                    Ok(1) => i32::from(buf[0]),
+                    _ => -1,
+                }
+            }
+            Io::Buffered { input, .. } => input.pop_front().map_or(-1, i32::from),
+        }
+    }
+
+    #[allow(clippy::too_many_lines)]
+    pub fn execute(&mut self, call: Call) -> Result<(), String> {
+        match call {
+            Call::Unimplemented(operator) => return Err(unimplemented(operator)),
+            Call::PushStack(val) => self.push(val)?,
+            Call::PushControlStack(val) => self.push_control(val)?,
+            Call::ToggleControlStack | Call::ToggleControl => self.toggle_control()?,
+
+            Call::Number(num) => {
+                let x = self.pop()?;
+                self.push(x ^ num)?;
+            }
+
+            // simple stack
+            Call::PushZero => self.push(0)?,
+            Call::PopZero | Call::Under | Call::Unduplicate => {
+                self.pop()?;
+            }
+            Call::PopMainToControl => {
+                let x = self.pop()?;
+                self.push_control(x)?;
+            }
+            Call::PopControlToMain => {
+                let x = self.pop_control()?;
+                self.push(x)?;
+            }
+            Call::SwapStacks => {
+                let x = self.pop()?;
+                let y = self.pop_control()?;
+                self.push(y)?;
+                self.push_control(x)?;
+            }
+
+            // i/o
+            Call::Write => {
+                let x = self.pop()?;
+                self.write(x)?;
+            }
+            Call::Read => {
+                let x = self.read();
+                self.push(x)?;
+            }
+
+            // number
+            Call::Increment => {
+                let x = self.pop()?;
+                self.push(x.wrapping_add(1))?;
+            }
+            Call::Decrement => {
+                let x = self.pop()?;
+                self.push(x.wrapping_sub(1))?;
+            }
+            Call::Add => {
+                let x = self.pop()?;
+                let y = self.pop()?;
+                self.push(y.wrapping_add(x))?;
+                self.push(x)?;
+            }
+            Call::Subtract => {
+                let x = self.pop()?;
+                let y = self.pop()?;
+                self.push(y.wrapping_sub(x))?;
+                self.push(x)?;
+            }
+            Call::Divide => {
+                // checked before popping so the dump still shows both operands
+                if self.peek(0)? == 0 {
+                    return Err("division by zero".to_string());
+                }
+                let x = self.pop()?;
+                let y = self.pop()?;
+                let (div, rem) = floor_div_rem(y, x);
+                self.push(div)?;
+                self.push(rem)?;
+                self.push(x)?;
+            }
+            Call::Multiply => {
+                let x = self.pop()?;
+                let rem = self.pop()?;
+                let div = self.pop()?;
+                self.push(x.wrapping_mul(div).wrapping_add(rem))?;
+                self.push(x)?;
+            }
+
+            // bitwise
+            Call::Not => {
+                let x = self.pop()?;
+                self.push(!x)?;
+            }
+            Call::And | Call::Or => {
+                let x = self.pop()?;
+                let y = self.pop()?;
+                let z = self.pop()?;
+                self.push(z ^ if call == Call::And { y & x } else { y | x })?;
+                self.push(y)?;
+                self.push(x)?;
+            }
+            Call::Xor => {
+                let x = self.pop()?;
+                let y = self.pop()?;
+                self.push(y ^ x)?;
+                self.push(x)?;
+            }
+            // rotates are mod 32, like llvm.fshl/llvm.fshr
+            #[allow(clippy::cast_sign_loss)]
+            Call::RotateLeft => {
+                let x = self.pop()?;
+                let y = self.pop()?;
+                self.push(y.rotate_left(x as u32 % 32))?;
+                self.push(x)?;
+            }
+            #[allow(clippy::cast_sign_loss)]
+            Call::RotateRight => {
+                let x = self.pop()?;
+                let y = self.pop()?;
+                self.push(y.rotate_right(x as u32 % 32))?;
+                self.push(x)?;
+            }
+
+            // comparisons
+            Call::EqualityCheck => {
+                if self.peek(1)? == self.peek(0)? {
+                    self.toggle_control()?;
+                }
+            }
+            Call::LessThanCheck => {
+                if self.peek(1)? < self.peek(0)? {
+                    self.toggle_control()?;
+                }
+            }
+            Call::GreaterThanCheck => {
+                if self.peek(1)? > self.peek(0)? {
+                    self.toggle_control()?;
+                }
+            }
+
+            // complex stack
+            Call::SwapTop => {
+                let x = self.pop()?;
+                let y = self.pop()?;
+                self.push(x)?;
+                self.push(y)?;
+            }
+            Call::Dig => {
+                let x = self.pop()?;
+                let y = self.pop()?;
+                let z = self.pop()?;
+                self.push(y)?;
+                self.push(x)?;
+                self.push(z)?;
+            }
+            Call::Bury => {
+                let x = self.pop()?;
+                let y = self.pop()?;
+                let z = self.pop()?;
+                self.push(x)?;
+                self.push(z)?;
+                self.push(y)?;
+            }
+            Call::Flip => {
+                let x = self.pop()?;
+                let y = self.pop()?;
+                let z = self.pop()?;
+                self.push(x)?;
+                self.push(y)?;
+                self.push(z)?;
+            }
+            Call::SwapLower => {
+                let x = self.pop()?;
+                let y = self.pop()?;
+                let z = self.pop()?;
+                self.push(y)?;
+                self.push(z)?;
+                self.push(x)?;
+            }
+            Call::Over => {
+                let y = self.peek(1)?;
+                self.push(y)?;
+            }
+
+            // misc
+            Call::Duplicate => {
+                let x = self.peek(0)?;
+                self.push(x)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// what every runtime says when an operator's inverse isn't implemented
+fn unimplemented(operator: i32) -> String {
+    let operator = u32::try_from(operator)
+        .ok()
+        .and_then(char::from_u32)
+        .unwrap_or('?');
+    format!("inverse '{operator}' is unimplemented")
+}
+
+// floor division, the remainder takes the divisor's sign so Multiply's
+// x*div+rem gets y back whatever the signs. INT32_MIN / -1 wraps like the
+// other arithmetic
+const fn floor_div_rem(y: i32, x: i32) -> (i32, i32) {
+    let div = y.wrapping_div(x);
+    let rem = y.wrapping_rem(x);
+    if rem != 0 && (rem < 0) != (x < 0) {
+        (div - 1, rem + x)
+    } else {
+        (div, rem)
+    }
+}
+
+// the call the generated code makes before an operator so runtime errors can
+// say where they happened: at(row, column, direction, inverse), or under
+// --max-steps step(...) which also counts it. rows and columns are 1 based and
+// directions count North, South, East, West from 0.
+// None if there's nothing to count and the operator can't fail, there's no
+// need to move the location then
+#[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+pub fn operator_location(
+    operator: &Operator,
+    count_steps: bool,
+) -> Option<(&'static str, [i32; 4])> {
+    // branches can leave the expression by popping the control stack
+    let branch = matches!(
+        operator.operation,
+        OperatorSymbol::EastBranch
+            | OperatorSymbol::WestBranch
+            | OperatorSymbol::SouthBranch
+            | OperatorSymbol::NorthBranch
+    );
+    // halting isn't a step, the interpreter stops in front of it
+    let function = if count_steps && !matches!(operator.operation, OperatorSymbol::Halt) {
+        "step"
+    } else if branch || !operator_to_calls(operator).is_empty() {
+        "at"
+    } else {
+        return None;
+    };
+    let Position(x, y) = operator.position;
+    Some((
+        function,
+        [
+            y as i32 + 1,
+            x as i32 + 1,
+            operator.in_direction as i32,
+            i32::from(operator.inverse),
+        ],
+    ))
+}
+
+// the calls the generated code makes for one operator, mirrors operator_to_llvm_ir
+#[allow(clippy::match_same_arms, clippy::too_many_lines)]
+pub fn operator_to_calls(operator_data: &Operator) -> Vec<Call> {
+    let Operator {
+        operation,
+        inverse,
+        in_direction: direction,
+        ..
+    } = operator_data;
+
+    match (*inverse, operation) {
+        (_, OperatorSymbol::Blank) => vec![],
+
+        // data
+        #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+        (_, OperatorSymbol::Number(num)) => vec![Call::Number(*num as i32)],
+        #[allow(clippy::cast_possible_wrap)]
+        (false, OperatorSymbol::String(str)) => str
+            .chars()
+            .map(|char| Call::PushStack(char as i32))
+            .collect(),
+        (true, OperatorSymbol::String(_)) => vec![Call::Unimplemented('"' as i32)],
+
+        // stack
+        (false, OperatorSymbol::PushZero) => vec![Call::PushZero],
+        (true, OperatorSymbol::PushZero) => vec![Call::PopZero],
+
+        (false, OperatorSymbol::PopZero) => vec![Call::PopZero],
+        (true, OperatorSymbol::PopZero) => vec![Call::PushZero],
+
+        (false, OperatorSymbol::PopMainToControl) => vec![Call::PopMainToControl],
+        (true, OperatorSymbol::PopMainToControl) => vec![Call::PopControlToMain],
+
+        (false, OperatorSymbol::PopControlToMain) => vec![Call::PopControlToMain],
+        (true, OperatorSymbol::PopControlToMain) => vec![Call::PopMainToControl],
+
+        (_, OperatorSymbol::SwapStacks) => vec![Call::SwapStacks],
+
+        // i/o
+        (false, OperatorSymbol::Write) => vec![Call::Write],
+        (true, OperatorSymbol::Write) => vec![Call::Unimplemented('w' as i32)],
+
+        (false, OperatorSymbol::Read) => vec![Call::Read],
+        (true, OperatorSymbol::Read) => vec![Call::Unimplemented('r' as i32)],
+
+        // number
+        (false, OperatorSymbol::Increment) => vec![Call::Increment],
+        (true, OperatorSymbol::Increment) => vec![Call::Decrement],
+
+        (false, OperatorSymbol::Decrement) => vec![Call::Decrement],
+        (true, OperatorSymbol::Decrement) => vec![Call::Increment],
+
+        (false, OperatorSymbol::Add) => vec![Call::Add],
+        (true, OperatorSymbol::Add) => vec![Call::Subtract],
+
+        (false, OperatorSymbol::Subtract) => vec![Call::Subtract],
+        (true, OperatorSymbol::Subtract) => vec![Call::Add],
+
+        (false, OperatorSymbol::Divide) => vec![Call::Divide],
+        (true, OperatorSymbol::Divide) => vec![Call::Multiply],
+
+        (false, OperatorSymbol::Multiply) => vec![Call::Multiply],
+        (true, OperatorSymbol::Multiply) => vec![Call::Divide],
+
+        // bitwise
+        (_, OperatorSymbol::Not) => vec![Call::Not],
+
+        (_, OperatorSymbol::And) => vec![Call::And],
+
+        (_, OperatorSymbol::Or) => vec![Call::Or],
+
+        (_, OperatorSymbol::Xor) => vec![Call::Xor],
+
+        (false, OperatorSymbol::RotateLeft) => vec![Call::RotateLeft],
+        (true, OperatorSymbol::RotateLeft) => vec![Call::RotateRight],
+
+        (false, OperatorSymbol::RotateRight) => vec![Call::RotateRight],
+        (true, OperatorSymbol::RotateRight) => vec![Call::RotateLeft],
+
+        // comparisons
+        (_, OperatorSymbol::ToggleControl) => vec![Call::ToggleControl],
+
+        (_, OperatorSymbol::EqualityCheck) => vec![Call::EqualityCheck],
+
+        (_, OperatorSymbol::LessThanCheck) => vec![Call::LessThanCheck],
+
+        (_, OperatorSymbol::GreaterThanCheck) => vec![Call::GreaterThanCheck],
+
+        // stack movement
+        (_, OperatorSymbol::SwapTop) => vec![Call::SwapTop],
+
+        (false, OperatorSymbol::Dig) => vec![Call::Dig],
+        (true, OperatorSymbol::Dig) => vec![Call::Bury],
+
+        (false, OperatorSymbol::Bury) => vec![Call::Bury],
+        (true, OperatorSymbol::Bury) => vec![Call::Dig],
+
+        (_, OperatorSymbol::Flip) => vec![Call::Flip],
+
+        (_, OperatorSymbol::SwapLower) => vec![Call::SwapLower],
+
+        (false, OperatorSymbol::Over) => vec![Call::Over],
+        (true, OperatorSymbol::Over) => vec![Call::Under],
+
+        (false, OperatorSymbol::Under) => vec![Call::Under],
+        (true, OperatorSymbol::Under) => vec![Call::Over],
+
+        // misc
+        (false, OperatorSymbol::Duplicate) => vec![Call::Duplicate],
+        (true, OperatorSymbol::Duplicate) => vec![Call::Unduplicate],
+
+        (false, OperatorSymbol::Unduplicate) => vec![Call::Unduplicate],
+        (true, OperatorSymbol::Unduplicate) => vec![Call::Duplicate],
+
+        (_, OperatorSymbol::InverseMode) => vec![], // handled at parse time
+        (_, OperatorSymbol::Halt) => vec![],
+
+        // direction changing
+        (_, OperatorSymbol::Mirror1) => vec![], // handled at parse time
+        (_, OperatorSymbol::Mirror2) => vec![], // handled at parse time
+        (false, OperatorSymbol::EastBranch) => match direction {
+            Direction::North => vec![Call::PushControlStack(1)],
+            Direction::South => vec![Call::PushControlStack(0)],
+            Direction::East => vec![Call::ToggleControlStack],
+            Direction::West => vec![], // dealt with elsewhere
+        },
+        (true, OperatorSymbol::EastBranch) => match direction {
+            Direction::North => vec![Call::PushControlStack(0)],
+            Direction::South => vec![Call::PushControlStack(1)],
+            Direction::East => vec![Call::ToggleControlStack],
+            Direction::West => vec![], // dealt with elsewhere
+        },
+
+        (false, OperatorSymbol::WestBranch) => match direction {
+            Direction::North => vec![Call::PushControlStack(0)],
+            Direction::South => vec![Call::PushControlStack(1)],
+            Direction::East => vec![], // dealt with elsewhere
+            Direction::West => vec![Call::ToggleControlStack],
+        },
+        (true, OperatorSymbol::WestBranch) => match direction {
+            Direction::North => vec![Call::PushControlStack(1)],
+            Direction::South => vec![Call::PushControlStack(0)],
+            Direction::East => vec![], // dealt with elsewhere
+            Direction::West => vec![Call::ToggleControlStack],
+        },
+
+        (false, OperatorSymbol::SouthBranch) => match direction {
+            Direction::North => vec![], // dealt with elsewhere
+            Direction::South => vec![Call::ToggleControlStack],
+            Direction::East => vec![Call::PushControlStack(1)],
+            Direction::West => vec![Call::PushControlStack(0)],
+        },
+        (true, OperatorSymbol::SouthBranch) => match direction {
+            Direction::North => vec![], // dealt with elsewhere
+            Direction::South => vec![Call::ToggleControlStack],
+            Direction::East => vec![Call::PushControlStack(0)],
+            Direction::West => vec![Call::PushControlStack(1)],
+        },
+
+        (false, OperatorSymbol::NorthBranch) => match direction {
+            Direction::North => vec![Call::ToggleControlStack],
+            Direction::South => vec![], // dealt with elsewhere
+            Direction::East => vec![Call::PushControlStack(0)],
+            Direction::West => vec![Call::PushControlStack(1)],
+        },
+        (true, OperatorSymbol::NorthBranch) => match direction {
+            Direction::North => vec![Call::ToggleControlStack],
+            Direction::South => vec![], // dealt with elsewhere
+            Direction::East => vec![Call::PushControlStack(1)],
+            Direction::West => vec![Call::PushControlStack(0)],
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+
+    use std::fmt::Write as _;
+
+    use super::*;
+    use crate::{operator_to_llvm_ir, Position};
+
+    fn all_symbols() -> Vec<OperatorSymbol> {
+        vec![
+            OperatorSymbol::Blank,
+            OperatorSymbol::Number(42),
+            OperatorSymbol::String("hi".to_string()),
+            OperatorSymbol::PushZero,
+            OperatorSymbol::PopZero,
+            OperatorSymbol::PopMainToControl,
+            OperatorSymbol::PopControlToMain,
+            OperatorSymbol::SwapStacks,
+            OperatorSymbol::Write,
+            OperatorSymbol::Read,
+            OperatorSymbol::Increment,
+            OperatorSymbol::Decrement,
+            OperatorSymbol::Add,
+            OperatorSymbol::Subtract,
+            OperatorSymbol::Divide,
+            OperatorSymbol::Multiply,
+            OperatorSymbol::Not,
+            OperatorSymbol::And,
+            OperatorSymbol::Or,
+            OperatorSymbol::Xor,
+            OperatorSymbol::RotateLeft,
+            OperatorSymbol::RotateRight,
+            OperatorSymbol::ToggleControl,
+            OperatorSymbol::EqualityCheck,
+            OperatorSymbol::LessThanCheck,
+            OperatorSymbol::GreaterThanCheck,
+            OperatorSymbol::SwapTop,
+            OperatorSymbol::Dig,
+            OperatorSymbol::Bury,
+            OperatorSymbol::Flip,
+            OperatorSymbol::SwapLower,
+            OperatorSymbol::Over,
+            OperatorSymbol::Under,
+            OperatorSymbol::Duplicate,
+            OperatorSymbol::Unduplicate,
+            OperatorSymbol::InverseMode,
+            OperatorSymbol::Halt,
+            OperatorSymbol::Mirror1,
+            OperatorSymbol::Mirror2,
+            OperatorSymbol::EastBranch,
+            OperatorSymbol::WestBranch,
+            OperatorSymbol::SouthBranch,
+            OperatorSymbol::NorthBranch,
+        ]
+    }
+
+    const fn operator(operation: OperatorSymbol, inverse: bool) -> Operator {
+        Operator {
+            operation,
+            in_direction: Direction::East,
+            inverse,
+            position: Position(0, 0),
+        }
+    }
+
+    // the properties below run on the rust runtime, this makes sure they say
+    // something about the llvm ir too
+    #[test]
+    fn calls_match_llvm_ir() {
+        for operation in all_symbols() {
+            for inverse in [false, true] {
+                for in_direction in [
+                    Direction::North,
+                    Direction::South,
+                    Direction::East,
+                    Direction::West,
+                ] {
+                    let operator = Operator {
+                        in_direction,
+                        ..operator(operation.clone(), inverse)
+                    };
+                    let mut llvm_ir = String::new();
+                    operator_to_llvm_ir(&mut llvm_ir, operator.clone());
+                    let llvm_ir: Vec<_> = llvm_ir
+                        .lines()
+                        .map(str::trim)
+                        .filter(|line| !line.is_empty())
+                        .collect();
+                    let calls: Vec<_> = operator_to_calls(&operator)
+                        .into_iter()
+                        .map(|call| match call.symbol() {
+                            (symbol, Some(arg)) => format!("call void @{symbol}(i32 {arg})"),
+                            (symbol, None) => format!("call void @{symbol}()"),
+                        })
+                        .collect();
+                    assert_eq!(llvm_ir, calls, "{operator:?}");
+                }
+            }
+        }
+    }
+
+    // whether running call now can be undone by its inverse. preconditions
+    // from the spec, like `)` needing a zero on top, are part of this
+    fn reversible(call: Call, runtime: &Runtime) -> bool {
+        let depth = runtime.primary.len();
+        let top = |n: usize| runtime.primary[depth - 1 - n];
+        let control_is_bool = matches!(runtime.control.last(), Some(0 | 1));
+        match call {
+            Call::PushZero => true,
+            Call::PopZero => depth >= 1 && top(0) == 0,
+            Call::PopMainToControl => depth >= 1,
+            Call::PopControlToMain => !runtime.control.is_empty(),
+            Call::SwapStacks => depth >= 1 && !runtime.control.is_empty(),
+            Call::Number(_) | Call::Increment | Call::Decrement | Call::Not | Call::Duplicate => {
+                depth >= 1
+            }
+            Call::Add
+            | Call::Subtract
+            | Call::Xor
+            | Call::RotateLeft
+            | Call::RotateRight
+            | Call::SwapTop
+            | Call::Over => depth >= 2,
+            Call::Divide => depth >= 2 && top(0) != 0,
+            Call::Multiply => {
+                depth >= 3 && {
+                    // a floor division remainder, which takes the divisor's sign
+                    let (x, rem, div) = (top(0), top(1), top(2));
+                    let remainder = if x > 0 { 0..=x - 1 } else { x + 1..=0 };
+                    x != 0
+                        && remainder.contains(&rem)
+                        // x * div can overflow on its own when rem brings it back
+                        && i32::try_from(i64::from(x) * i64::from(div) + i64::from(rem)).is_ok()
+                }
+            }
+            Call::And | Call::Or | Call::Dig | Call::Bury | Call::Flip | Call::SwapLower => {
+                depth >= 3
+            }
+            Call::ToggleControl => control_is_bool,
+            Call::EqualityCheck | Call::LessThanCheck | Call::GreaterThanCheck => {
+                depth >= 2 && control_is_bool
+            }
+            Call::Under => depth >= 3 && top(0) == top(2),
+            Call::Unduplicate => depth >= 2 && top(0) == top(1),
+            _ => false,
+        }
+    }
+
+    // everything with an inverse that stays on the stacks, so no i/o, strings or branches
+    fn reversible_operator() -> impl Strategy<Value = Operator> {
+        let operation = prop_oneof![
+            Just(OperatorSymbol::Blank),
+            (0..1000usize).prop_map(OperatorSymbol::Number),
+            Just(OperatorSymbol::PushZero),
+            Just(OperatorSymbol::PopZero),
+            Just(OperatorSymbol::PopMainToControl),
+            Just(OperatorSymbol::PopControlToMain),
+            Just(OperatorSymbol::SwapStacks),
+            Just(OperatorSymbol::Increment),
+            Just(OperatorSymbol::Decrement),
+            Just(OperatorSymbol::Add),
+            Just(OperatorSymbol::Subtract),
+            Just(OperatorSymbol::Divide),
+            Just(OperatorSymbol::Multiply),
+            Just(OperatorSymbol::Not),
+            Just(OperatorSymbol::And),
+            Just(OperatorSymbol::Or),
+            Just(OperatorSymbol::Xor),
+            Just(OperatorSymbol::RotateLeft),
+            Just(OperatorSymbol::RotateRight),
+            Just(OperatorSymbol::ToggleControl),
+            Just(OperatorSymbol::EqualityCheck),
+            Just(OperatorSymbol::LessThanCheck),
+            Just(OperatorSymbol::GreaterThanCheck),
+            Just(OperatorSymbol::SwapTop),
+            Just(OperatorSymbol::Dig),
+            Just(OperatorSymbol::Bury),
+            Just(OperatorSymbol::Flip),
+            Just(OperatorSymbol::SwapLower),
+            Just(OperatorSymbol::Over),
+            Just(OperatorSymbol::Under),
+            Just(OperatorSymbol::Duplicate),
+            Just(OperatorSymbol::Unduplicate),
+        ];
+        (operation, any::<bool>()).prop_map(|(operation, inverse)| operator(operation, inverse))
+    }
+
+    // small values so equal neighbours, zeros and exact divisions actually come up
+    fn value() -> impl Strategy<Value = i32> {
+        prop_oneof![-4..=4, any::<i32>()]
+    }
+
+    proptest! {
+        #[test]
+        fn inverse_mode_undoes_forward(
+            primary in vec(value(), 0..8),
+            control in vec(0..=1, 0..4),
+            operators in vec(reversible_operator(), 0..64),
+        ) {
+            let mut runtime = Runtime::new();
+            runtime.primary = primary.clone();
+            runtime.control = control.clone();
+            let mut executed = vec![];
+            for operator in operators {
+                let calls = operator_to_calls(&operator);
+                if calls.iter().all(|call| reversible(*call, &runtime)) {
+                    for call in calls {
+                        runtime.execute(call).unwrap();
+                    }
+                    executed.push(operator);
+                }
+            }
+
+            // running backwards reads the same cells in reverse order with inverse mode flipped
+            for operator in executed.into_iter().rev() {
+                let inverse = !operator.inverse;
+                for call in operator_to_calls(&Operator { inverse, ..operator }) {
+                    runtime.execute(call).unwrap();
+                }
+            }
+            prop_assert_eq!(runtime.primary, primary);
+            prop_assert_eq!(runtime.control, control);
+        }
+
+        #[test]
+        fn multiply_undoes_divide_whatever_the_signs(
+            y in value(),
+            x in value().prop_filter("divisor", |x| *x != 0),
+        ) {
+            let mut runtime = Runtime::new();
+            runtime.primary = vec![y, x];
+            runtime.execute(Call::Divide).unwrap();
+            runtime.execute(Call::Multiply).unwrap();
+            prop_assert_eq!(runtime.primary, vec![y, x]);
+        }
+    }
+
+    // (operator, inverse, primary before, control before, primary after, control after),
+    // stacks written bottom to top. branches are entered heading East
+    type Case = (
+        OperatorSymbol,
+        bool,
+        &'static [i32],
+        &'static [i32],
+        &'static [i32],
+        &'static [i32],
+    );
+
+    #[rustfmt::skip]
+    fn conformance() -> Vec<Case> {
+        vec![
+            (OperatorSymbol::Blank, false, &[1, 2], &[], &[1, 2], &[]),
+            (OperatorSymbol::Blank, true, &[1, 2], &[], &[1, 2], &[]),
+
+            // data
+            (OperatorSymbol::Number(5), false, &[1, 2], &[], &[1, 7], &[]),
+            (OperatorSymbol::Number(5), true, &[1, 7], &[], &[1, 2], &[]),
+            (OperatorSymbol::String("hi".to_string()), false, &[0], &[], &[0, 104, 105], &[]),
+
+            // stack
+            (OperatorSymbol::PushZero, false, &[1], &[], &[1, 0], &[]),
+            (OperatorSymbol::PushZero, true, &[1, 0], &[], &[1], &[]),
+            (OperatorSymbol::PopZero, false, &[1, 0], &[], &[1], &[]),
+            (OperatorSymbol::PopZero, true, &[1], &[], &[1, 0], &[]),
+            (OperatorSymbol::PopMainToControl, false, &[1, 2], &[3], &[1], &[3, 2]),
+            (OperatorSymbol::PopMainToControl, true, &[1], &[3, 2], &[1, 2], &[3]),
+            (OperatorSymbol::PopControlToMain, false, &[1], &[3, 2], &[1, 2], &[3]),
+            (OperatorSymbol::PopControlToMain, true, &[1, 2], &[3], &[1], &[3, 2]),
+            (OperatorSymbol::SwapStacks, false, &[1, 2], &[3], &[1, 3], &[2]),
+            (OperatorSymbol::SwapStacks, true, &[1, 2], &[3], &[1, 3], &[2]),
+
+            // i/o, with "A" on stdin
+            (OperatorSymbol::Write, false, &[1, 65], &[], &[1], &[]),
+            (OperatorSymbol::Read, false, &[1], &[], &[1, 65], &[]),
+
+            // number
+            (OperatorSymbol::Increment, false, &[1], &[], &[2], &[]),
+            (OperatorSymbol::Increment, true, &[2], &[], &[1], &[]),
+            (OperatorSymbol::Increment, false, &[i32::MAX], &[], &[i32::MIN], &[]),
+            (OperatorSymbol::Decrement, false, &[2], &[], &[1], &[]),
+            (OperatorSymbol::Decrement, true, &[1], &[], &[2], &[]),
+            (OperatorSymbol::Add, false, &[5, 3], &[], &[8, 3], &[]),
+            (OperatorSymbol::Add, true, &[8, 3], &[], &[5, 3], &[]),
+            (OperatorSymbol::Subtract, false, &[5, 3], &[], &[2, 3], &[]),
+            (OperatorSymbol::Subtract, true, &[2, 3], &[], &[5, 3], &[]),
+            (OperatorSymbol::Divide, false, &[7, 2], &[], &[3, 1, 2], &[]),
+            (OperatorSymbol::Divide, true, &[3, 1, 2], &[], &[7, 2], &[]),
+            // rounds down, the remainder takes the divisor's sign
+            (OperatorSymbol::Divide, false, &[-7, 2], &[], &[-4, 1, 2], &[]),
+            (OperatorSymbol::Divide, false, &[7, -2], &[], &[-4, -1, -2], &[]),
+            (OperatorSymbol::Divide, false, &[-7, -2], &[], &[3, -1, -2], &[]),
+            (OperatorSymbol::Divide, false, &[-6, 3], &[], &[-2, 0, 3], &[]),
+            (OperatorSymbol::Divide, false, &[i32::MIN, -1], &[], &[i32::MIN, 0, -1], &[]),
+            (OperatorSymbol::Divide, false, &[i32::MAX, i32::MIN], &[], &[-1, -1, i32::MIN], &[]),
+            (OperatorSymbol::Multiply, false, &[3, 1, 2], &[], &[7, 2], &[]),
+            (OperatorSymbol::Multiply, true, &[7, 2], &[], &[3, 1, 2], &[]),
+            (OperatorSymbol::Multiply, false, &[-4, 1, 2], &[], &[-7, 2], &[]),
+            (OperatorSymbol::Multiply, false, &[-4, -1, -2], &[], &[7, -2], &[]),
+            (OperatorSymbol::Multiply, true, &[-7, -2], &[], &[3, -1, -2], &[]),
+
+            // bitwise
+            (OperatorSymbol::Not, false, &[5], &[], &[-6], &[]),
+            (OperatorSymbol::Not, true, &[-6], &[], &[5], &[]),
+            (OperatorSymbol::And, false, &[12, 10, 6], &[], &[14, 10, 6], &[]),
+            (OperatorSymbol::And, true, &[14, 10, 6], &[], &[12, 10, 6], &[]),
+            (OperatorSymbol::Or, false, &[12, 10, 6], &[], &[2, 10, 6], &[]),
+            (OperatorSymbol::Or, true, &[2, 10, 6], &[], &[12, 10, 6], &[]),
+            (OperatorSymbol::Xor, false, &[10, 6], &[], &[12, 6], &[]),
+            (OperatorSymbol::Xor, true, &[12, 6], &[], &[10, 6], &[]),
+            (OperatorSymbol::RotateLeft, false, &[1, 4], &[], &[16, 4], &[]),
+            (OperatorSymbol::RotateLeft, false, &[i32::MIN, 33], &[], &[1, 33], &[]),
+            (OperatorSymbol::RotateLeft, true, &[16, 4], &[], &[1, 4], &[]),
+            (OperatorSymbol::RotateRight, false, &[16, 4], &[], &[1, 4], &[]),
+            (OperatorSymbol::RotateRight, true, &[1, 4], &[], &[16, 4], &[]),
+
+            // comparisons toggle the top of the control stack
+            (OperatorSymbol::ToggleControl, false, &[], &[0], &[], &[1]),
+            (OperatorSymbol::ToggleControl, true, &[], &[1], &[], &[0]),
+            (OperatorSymbol::EqualityCheck, false, &[3, 3], &[0], &[3, 3], &[1]),
+            (OperatorSymbol::EqualityCheck, false, &[3, 4], &[0], &[3, 4], &[0]),
+            (OperatorSymbol::EqualityCheck, true, &[3, 3], &[1], &[3, 3], &[0]),
+            (OperatorSymbol::LessThanCheck, false, &[3, 4], &[0], &[3, 4], &[1]),
+            (OperatorSymbol::LessThanCheck, false, &[4, 3], &[0], &[4, 3], &[0]),
+            (OperatorSymbol::LessThanCheck, true, &[3, 4], &[1], &[3, 4], &[0]),
+            (OperatorSymbol::GreaterThanCheck, false, &[4, 3], &[0], &[4, 3], &[1]),
+            (OperatorSymbol::GreaterThanCheck, false, &[3, 4], &[0], &[3, 4], &[0]),
+            (OperatorSymbol::GreaterThanCheck, true, &[4, 3], &[1], &[4, 3], &[0]),
+
+            // stack movement
+            (OperatorSymbol::SwapTop, false, &[1, 2], &[], &[2, 1], &[]),
+            (OperatorSymbol::SwapTop, true, &[2, 1], &[], &[1, 2], &[]),
+            (OperatorSymbol::Dig, false, &[1, 2, 3], &[], &[2, 3, 1], &[]),
+            (OperatorSymbol::Dig, true, &[2, 3, 1], &[], &[1, 2, 3], &[]),
+            (OperatorSymbol::Bury, false, &[1, 2, 3], &[], &[3, 1, 2], &[]),
+            (OperatorSymbol::Bury, true, &[3, 1, 2], &[], &[1, 2, 3], &[]),
+            (OperatorSymbol::Flip, false, &[1, 2, 3], &[], &[3, 2, 1], &[]),
+            (OperatorSymbol::Flip, true, &[3, 2, 1], &[], &[1, 2, 3], &[]),
+            (OperatorSymbol::SwapLower, false, &[1, 2, 3], &[], &[2, 1, 3], &[]),
+            (OperatorSymbol::SwapLower, true, &[2, 1, 3], &[], &[1, 2, 3], &[]),
+            (OperatorSymbol::Over, false, &[1, 2], &[], &[1, 2, 1], &[]),
+            (OperatorSymbol::Over, true, &[1, 2, 1], &[], &[1, 2], &[]),
+            (OperatorSymbol::Under, false, &[1, 2, 1], &[], &[1, 2], &[]),
+            (OperatorSymbol::Under, true, &[1, 2], &[], &[1, 2, 1], &[]),
+
+            // misc
+            (OperatorSymbol::Duplicate, false, &[1, 2], &[], &[1, 2, 2], &[]),
+            (OperatorSymbol::Duplicate, true, &[1, 2, 2], &[], &[1, 2], &[]),
+            (OperatorSymbol::Unduplicate, false, &[1, 2, 2], &[], &[1, 2], &[]),
+            (OperatorSymbol::Unduplicate, true, &[1, 2], &[], &[1, 2, 2], &[]),
+            (OperatorSymbol::InverseMode, false, &[1], &[0], &[1], &[0]),
+            (OperatorSymbol::InverseMode, true, &[1], &[0], &[1], &[0]),
+            (OperatorSymbol::Halt, false, &[1], &[0], &[1], &[0]),
+            (OperatorSymbol::Halt, true, &[1], &[0], &[1], &[0]),
+
+            // direction changing
+            (OperatorSymbol::Mirror1, false, &[1], &[0], &[1], &[0]),
+            (OperatorSymbol::Mirror1, true, &[1], &[0], &[1], &[0]),
+            (OperatorSymbol::Mirror2, false, &[1], &[0], &[1], &[0]),
+            (OperatorSymbol::Mirror2, true, &[1], &[0], &[1], &[0]),
+            (OperatorSymbol::EastBranch, false, &[1], &[0], &[1], &[1]),
+            (OperatorSymbol::EastBranch, true, &[1], &[1], &[1], &[0]),
+            // the branch itself pops the control stack
+            (OperatorSymbol::WestBranch, false, &[1], &[0], &[1], &[0]),
+            (OperatorSymbol::WestBranch, true, &[1], &[0], &[1], &[0]),
+            (OperatorSymbol::SouthBranch, false, &[1], &[], &[1], &[1]),
+            (OperatorSymbol::SouthBranch, true, &[1], &[], &[1], &[0]),
+            (OperatorSymbol::NorthBranch, false, &[1], &[], &[1], &[0]),
+            (OperatorSymbol::NorthBranch, true, &[1], &[], &[1], &[1]),
+        ]
+    }
+
+    // no inverse defined yet, the generated code stops with "inverse 'w' is unimplemented"
+    fn unimplemented() -> Vec<(OperatorSymbol, bool)> {
+        vec![
+            (OperatorSymbol::String("hi".to_string()), true),
+            (OperatorSymbol::Write, true),
+            (OperatorSymbol::Read, true),
+        ]
+    }
+
+    #[test]
+    fn conformance_covers_every_operator() {
+        let discriminant = std::mem::discriminant::<OperatorSymbol>;
+        let covered: Vec<_> = conformance()
+            .into_iter()
+            .map(|(operation, inverse, ..)| (operation, inverse))
+            .chain(unimplemented())
+            .map(|(operation, inverse)| (discriminant(&operation), inverse))
+            .collect();
+        for operation in all_symbols() {
+            for inverse in [false, true] {
+                assert!(
+                    covered.contains(&(discriminant(&operation), inverse)),
+                    "no conformance case for {operation:?} with inverse {inverse}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn runtime_conformance() {
+        for (operation, inverse, primary, control, primary_after, control_after) in conformance() {
+            let operator = operator(operation, inverse);
+            let mut runtime = Runtime::buffered();
+            runtime.primary = primary.to_vec();
+            runtime.control = control.to_vec();
+            if let Io::Buffered { input, .. } = &mut runtime.io {
+                input.push_back(b'A');
+            }
+            for call in operator_to_calls(&operator) {
+                runtime.execute(call).unwrap();
+            }
+            assert_eq!(
+                (&runtime.primary[..], &runtime.control[..]),
+                (primary_after, control_after),
+                "{operator:?} on {primary:?} {control:?}"
+            );
+            if let Io::Buffered { output, .. } = &runtime.io {
+                let expected: &[u8] = if matches!(operator.operation, OperatorSymbol::Write) {
+                    b"A"
+                } else {
+                    b""
+                };
+                assert_eq!(output, expected, "{operator:?} output");
+            }
+        }
+    }
+
+    #[test]
+    fn errors_say_where_they_happened() {
+        let mut runtime = Runtime::buffered();
+        runtime.primary = vec![5, 0];
+        runtime.control = vec![1];
+        assert_eq!(
+            runtime.error("division by zero"),
+            "error: division by zero\nmain:    [5, 0]\ncontrol: [1]"
+        );
+        runtime.at = Some((Position(3, 1), Direction::West, true));
+        assert_eq!(
+            runtime.error("division by zero"),
+            "error at row 2, column 4 heading West (inverse): division by zero\nmain:    [5, 0]\ncontrol: [1]"
+        );
+    }
+
+    #[test]
+    fn final_state_and_halt_status() {
+        let mut runtime = Runtime::buffered();
+        assert_eq!(runtime.halt_status(HaltStatus::Top), 0);
+        runtime.primary = vec![5, 300];
+        runtime.control = vec![1, 0];
+        assert_eq!(
+            runtime.final_state(StateFormat::Text),
+            "main:    [5, 300]\ncontrol: [1, 0]"
+        );
+        assert_eq!(
+            runtime.final_state(StateFormat::Json),
+            r#"{"main": [5, 300], "control": [1, 0]}"#
+        );
+        assert_eq!(runtime.halt_status(HaltStatus::Zero), 0);
+        assert_eq!(runtime.halt_status(HaltStatus::Top), 300);
+    }
+
+    // a main that sets up the stacks, runs one operator, then dumps both stacks on stderr
+    fn prelude_program(operator: Operator, primary: &[i32], control: &[i32]) -> String {
+        let mut llvm_ir = String::from(crate::PRELUDE);
+        llvm_ir.push_str("\ndefine i32 @main() {");
+        for val in primary {
+            write!(llvm_ir, "\n    call void @push_stack(i32 {val})").unwrap();
+        }
+        for val in control {
+            write!(llvm_ir, "\n    call void @push_control_stack(i32 {val})").unwrap();
+        }
+        operator_to_llvm_ir(&mut llvm_ir, operator);
+        llvm_ir.push_str("\n    call void @dump_final_state(i32 0)\n    ret i32 0\n}\n");
+        llvm_ir
+    }
+
+    struct PreludeRun {
+        output: String,
+        primary: Vec<i32>,
+        control: Vec<i32>,
+    }
+
+    // None if lli isn't installed
+    fn run_prelude(
+        operator: Operator,
+        primary: &[i32],
+        control: &[i32],
+    ) -> Option<Result<PreludeRun, String>> {
+        use std::io::Write as _;
+        use std::process::{Command, Stdio};
+
+        let path =
+            std::env::temp_dir().join(format!("befreak-conformance-{}.ll", std::process::id()));
+        std::fs::write(&path, prelude_program(operator, primary, control)).unwrap();
+        let child = Command::new("lli")
+            .arg(&path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return None,
+            Err(err) => panic!("couldn't run lli: {err}"),
+        };
+        child.stdin.take().unwrap().write_all(b"A").unwrap();
+        let output = child.wait_with_output().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if !output.status.success() {
+            return Some(Err(format!(
+                "{stdout}{}",
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+
+        // "main:    [a, b]" then "control: [c]"
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let mut stacks = stderr.lines().map(|line| {
+            let (_, values) = line.split_once('[').unwrap();
+            values
+                .trim_end_matches(']')
+                .split(", ")
+                .filter(|val| !val.is_empty())
+                .map(|val| val.parse::<i32>().unwrap())
+                .collect::<Vec<_>>()
+        });
+        let primary = stacks.next().unwrap();
+        let control = stacks.next().unwrap();
+        let output = stdout.into_owned();
+        Some(Ok(PreludeRun {
+            output,
+            primary,
+            control,
+        }))
+    }
+
+    #[test]
+    fn prelude_conformance() {
+        for (operation, inverse, primary, control, primary_after, control_after) in conformance() {
+            let expected_output = if matches!(operation, OperatorSymbol::Write) {
+                "A"
+            } else {
+                ""
+            };
+            let operator = operator(operation, inverse);
+            let description = format!("{operator:?} on {primary:?} {control:?}");
+            let Some(result) = run_prelude(operator, primary, control) else {
+                eprintln!("lli isn't installed, skipping");
+                return;
+            };
+            let run = result.unwrap_or_else(|err| panic!("{description} failed: {err}"));
+            assert_eq!(
+                (&run.primary[..], &run.control[..], &run.output[..]),
+                (primary_after, control_after, expected_output),
+                "{description}"
+            );
+        }
+        for (operation, inverse) in unimplemented() {
+            let operator = operator(operation, inverse);
+            let description = format!("{operator:?}");
+            if let Some(result) = run_prelude(operator, &[0], &[]) {
+                let err = result.err().unwrap_or_else(|| panic!("{description} ran"));
+                assert!(err.contains("' is unimplemented"), "{description}: {err}");
+            }
+        }
+    }
+}
@@ -0,0 +1,319 @@
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use array2d::Array2D;
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+
+use crate::interpreter::Interpreter;
+use crate::{get_char, location, ExpressionTree, Position};
+
+// the stacks are drawn to the right of the grid
+const STACK_WIDTH: usize = 12;
+const KEYS: &str = "space play/pause  r reverse  right step  left back  +/- speed  q quit";
+// rows above the grid and below it
+const HEADER: usize = 3;
+const FOOTER: usize = 2;
+
+struct Player {
+    playing: bool,
+    backwards: bool,
+    // steps per second
+    speed: u32,
+    error: Option<String>,
+}
+
+impl Player {
+    // one step in the direction it's playing, false if it can't go any further
+    fn advance(&mut self, interpreter: &mut Interpreter, backwards: bool) -> bool {
+        let result = if backwards {
+            interpreter.back()
+        } else {
+            interpreter.step()
+        };
+        match result {
+            Ok(()) => {
+                self.error = None;
+                if backwards {
+                    interpreter.steps > 0
+                } else {
+                    !interpreter.halted()
+                }
+            }
+            Err(err) => {
+                self.error = Some(err);
+                false
+            }
+        }
+    }
+}
+
+// what's drawn each frame, as plain text so it doesn't need a terminal.
+// the current cell is highlighted over it afterwards
+struct Frame {
+    lines: Vec<String>,
+    cell: (usize, usize),
+}
+
+// the first row or column to show so the target stays in the middle of the view
+const fn scroll(target: usize, size: usize, view: usize) -> usize {
+    let first = target.saturating_sub(view / 2);
+    let last = size.saturating_sub(view);
+    if first < last {
+        first
+    } else {
+        last
+    }
+}
+
+fn frame(
+    interpreter: &Interpreter,
+    code: &Array2D<char>,
+    player: &Player,
+    width: usize,
+    height: usize,
+) -> Frame {
+    let state = &interpreter.state;
+    let Position(x, y) = state.position;
+    let view_width = code
+        .num_columns()
+        .min(width.saturating_sub(2 * STACK_WIDTH + 3))
+        .max(1);
+    let view_height = code
+        .num_rows()
+        .min(height.saturating_sub(HEADER + FOOTER))
+        .max(1);
+    let left = scroll(x, code.num_columns(), view_width);
+    let top = scroll(y, code.num_rows(), view_height);
+
+    let mut lines = vec![
+        format!(
+            "{} {}: {:?}",
+            location(state.position, state.direction),
+            if state.inverse_mode {
+                "inverse"
+            } else {
+                "normal"
+            },
+            interpreter.operator().operation
+        ),
+        format!(
+            "{} {} at {} steps/s, step {}{}",
+            if player.playing { "playing" } else { "paused" },
+            if player.backwards {
+                "backwards"
+            } else {
+                "forwards"
+            },
+            player.speed,
+            interpreter.steps,
+            match &player.error {
+                Some(err) => format!(", error: {err}"),
+                None if interpreter.halted() => ", halted".to_string(),
+                None => String::new(),
+            }
+        ),
+        format!("{:view_width$} | {:STACK_WIDTH$}{}", "", "main", "control"),
+    ];
+    // top of the stack first
+    let main = interpreter.runtime.primary.iter().rev();
+    let control = interpreter.runtime.control.iter().rev();
+    let mut main = main.map(ToString::to_string);
+    let mut control = control.map(ToString::to_string);
+    // the stacks can carry on below the grid, as far as the terminal goes
+    let depth = main.len().max(control.len());
+    let body_height = depth
+        .min(height.saturating_sub(HEADER + FOOTER))
+        .max(view_height);
+    for row in top..top + body_height {
+        let cells: String = (left..left + view_width)
+            .map(|column| *get_char(code, Position(column, row)).unwrap_or(&' '))
+            .collect();
+        lines.push(format!(
+            "{cells} | {:STACK_WIDTH$}{}",
+            main.next().unwrap_or_default(),
+            control.next().unwrap_or_default()
+        ));
+    }
+    let output = String::from_utf8_lossy(interpreter.output());
+    let output = format!("{:?}", output);
+    // the end of the output is what changed last
+    let skip = output
+        .chars()
+        .count()
+        .saturating_sub(width.saturating_sub(8));
+    lines.push(format!(
+        "output: {}",
+        output.chars().skip(skip).collect::<String>()
+    ));
+    lines.push(KEYS.to_string());
+
+    Frame {
+        lines,
+        cell: (x - left, HEADER + y - top),
+    }
+}
+
+fn draw(
+    stdout: &mut impl Write,
+    frame: &Frame,
+    code: &Array2D<char>,
+    interpreter: &Interpreter,
+    width: usize,
+) -> std::io::Result<()> {
+    for (row, line) in frame.lines.iter().enumerate() {
+        let line: String = line.chars().take(width).collect();
+        #[allow(clippy::cast_possible_truncation)]
+        queue!(
+            stdout,
+            MoveTo(0, row as u16),
+            Print(line),
+            Clear(ClearType::UntilNewLine)
+        )?;
+    }
+    queue!(stdout, Clear(ClearType::FromCursorDown))?;
+
+    // the colour says which mode it's in, the status line says which way it's heading
+    let state = &interpreter.state;
+    let background = if state.inverse_mode {
+        Color::Magenta
+    } else {
+        Color::Cyan
+    };
+    let (column, row) = frame.cell;
+    #[allow(clippy::cast_possible_truncation)]
+    queue!(
+        stdout,
+        MoveTo(column as u16, row as u16),
+        SetForegroundColor(Color::Black),
+        SetBackgroundColor(background),
+        Print(get_char(code, state.position).unwrap()),
+        ResetColor
+    )?;
+    stdout.flush()
+}
+
+// puts the terminal back however the tui exits
+struct Guard;
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        let _ = execute!(std::io::stdout(), Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+// animates the interpreter over the grid until q is pressed
+pub fn visualize(tree: &ExpressionTree, code: &Array2D<char>) -> std::io::Result<()> {
+    let mut interpreter = Interpreter::new(tree);
+    let mut player = Player {
+        playing: false,
+        backwards: false,
+        speed: 8,
+        error: None,
+    };
+    let mut stdout = std::io::stdout();
+    terminal::enable_raw_mode()?;
+    let _guard = Guard;
+    execute!(stdout, EnterAlternateScreen, Hide)?;
+
+    let mut next_tick = Instant::now();
+    loop {
+        let (width, height) = terminal::size()?;
+        let (width, height) = (usize::from(width), usize::from(height));
+        let frame = frame(&interpreter, code, &player, width, height);
+        draw(&mut stdout, &frame, code, &interpreter, width)?;
+
+        let timeout = if player.playing {
+            next_tick.saturating_duration_since(Instant::now())
+        } else {
+            Duration::from_secs(60)
+        };
+        if event::poll(timeout)? {
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Ok(())
+                }
+                KeyCode::Char(' ') => {
+                    player.playing = !player.playing;
+                    next_tick = Instant::now();
+                }
+                KeyCode::Char('r') => player.backwards = !player.backwards,
+                KeyCode::Right | KeyCode::Char('s') => {
+                    player.playing = false;
+                    player.advance(&mut interpreter, false);
+                }
+                KeyCode::Left | KeyCode::Char('b') => {
+                    player.playing = false;
+                    player.advance(&mut interpreter, true);
+                }
+                KeyCode::Char('+' | '=') => player.speed = (player.speed * 2).min(4096),
+                KeyCode::Char('-') => player.speed = (player.speed / 2).max(1),
+                _ => {}
+            }
+        } else if player.playing {
+            // at high speeds take several steps per frame rather than redrawing every step
+            let steps = (player.speed / 32).max(1);
+            for _ in 0..steps {
+                if !player.advance(&mut interpreter, player.backwards) {
+                    player.playing = false;
+                    break;
+                }
+            }
+            next_tick = Instant::now() + Duration::from_secs(1) * steps / player.speed;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_code, read_string};
+
+    #[test]
+    fn frame_highlights_the_cell_next_to_both_stacks() {
+        let code = read_string(include_str!("../tests/fixtures/hello.bf"));
+        let tree = parse_code(&code, false).unwrap();
+        let mut interpreter = Interpreter::new(&tree);
+        let mut player = Player {
+            playing: false,
+            backwards: false,
+            speed: 8,
+            error: None,
+        };
+        // ( / \ 01 pushes a zero then xors 10 into it
+        for _ in 0..4 {
+            assert!(player.advance(&mut interpreter, false));
+        }
+        interpreter.runtime.control.push(1);
+
+        let frame = frame(&interpreter, &code, &player, 80, 24);
+        assert_eq!(
+            frame.lines[0],
+            "row 1, column 15 heading West normal: String(\"!dlrow olleH\")"
+        );
+        let (column, row) = frame.cell;
+        assert_eq!(frame.lines[row].chars().nth(column), Some('"'));
+        assert_eq!(column, 14);
+        assert!(frame.lines[HEADER].ends_with(&format!(" | {:STACK_WIDTH$}1", "10")));
+        assert_eq!(frame.lines.len(), HEADER + code.num_rows() + FOOTER);
+    }
+
+    #[test]
+    fn scrolls_to_keep_the_cell_in_view() {
+        assert_eq!(scroll(3, 100, 20), 0);
+        assert_eq!(scroll(50, 100, 20), 40);
+        assert_eq!(scroll(99, 100, 20), 80);
+        assert_eq!(scroll(5, 10, 20), 0);
+    }
+}
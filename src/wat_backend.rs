@@ -0,0 +1,78 @@
+use std::fmt::Write;
+
+use crate::dispatch::{lower, Next, Target};
+use crate::{CompileOptions, ExpressionTree, HaltStatus};
+
+// the runtime exposes the same functions as the LLVM PRELUDE, minus the `@`,
+// so the calls are the ones runtime::operator_to_calls gives the jit
+const RUNTIME: &str = include_str!("../runtime/befreak.wat");
+
+pub fn compile_wat(data: ExpressionTree, options: &CompileOptions) {
+    if options.bignum {
+        eprintln!("--bignum is not supported by the wat backend");
+        std::process::exit(2);
+    }
+
+    let mut wat = String::from("(module\n");
+    wat.push_str(RUNTIME);
+
+    wat.push_str("\n  ;;;; actual codegen begin\n");
+    // expressions return the index of the next one like the c backend, see
+    // dispatch. -1 is halting
+    let dispatch = lower(data, options.max_steps.is_some());
+    let mut table = String::new();
+    wat.push_str("\n  (type $expression (func (result i32)))\n");
+    for expression in dispatch.expressions {
+        let symbol = expression.symbol;
+        write!(wat, "\n  (func ${symbol} (type $expression)").unwrap();
+        write!(table, " ${symbol}").unwrap();
+        for (function, args) in expression.calls {
+            write!(wat, "\n    (call ${function}").unwrap();
+            for arg in args {
+                write!(wat, " (i32.const {arg})").unwrap();
+            }
+            wat.push(')');
+        }
+        let next = |(index, symbol): Target| format!("(i32.const {index}) ;; {symbol}\n");
+        match expression.next {
+            Next::Halt => wat.push_str("\n    (i32.const -1))\n"),
+            Next::One(id1) => write!(wat, "\n    {})", next(id1)).unwrap(),
+            Next::Two(id1, id2) => write!(
+                wat,
+                "\n    (if (result i32) (call $pop_control_stack_i1)\n      (then {}      )\n      (else {}      )))\n",
+                next(id1),
+                next(id2)
+            )
+            .unwrap(),
+        }
+    }
+    write!(wat, "\n  (table $expressions funcref (elem{table}))\n").unwrap();
+
+    wat.push_str(
+        "\n  ;;;; actual codegen over\n\n  (func (export \"_start\")\n    (local $next i32)",
+    );
+    if let Some(max_steps) = options.max_steps {
+        write!(wat, "\n    (call $limit_steps (i64.const {max_steps}))").unwrap();
+    }
+    write!(
+        wat,
+        "\n    (local.set $next (i32.const {}))\n    (block $halt\n      (loop $dispatch\n        (br_if $halt (i32.lt_s (local.get $next) (i32.const 0)))\n        (local.set $next (call_indirect $expressions (type $expression) (local.get $next)))\n        (br $dispatch)))",
+        dispatch.start
+    )
+    .unwrap();
+    // the loop only ends once the program halts
+    if let Some(format) = options.dump_final_state {
+        write!(
+            wat,
+            "\n    (call $dump_final_state (i32.const {}))",
+            format.json()
+        )
+        .unwrap();
+    }
+    // wasi's _start doesn't return a status, it has to exit with it
+    wat.push_str(match options.halt_status {
+        HaltStatus::Zero => ")\n)",
+        HaltStatus::Top => "\n    (call $proc_exit (call $halt_status)))\n)",
+    });
+    println!("{wat}");
+}
@@ -0,0 +1,142 @@
+// programs that loop for a long time have to run in the c, llvm and wasm
+// backends however they're built, moving between expressions mustn't grow the
+// native or wasm stack. skipped if cc or llc aren't on the PATH, and the wasm
+// test only runs with --ignored since it needs wasmtime
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+mod common;
+use common::{befreak_compiler, fixtures};
+
+// enough steps of primes.bf for a call per expression to overflow an 8MB stack
+const STEPS: &str = "30000000";
+
+fn fixture(name: &str) -> PathBuf {
+    fixtures()
+        .into_iter()
+        .find(|path| path.ends_with(name))
+        .unwrap()
+}
+
+fn temp(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("befreak-backends-{}-{name}", std::process::id()))
+}
+
+// None if the tool isn't installed
+fn tool(command: &mut Command) -> Option<Output> {
+    match command.output() {
+        Ok(output) => Some(output),
+        Err(err) if err.kind() == ErrorKind::NotFound => None,
+        Err(err) => panic!("couldn't run {command:?}: {err}"),
+    }
+}
+
+fn name(fixture: &Path) -> &str {
+    fixture.file_stem().unwrap().to_str().unwrap()
+}
+
+fn run_c(fixture: &Path, cc_args: &[&str]) -> Option<Output> {
+    let c = temp(&format!("{}.c", name(fixture)));
+    let binary = temp(name(fixture));
+    let source = befreak_compiler(&["--emit", "c", "--max-steps", STEPS], fixture);
+    std::fs::write(&c, source).unwrap();
+    let compiled = tool(
+        Command::new("cc")
+            .args(cc_args)
+            .arg(&c)
+            .arg("-o")
+            .arg(&binary),
+    );
+    std::fs::remove_file(&c).unwrap();
+    let compiled = compiled?;
+    assert!(
+        compiled.status.success(),
+        "{}",
+        String::from_utf8_lossy(&compiled.stderr)
+    );
+    let output = Command::new(&binary).output().unwrap();
+    std::fs::remove_file(&binary).unwrap();
+    Some(output)
+}
+
+fn run_llvm(fixture: &Path, llc_args: &[&str]) -> Option<Output> {
+    let ir = temp(&format!("{}.ll", name(fixture)));
+    let object = temp(&format!("{}.o", name(fixture)));
+    let binary = temp(name(fixture));
+    let source = befreak_compiler(&["--emit", "llvm", "--max-steps", STEPS], fixture);
+    std::fs::write(&ir, source).unwrap();
+    let compiled = tool(
+        Command::new("llc")
+            .args(llc_args)
+            .args(["-relocation-model=pic", "-filetype=obj"])
+            .arg(&ir)
+            .arg("-o")
+            .arg(&object),
+    );
+    std::fs::remove_file(&ir).unwrap();
+    let compiled = compiled?;
+    assert!(
+        compiled.status.success(),
+        "{}",
+        String::from_utf8_lossy(&compiled.stderr)
+    );
+    let linked = tool(Command::new("cc").arg(&object).arg("-o").arg(&binary));
+    std::fs::remove_file(&object).unwrap();
+    assert!(linked?.status.success());
+    let output = Command::new(&binary).output().unwrap();
+    std::fs::remove_file(&binary).unwrap();
+    Some(output)
+}
+
+fn run_wasm(fixture: &Path) -> Output {
+    let wat = temp(&format!("{}.wat", name(fixture)));
+    let source = befreak_compiler(&["--emit", "wat", "--max-steps", STEPS], fixture);
+    std::fs::write(&wat, source).unwrap();
+    let output = tool(Command::new("wasmtime").arg("run").arg(&wat));
+    std::fs::remove_file(&wat).unwrap();
+    output.expect("wasmtime isn't installed")
+}
+
+#[test]
+fn c_loops_without_tail_calls() {
+    // gcc only turns calls into jumps when optimising, and tcc never does
+    let Some(output) = run_c(&fixture("primes.bf"), &["-O0"]) else {
+        return eprintln!("cc isn't installed, skipping");
+    };
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(124), "{stderr}");
+    assert!(stderr.ends_with("ran out of steps\nmain:    [256, 3059, 8, 1]\ncontrol: [1, 1]\n"));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("3 5 7 11 13 "));
+    assert!(stdout.ends_with(" 24443 24469 "));
+}
+
+#[test]
+fn llvm_loops_without_optimising() {
+    // --emit llvm goes through the llvm feature's builder when it's enabled
+    let Some(output) = run_llvm(&fixture("primes.bf"), &["-O0"]) else {
+        return eprintln!("llc or cc isn't installed, skipping");
+    };
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(124), "{stderr}");
+    assert!(stderr.ends_with("ran out of steps\nmain:    [256, 3059, 8, 1]\ncontrol: [1, 1]\n"));
+}
+
+#[test]
+#[ignore = "needs wasmtime"]
+fn wasm_runs_the_fixtures_like_c() {
+    for fixture in fixtures() {
+        let wasm = run_wasm(&fixture);
+        let Some(c) = run_c(&fixture, &["-O0"]) else {
+            return eprintln!("cc isn't installed, skipping");
+        };
+        assert_eq!(wasm.status.code(), c.status.code(), "{fixture:?}");
+        assert_eq!(
+            String::from_utf8_lossy(&wasm.stderr),
+            String::from_utf8_lossy(&c.stderr),
+            "{fixture:?}"
+        );
+        assert_eq!(wasm.stdout, c.stdout, "{fixture:?}");
+    }
+}
@@ -0,0 +1,87 @@
+// --bignum through the c backend, cells past i32 have to rotate reversibly and
+// be printed in full. skipped if cc isn't on the PATH
+use std::io::ErrorKind;
+use std::process::{Command, Output};
+
+// program, --dump-final-state text on stderr
+const PROGRAMS: [(&str, &str); 4] = [
+    // anything that fits in an i32 rotates like the i32 runtimes
+    ("@(5(3{ @\n", "main:    [40, 3]\ncontrol: []\n"),
+    ("@(1(1} @\n", "main:    [-2147483648, 1]\ncontrol: []\n"),
+    // 65536 * 65536 + 1, only the low 32 bits rotate
+    (
+        "@(65536(1(65536*)(1} @\n",
+        "main:    [2147483648, 1]\ncontrol: []\n",
+    ),
+    // a right rotate that shifts a set bit out is still undone by a left one
+    (
+        "@(65536(1(65536*)(1}{ @\n",
+        "main:    [4294967297, 1]\ncontrol: []\n",
+    ),
+];
+
+fn run(index: usize, program: &str, options: &[&str]) -> Option<Output> {
+    let temp = |name: &str| {
+        std::env::temp_dir().join(format!("befreak-bignum-{}-{name}", std::process::id()))
+    };
+    let source = temp(&format!("{index}.bf"));
+    std::fs::write(&source, program).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_befreak-compiler"))
+        .args(["--bignum", "--emit", "c"])
+        .args(options)
+        .arg(&source)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let c = temp(&format!("{index}.c"));
+    let binary = temp(&index.to_string());
+    std::fs::write(&c, output.stdout).unwrap();
+    let compiled = match Command::new("cc").arg(&c).arg("-o").arg(&binary).output() {
+        Ok(output) => output,
+        Err(err) if err.kind() == ErrorKind::NotFound => return None,
+        Err(err) => panic!("couldn't run cc: {err}"),
+    };
+    assert!(
+        compiled.status.success(),
+        "{}",
+        String::from_utf8_lossy(&compiled.stderr)
+    );
+    let output = Command::new(&binary).output().unwrap();
+    for path in [&source, &c, &binary] {
+        std::fs::remove_file(path).unwrap();
+    }
+    Some(output)
+}
+
+#[test]
+fn rotates_are_reversible() {
+    for (index, (program, expected)) in PROGRAMS.into_iter().enumerate() {
+        let Some(output) = run(index, program, &["--dump-final-state", "text"]) else {
+            return eprintln!("cc isn't installed, skipping");
+        };
+        assert_eq!(
+            String::from_utf8(output.stderr).unwrap(),
+            expected,
+            "{program:?}"
+        );
+    }
+}
+
+#[test]
+fn halting_uses_the_whole_value() {
+    // 65536 * 65536 + 300, the status is that mod 256
+    let program = "@(65536(300(65536*) @\n";
+    let options = ["--exit-code", "top", "--dump-final-state", "json"];
+    let Some(output) = run(PROGRAMS.len(), program, &options) else {
+        return eprintln!("cc isn't installed, skipping");
+    };
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "{\"main\": [4294967596], \"control\": []}\n"
+    );
+    assert_eq!(output.status.code(), Some(44));
+}
@@ -0,0 +1,269 @@
+// runtime errors say which operator failed and dump both stacks, the same way
+// in every backend, and so do halts under --dump-final-state. lli and cc are
+// skipped if they aren't on the PATH, and the wasm test only runs with --ignored
+// since it needs wasmtime
+use std::io::{ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+const PROGRAMS: [(&str, &str); 4] = [
+    (
+        "@w @\n",
+        "error at row 1, column 2 heading East: stack underflow\nmain:    []\ncontrol: []\n",
+    ),
+    (
+        "@(5(0% @\n",
+        "error at row 1, column 6 heading East: division by zero\nmain:    [5, 0]\ncontrol: []\n",
+    ),
+    (
+        "@(65(1?w @\n",
+        "error at row 1, column 8 heading East (inverse): inverse 'w' is unimplemented\nmain:    [65, 1]\ncontrol: []\n",
+    ),
+    (
+        include_str!("fixtures/ladder.bf"),
+        "error at row 2, column 2 heading South: control stack underflow\nmain:    []\ncontrol: []\n",
+    ),
+];
+
+fn befreak_compiler(args: &[&str], program: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_befreak-compiler"))
+        .args(args)
+        .arg(program)
+        .output()
+        .unwrap()
+}
+
+// None if the tool isn't installed
+fn tool(command: &mut Command) -> Option<Output> {
+    match command.output() {
+        Ok(output) => Some(output),
+        Err(err) if err.kind() == ErrorKind::NotFound => None,
+        Err(err) => panic!("couldn't run {command:?}: {err}"),
+    }
+}
+
+fn temp(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("befreak-errors-{}-{name}", std::process::id()))
+}
+
+// the stdout and stderr of each backend that could be run, which all have to
+// exit with status
+fn errors(
+    index: usize,
+    program: &str,
+    options: &[&str],
+    status: i32,
+) -> Vec<(&'static str, String, String)> {
+    let source = temp(&format!("{index}.bf"));
+    std::fs::write(&source, program).unwrap();
+    let mut errors = vec![];
+    let mut check = |backend, output: Output| {
+        assert_eq!(
+            output.status.code(),
+            Some(status),
+            "{backend} on {program:?}"
+        );
+        errors.push((
+            backend,
+            String::from_utf8_lossy(&output.stdout).into_owned(),
+            String::from_utf8(output.stderr).unwrap(),
+        ));
+    };
+    let compile = |emit| befreak_compiler(&[options, &["--emit", emit]].concat(), &source);
+
+    if cfg!(feature = "jit") {
+        check(
+            "run",
+            befreak_compiler(&[&["run"], options].concat(), &source),
+        );
+    }
+
+    let ir = temp(&format!("{index}.ll"));
+    let output = compile("llvm-text");
+    std::fs::write(&ir, output.stdout).unwrap();
+    if let Some(output) = tool(Command::new("lli").arg(&ir)) {
+        check("lli", output);
+    }
+    std::fs::remove_file(&ir).unwrap();
+
+    let c = temp(&format!("{index}.c"));
+    let binary = temp(&index.to_string());
+    let output = compile("c");
+    std::fs::write(&c, output.stdout).unwrap();
+    if let Some(output) = tool(Command::new("cc").arg(&c).arg("-o").arg(&binary)) {
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        check("c", Command::new(&binary).output().unwrap());
+        std::fs::remove_file(&binary).unwrap();
+    }
+    std::fs::remove_file(&c).unwrap();
+
+    std::fs::remove_file(&source).unwrap();
+    errors
+}
+
+#[test]
+fn errors_point_at_the_operator() {
+    for (index, (program, expected)) in PROGRAMS.into_iter().enumerate() {
+        for (backend, output, error) in errors(index, program, &[], 1) {
+            assert_eq!(error, expected, "{backend} on {program:?}");
+            assert_eq!(output, "", "{backend} on {program:?}");
+        }
+    }
+}
+
+#[test]
+fn max_steps_stops_runaway_programs() {
+    let program = include_str!("fixtures/loop.bf");
+    let errors = errors(PROGRAMS.len(), program, &["--max-steps", "50"], 124);
+    for (backend, _, error) in errors {
+        // run and the c compiler warn about the loop first
+        let error = error
+            .lines()
+            .filter(|line| !line.starts_with("warning"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            error,
+            [
+                "error at row 2, column 3 heading South: ran out of steps",
+                "main:    []",
+                "control: [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]"
+            ],
+            "{backend}"
+        );
+    }
+}
+
+#[test]
+fn stacks_overflow_at_the_same_size() {
+    // every time round the loop leaves another cell on the control stack
+    let program = include_str!("fixtures/loop.bf");
+    for (backend, _, error) in errors(PROGRAMS.len() + 3, program, &[], 1) {
+        let error = error
+            .lines()
+            .filter(|line| !line.starts_with("warning"))
+            .collect::<Vec<_>>();
+        let control = format!("control: [1{}]", ", 0".repeat(16383));
+        assert_eq!(
+            error,
+            [
+                "error at row 1, column 3 heading West: control stack overflow",
+                "main:    []",
+                &control
+            ],
+            "{backend}"
+        );
+    }
+}
+
+#[test]
+fn halting_can_exit_with_the_top_of_the_stack() {
+    let program = "@(5(300 @\n";
+    let options = ["--exit-code", "top", "--dump-final-state", "json"];
+    // statuses are truncated to a byte, 300 & 0xff
+    for (backend, _, state) in errors(PROGRAMS.len() + 1, program, &options, 44) {
+        assert_eq!(
+            state, "{\"main\": [5, 300], \"control\": []}\n",
+            "{backend}"
+        );
+    }
+    let options = ["--dump-final-state", "text"];
+    for (backend, _, state) in errors(PROGRAMS.len() + 2, program, &options, 0) {
+        assert_eq!(state, "main:    [5, 300]\ncontrol: []\n", "{backend}");
+    }
+}
+
+#[test]
+#[ignore = "needs wasmtime"]
+fn wasm_fails_like_the_other_backends() {
+    let program = include_str!("fixtures/loop.bf");
+    let halts = "@(5(300 @\n";
+    let cases = PROGRAMS
+        .iter()
+        .map(|(program, _)| (*program, &[][..], 1))
+        .chain([
+            (program, &["--max-steps", "50"][..], 124),
+            (program, &[], 1),
+            (
+                halts,
+                &["--exit-code", "top", "--dump-final-state", "json"],
+                44,
+            ),
+            (halts, &["--dump-final-state", "text"], 0),
+        ]);
+    for (index, (program, options, status)) in cases.enumerate() {
+        let index = index + 100;
+        let source = temp(&format!("{index}.bf"));
+        let wat = temp(&format!("{index}.wat"));
+        std::fs::write(&source, program).unwrap();
+        let output = befreak_compiler(&[options, &["--emit", "wat"]].concat(), &source);
+        std::fs::write(&wat, output.stdout).unwrap();
+        let wasm = tool(Command::new("wasmtime").arg("run").arg(&wat));
+        std::fs::remove_file(&wat).unwrap();
+        std::fs::remove_file(&source).unwrap();
+        let wasm = wasm.expect("wasmtime isn't installed");
+
+        assert_eq!(wasm.status.code(), Some(status), "{program:?}");
+        let stderr = String::from_utf8(wasm.stderr).unwrap();
+        // run and the c compiler warn about the loop first, wasm doesn't
+        for (backend, stdout, error) in errors(index, program, options, status) {
+            let error = error
+                .lines()
+                .filter(|line| !line.starts_with("warning"))
+                .map(|line| format!("{line}\n"))
+                .collect::<String>();
+            assert_eq!(stderr, error, "{backend} on {program:?}");
+            assert_eq!(
+                String::from_utf8_lossy(&wasm.stdout),
+                stdout,
+                "{backend} on {program:?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn the_prelude_only_prints_what_the_program_does() {
+    // --dump-final-state and --exit-code are for scripts, which read stdout too
+    let source = temp("quiet.bf");
+    let ir = temp("quiet.ll");
+    std::fs::write(&source, "@(5(300 @\n").unwrap();
+    let output = befreak_compiler(&["--emit", "llvm-text"], &source);
+    std::fs::write(&ir, output.stdout).unwrap();
+    let output = tool(Command::new("lli").arg(&ir));
+    std::fs::remove_file(&ir).unwrap();
+    std::fs::remove_file(&source).unwrap();
+    let Some(output) = output else {
+        return eprintln!("lli isn't installed, skipping");
+    };
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "");
+}
+
+#[test]
+fn closed_stdout_exits_quietly() {
+    // `run primes.bf | head`, the compiled backends are killed by SIGPIPE
+    if !cfg!(feature = "jit") {
+        return;
+    }
+    let mut child = Command::new(env!("CARGO_BIN_EXE_befreak-compiler"))
+        .args([
+            "run",
+            concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/primes.bf"),
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut primes = [0; 12];
+    let mut stdout = child.stdout.take().unwrap();
+    stdout.read_exact(&mut primes).unwrap();
+    assert_eq!(&primes, b"3 5 7 11 13 ");
+    drop(stdout);
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "");
+    assert_eq!(output.status.code(), Some(141));
+}
@@ -0,0 +1,3 @@
+/"Hello world!"01\
+\(13v     'wsv)@(/
+    \(=13=13)/    
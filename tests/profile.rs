@@ -0,0 +1,57 @@
+// run --profile counts what the jit compiled code actually executes
+#![cfg(feature = "jit")]
+use std::path::Path;
+use std::process::Command;
+
+fn profile(fixture: &str) -> (String, String) {
+    let html = std::env::temp_dir().join(format!("befreak-{fixture}-{}.html", std::process::id()));
+    let output = Command::new(env!("CARGO_BIN_EXE_befreak-compiler"))
+        .args(["run", "--profile", "--profile-html"])
+        .arg(&html)
+        .arg(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("tests/fixtures")
+                .join(fixture),
+        )
+        .output()
+        .unwrap();
+    let page = std::fs::read_to_string(&html).unwrap();
+    std::fs::remove_file(&html).unwrap();
+    (String::from_utf8(output.stderr).unwrap(), page)
+}
+
+#[test]
+fn reports_each_cell_once_per_run() {
+    let (report, page) = profile("inverse.bf");
+    assert!(
+        report.starts_with(
+            "executed 6 operators, 1 of 1 expressions were reached\n\
+             hottest cells:\n  row 1, column 2: 1 times, '(' PushZero\n"
+        ),
+        "{report}"
+    );
+    assert!(
+        page.contains("title=\"row 1, column 10: 1\">w</span>"),
+        "{page}"
+    );
+}
+
+#[test]
+fn reports_even_if_the_program_dies() {
+    // hello runs off the bottom of its stack after printing, so the last time
+    // round the loop stops short of the branch at column 5
+    let (report, _) = profile("hello.bf");
+    let report = report
+        .strip_prefix(
+            "error at row 2, column 13 heading West: stack underflow\nmain:    []\ncontrol: []\n",
+        )
+        .unwrap();
+    assert!(
+        report.starts_with("executed 156 operators, 3 of 4 expressions were reached\n"),
+        "{report}"
+    );
+    assert!(
+        report.contains("  row 2, column 5: 14 times, 'v' SouthBranch\n"),
+        "{report}"
+    );
+}
@@ -0,0 +1,54 @@
+// golden ExpressionTree dumps and llvm ir for every grid in tests/fixtures.
+// after an intended codegen change, update them with
+//     BLESS=1 cargo test --test snapshots
+use std::fs;
+use std::path::Path;
+
+mod common;
+use common::{befreak_compiler, fixtures};
+
+fn check(snapshot: &Path, actual: &str) -> Result<(), String> {
+    if std::env::var_os("BLESS").is_some() {
+        fs::write(snapshot, actual).unwrap();
+        return Ok(());
+    }
+    let expected =
+        fs::read_to_string(snapshot).map_err(|err| format!("{}: {err}", snapshot.display()))?;
+    if expected == actual {
+        return Ok(());
+    }
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    for line in 1.. {
+        let (expected_line, actual_line) = (expected_lines.next(), actual_lines.next());
+        if expected_line != actual_line {
+            return Err(format!(
+                "{} differs at line {line}\n  expected: {}\n  actual:   {}",
+                snapshot.display(),
+                expected_line.unwrap_or("<end of file>"),
+                actual_line.unwrap_or("<end of file>")
+            ));
+        }
+    }
+    unreachable!()
+}
+
+#[test]
+fn snapshots() {
+    let snapshots = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots");
+    let mut failures = vec![];
+    for fixture in fixtures() {
+        let name = fixture.file_stem().unwrap().to_str().unwrap();
+        for (emit, extension) in [("tree", "tree"), ("llvm-text", "ll")] {
+            let snapshot = snapshots.join(format!("{name}.{extension}"));
+            if let Err(err) = check(&snapshot, &befreak_compiler(&["--emit", emit], &fixture)) {
+                failures.push(err);
+            }
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{}\n\nif these changes are intended, rerun with BLESS=1 cargo test --test snapshots",
+        failures.join("\n\n")
+    );
+}
@@ -0,0 +1,690 @@
+
+;; globals
+@char_str = private unnamed_addr constant [3 x i8] c"%c\00", align 1
+; the operator goes between the quotes, see @unimplemented
+@unimplemented_str = private unnamed_addr global [29 x i8] c"inverse '?' is unimplemented\00", align 1
+
+;; runtime errors, see @die
+@error_str = private unnamed_addr constant [11 x i8] c"error: %s\0A\00", align 1
+@error_at_str = private unnamed_addr constant [45 x i8] c"error at row %d, column %d heading %s%s: %s\0A\00", align 1
+@inverse_str = private unnamed_addr constant [11 x i8] c" (inverse)\00", align 1
+@empty_str = private unnamed_addr constant [1 x i8] c"\00", align 1
+@north_str = private unnamed_addr constant [6 x i8] c"North\00", align 1
+@south_str = private unnamed_addr constant [6 x i8] c"South\00", align 1
+@east_str = private unnamed_addr constant [5 x i8] c"East\00", align 1
+@west_str = private unnamed_addr constant [5 x i8] c"West\00", align 1
+@main_str = private unnamed_addr constant [11 x i8] c"main:    [\00", align 1
+@control_str = private unnamed_addr constant [11 x i8] c"control: [\00", align 1
+@item_str = private unnamed_addr constant [5 x i8] c"%s%d\00", align 1
+@separator_str = private unnamed_addr constant [3 x i8] c", \00", align 1
+@end_str = private unnamed_addr constant [3 x i8] c"]\0A\00", align 1
+@underflow_str = private unnamed_addr constant [16 x i8] c"stack underflow\00", align 1
+@control_underflow_str = private unnamed_addr constant [24 x i8] c"control stack underflow\00", align 1
+@overflow_str = private unnamed_addr constant [15 x i8] c"stack overflow\00", align 1
+@control_overflow_str = private unnamed_addr constant [23 x i8] c"control stack overflow\00", align 1
+@division_str = private unnamed_addr constant [17 x i8] c"division by zero\00", align 1
+@out_of_steps_str = private unnamed_addr constant [17 x i8] c"ran out of steps\00", align 1
+@json_main_str = private unnamed_addr constant [11 x i8] c"{\22main\22: [\00", align 1
+@json_control_str = private unnamed_addr constant [13 x i8] c"\22control\22: [\00", align 1
+@json_separator_str = private unnamed_addr constant [4 x i8] c"], \00", align 1
+@json_end_str = private unnamed_addr constant [4 x i8] c"]}\0A\00", align 1
+@direction_names = private unnamed_addr constant [4 x i8*] [i8* getelementptr inbounds ([6 x i8], [6 x i8]* @north_str, i64 0, i64 0), i8* getelementptr inbounds ([6 x i8], [6 x i8]* @south_str, i64 0, i64 0), i8* getelementptr inbounds ([5 x i8], [5 x i8]* @east_str, i64 0, i64 0), i8* getelementptr inbounds ([5 x i8], [5 x i8]* @west_str, i64 0, i64 0)]
+
+declare dso_local i32 @printf(i8*, ...) #1
+;declare dso_local i32 @sleep(i32) #1
+declare dso_local void @exit(i32) #1
+declare dso_local i32 @getchar() #1
+declare dso_local i32 @dprintf(i32, i8*, ...) #1
+declare dso_local i32 @fflush(i8*) #1
+
+declare dso_local i32 @llvm.fshl.i32(i32, i32, i32) #1
+declare dso_local i32 @llvm.fshr.i32(i32, i32, i32) #1
+
+; both stacks hold STACK_CELLS (src/runtime.rs) cells
+; offsets point at the most recent value inserted
+; so must be incremented if you want to add
+; but can be used directly for peek
+@primary_stack = global [16384 x i32]  zeroinitializer, align 4
+@primary_offset = global i32 -1
+
+@control_stack = global [16384 x i32]  zeroinitializer, align 4
+@control_offset = global i32 -1
+
+; where the last operator that could fail is, set by @at. row 0 is nowhere yet
+@at_row = global i32 0
+@at_column = global i32 0
+@at_direction = global i32 0
+@at_inverse = global i32 0
+
+; operators run so far, and the most that can be under --max-steps
+@steps = global i64 0
+@max_steps = global i64 0
+
+;; general utility functions
+
+; called by the generated code before each operator that can fail,
+; rows and columns are 1 based and directions count North, South, East, West from 0
+define void @at(i32 %row, i32 %column, i32 %direction, i32 %inverse) {
+    store i32 %row, i32* @at_row
+    store i32 %column, i32* @at_column
+    store i32 %direction, i32* @at_direction
+    store i32 %inverse, i32* @at_inverse
+    ret void
+}
+
+; called by main under --max-steps
+define void @limit_steps(i64 %max) {
+    store i64 %max, i64* @max_steps
+    ret void
+}
+
+; called instead of @at before every operator but halt under --max-steps
+define void @step(i32 %row, i32 %column, i32 %direction, i32 %inverse) {
+entry:
+    call void @at(i32 %row, i32 %column, i32 %direction, i32 %inverse)
+    %steps = load i64, i64* @steps
+    %max = load i64, i64* @max_steps
+    %out = icmp eq i64 %steps, %max
+    br i1 %out, label %out_of_steps, label %count
+
+out_of_steps:
+    call void @exit_with(i8* getelementptr inbounds ([17 x i8], [17 x i8]* @out_of_steps_str, i64 0, i64 0), i32 124)
+    unreachable
+
+count:
+    %steps.1 = add i64 %steps, 1
+    store i64 %steps.1, i64* @steps
+    ret void
+}
+
+; name, "a, b, c" then close on stderr, bottom of the stack first
+define void @dump_stack(i8* %name, [16384 x i32]* %stack, i32 %offset, i8* %close) {
+entry:
+    call i32 (i32, i8*, ...) @dprintf(i32 2, i8* %name)
+    br label %cond
+
+cond:
+    %i = phi i32 [0, %entry], [%i.1, %body]
+    %more = icmp sle i32 %i, %offset
+    br i1 %more, label %body, label %end
+
+body:
+    %first = icmp eq i32 %i, 0
+    %separator = select i1 %first, i8* getelementptr inbounds ([1 x i8], [1 x i8]* @empty_str, i64 0, i64 0), i8* getelementptr inbounds ([3 x i8], [3 x i8]* @separator_str, i64 0, i64 0)
+    %ptr = getelementptr [16384 x i32], [16384 x i32]* %stack, i32 0, i32 %i
+    %val = load i32, i32* %ptr
+    call i32 (i32, i8*, ...) @dprintf(i32 2, i8* getelementptr inbounds ([5 x i8], [5 x i8]* @item_str, i64 0, i64 0), i8* %separator, i32 %val)
+    %i.1 = add i32 %i, 1
+    br label %cond
+
+end:
+    call i32 (i32, i8*, ...) @dprintf(i32 2, i8* %close)
+    ret void
+}
+
+; called by main under --dump-final-state once the program halts,
+; "main:    [a, b]" and "control: [c]", or {"main": [a, b], "control": [c]}
+define void @dump_final_state(i32 %json) {
+entry:
+    call i32 @fflush(i8* null)
+    %primary_offset = load i32, i32* @primary_offset
+    %control_offset = load i32, i32* @control_offset
+    %is_json = icmp ne i32 %json, 0
+    br i1 %is_json, label %as_json, label %as_text
+
+as_text:
+    call void @dump_stack(i8* getelementptr inbounds ([11 x i8], [11 x i8]* @main_str, i64 0, i64 0), [16384 x i32]* @primary_stack, i32 %primary_offset, i8* getelementptr inbounds ([3 x i8], [3 x i8]* @end_str, i64 0, i64 0))
+    call void @dump_stack(i8* getelementptr inbounds ([11 x i8], [11 x i8]* @control_str, i64 0, i64 0), [16384 x i32]* @control_stack, i32 %control_offset, i8* getelementptr inbounds ([3 x i8], [3 x i8]* @end_str, i64 0, i64 0))
+    ret void
+
+as_json:
+    call void @dump_stack(i8* getelementptr inbounds ([11 x i8], [11 x i8]* @json_main_str, i64 0, i64 0), [16384 x i32]* @primary_stack, i32 %primary_offset, i8* getelementptr inbounds ([4 x i8], [4 x i8]* @json_separator_str, i64 0, i64 0))
+    call void @dump_stack(i8* getelementptr inbounds ([13 x i8], [13 x i8]* @json_control_str, i64 0, i64 0), [16384 x i32]* @control_stack, i32 %control_offset, i8* getelementptr inbounds ([4 x i8], [4 x i8]* @json_end_str, i64 0, i64 0))
+    ret void
+}
+
+; called by main under --exit-code top once the program halts
+define i32 @halt_status() {
+entry:
+    %offset = load i32, i32* @primary_offset
+    %empty = icmp slt i32 %offset, 0
+    br i1 %empty, label %zero, label %top
+
+zero:
+    ret i32 0
+
+top:
+    %ptr = getelementptr [16384 x i32], [16384 x i32]* @primary_stack, i32 0, i32 %offset
+    %val = load i32, i32* %ptr
+    ret i32 %val
+}
+
+define void @die(i8* %msg) {
+    call void @exit_with(i8* %msg, i32 1)
+    unreachable
+}
+
+; "error at row 2, column 5 heading East (inverse): msg" and both stacks on stderr
+define void @exit_with(i8* %msg, i32 %status) {
+entry:
+    call i32 @fflush(i8* null)
+    %row = load i32, i32* @at_row
+    %known = icmp ne i32 %row, 0
+    br i1 %known, label %at, label %nowhere
+
+nowhere:
+    call i32 (i32, i8*, ...) @dprintf(i32 2, i8* getelementptr inbounds ([11 x i8], [11 x i8]* @error_str, i64 0, i64 0), i8* %msg)
+    br label %stacks
+
+at:
+    %column = load i32, i32* @at_column
+    %direction = load i32, i32* @at_direction
+    %name_ptr = getelementptr [4 x i8*], [4 x i8*]* @direction_names, i32 0, i32 %direction
+    %name = load i8*, i8** %name_ptr
+    %inverse = load i32, i32* @at_inverse
+    %is_inverse = icmp ne i32 %inverse, 0
+    %mode = select i1 %is_inverse, i8* getelementptr inbounds ([11 x i8], [11 x i8]* @inverse_str, i64 0, i64 0), i8* getelementptr inbounds ([1 x i8], [1 x i8]* @empty_str, i64 0, i64 0)
+    call i32 (i32, i8*, ...) @dprintf(i32 2, i8* getelementptr inbounds ([45 x i8], [45 x i8]* @error_at_str, i64 0, i64 0), i32 %row, i32 %column, i8* %name, i8* %mode, i8* %msg)
+    br label %stacks
+
+stacks:
+    %primary_offset = load i32, i32* @primary_offset
+    call void @dump_stack(i8* getelementptr inbounds ([11 x i8], [11 x i8]* @main_str, i64 0, i64 0), [16384 x i32]* @primary_stack, i32 %primary_offset, i8* getelementptr inbounds ([3 x i8], [3 x i8]* @end_str, i64 0, i64 0))
+    %control_offset = load i32, i32* @control_offset
+    call void @dump_stack(i8* getelementptr inbounds ([11 x i8], [11 x i8]* @control_str, i64 0, i64 0), [16384 x i32]* @control_stack, i32 %control_offset, i8* getelementptr inbounds ([3 x i8], [3 x i8]* @end_str, i64 0, i64 0))
+    call void @exit(i32 %status)
+    unreachable
+}
+
+define void @unimplemented(i32 %operator) {
+    %char = trunc i32 %operator to i8
+    %quoted = getelementptr inbounds [29 x i8], [29 x i8]* @unimplemented_str, i64 0, i64 9
+    store i8 %char, i8* %quoted
+    call void @die(i8* getelementptr inbounds ([29 x i8], [29 x i8]* @unimplemented_str, i64 0, i64 0))
+    unreachable
+}
+
+define void @increment_stack(i32 %amount) {
+    %offset.0 = load i32, i32* @primary_offset
+    %offset.1 = add i32 %offset.0, %amount
+    store i32 %offset.1, i32* @primary_offset
+    ret void
+}
+
+define void @increment_control_stack(i32 %amount) {
+    %offset.0 = load i32, i32* @control_offset
+    %offset.1 = add i32 %offset.0, %amount
+    store i32 %offset.1, i32* @control_offset
+    ret void
+}
+
+define void @push_stack(i32 %val) {
+    ; the last cell is STACK_CELLS - 1 (src/runtime.rs)
+    %top = load i32, i32* @primary_offset
+    %full = icmp sge i32 %top, 16383
+    br i1 %full, label %overflow, label %store
+
+overflow:
+    call void @die(i8* getelementptr inbounds ([15 x i8], [15 x i8]* @overflow_str, i64 0, i64 0))
+    unreachable
+
+store:
+    ; increment pointer by one, and put val onto the stack at it
+    call void @increment_stack(i32 1)
+    %offset = load i32, i32* @primary_offset
+    %ptr = getelementptr [16384 x i32], [16384 x i32]* @primary_stack, i32 0, i32 %offset
+    store i32 %val, i32* %ptr
+
+    ret void
+}
+
+define void @push_control_stack(i32 %val) {
+    ; the last cell is STACK_CELLS - 1 (src/runtime.rs)
+    %top = load i32, i32* @control_offset
+    %full = icmp sge i32 %top, 16383
+    br i1 %full, label %overflow, label %store
+
+overflow:
+    call void @die(i8* getelementptr inbounds ([23 x i8], [23 x i8]* @control_overflow_str, i64 0, i64 0))
+    unreachable
+
+store:
+    ; increment pointer by one, and put val onto the stack at it
+    call void @increment_control_stack(i32 1)
+    %offset = load i32, i32* @control_offset
+    %ptr = getelementptr [16384 x i32], [16384 x i32]* @control_stack, i32 0, i32 %offset
+    store i32 %val, i32* %ptr
+
+    ret void
+}
+
+define i32 @peek_stack(i32 %depth) {
+    %offset.0 = load i32, i32* @primary_offset
+    %offset.1 = sub i32 %offset.0, %depth
+    %empty = icmp slt i32 %offset.1, 0
+    br i1 %empty, label %underflow, label %load
+
+underflow:
+    call void @die(i8* getelementptr inbounds ([16 x i8], [16 x i8]* @underflow_str, i64 0, i64 0))
+    unreachable
+
+load:
+    %ptr = getelementptr [16384 x i32], [16384 x i32]* @primary_stack, i32 0, i32 %offset.1
+    %val = load i32, i32* %ptr
+
+    ret i32 %val
+}
+
+define i32 @pop_stack() {
+    ; get val from the stack at pointer
+    %offset = load i32, i32* @primary_offset
+    %empty = icmp slt i32 %offset, 0
+    br i1 %empty, label %underflow, label %load
+
+underflow:
+    call void @die(i8* getelementptr inbounds ([16 x i8], [16 x i8]* @underflow_str, i64 0, i64 0))
+    unreachable
+
+load:
+    %ptr = getelementptr [16384 x i32], [16384 x i32]* @primary_stack, i32 0, i32 %offset
+    %val = load i32, i32* %ptr
+
+    ; decrement pointer by one
+    call void @increment_stack(i32 -1)
+
+    ret i32 %val
+}
+
+define i32 @pop_control_stack() {
+    ; get val from the stack at pointer
+    %offset = load i32, i32* @control_offset
+    %empty = icmp slt i32 %offset, 0
+    br i1 %empty, label %underflow, label %load
+
+underflow:
+    call void @die(i8* getelementptr inbounds ([24 x i8], [24 x i8]* @control_underflow_str, i64 0, i64 0))
+    unreachable
+
+load:
+    %ptr = getelementptr [16384 x i32], [16384 x i32]* @control_stack, i32 0, i32 %offset
+    %val = load i32, i32* %ptr
+
+    ; decrement pointer by one
+    call void @increment_control_stack(i32 -1)
+
+    ret i32 %val
+}
+
+; zero = zero, everything else = 1
+define i1 @pop_control_stack_i1() {
+    %val = call i32 @pop_control_stack()
+    ; check if control stack is zero or one
+    %res = icmp ne i32 %val, 0
+    ret i1 %res
+}
+
+define void @toggle_control_stack() {
+    %val = call i32 @pop_control_stack()
+    ; check if control stack is zero or one
+    %cond = icmp eq i32 %val, 0
+    br i1 %cond, label %zero, label %not_zero
+zero:
+    call void @push_control_stack(i32 1)
+    ret void
+not_zero:
+    call void @push_control_stack(i32 0)
+    ret void
+}
+
+;; specific befreak operator impls
+
+define void @bf_Number(i32 %num) {
+    %val.0 = call i32 @pop_stack()
+    %val.1 = xor i32 %val.0, %num
+    call void @push_stack(i32 %val.1)
+    ret void
+}
+
+; simple stack
+define void @bf_PushZero() {
+    call void @push_stack(i32 0)
+    ret void
+}
+
+define void @bf_PopZero() {
+    call i32 @pop_stack()
+    ret void
+}
+
+define void @bf_PopMainToControl() {
+    %1 = call i32 @pop_stack()
+    call void @push_control_stack(i32 %1)
+    ret void
+}
+
+define void @bf_PopControlToMain() {
+    %1 = call i32 @pop_control_stack()
+    call void @push_stack(i32 %1)
+    ret void
+}
+
+define void @bf_SwapStacks() {
+    %1 = call i32 @pop_stack()
+    %2 = call i32 @pop_control_stack()
+    call void @push_stack(i32 %2)
+    call void @push_control_stack(i32 %1)
+    ret void
+}
+
+; i/o
+define void @bf_Write() {
+    %1 = call i32 @pop_stack()
+    call i32 (i8*, ...) @printf(i8* getelementptr inbounds ([3 x i8], [3 x i8]* @char_str, i64 0, i64 0), i32 %1)
+    ret void
+}
+
+define void @bf_Read() {
+    ; -1 at the end of input
+    %1 = call i32 @getchar()
+    call void @push_stack(i32 %1)
+    ret void
+}
+
+; number
+define void @bf_Increment() {
+    %1 = call i32 @pop_stack()
+    %2 = add i32 %1, 1
+    call void @push_stack(i32 %2)
+    ret void
+}
+
+define void @bf_Decrement() {
+    %1 = call i32 @pop_stack()
+    %2 = sub i32 %1, 1
+    call void @push_stack(i32 %2)
+    ret void
+}
+
+define void @bf_Add() {
+    %1 = call i32 @pop_stack()
+    %2 = call i32 @pop_stack()
+    %sum = add i32 %2, %1
+    call void @push_stack(i32 %sum)
+    call void @push_stack(i32 %1)
+    ret void
+}
+
+define void @bf_Subtract() {
+    %1 = call i32 @pop_stack()
+    %2 = call i32 @pop_stack()
+    %sum = sub i32 %2, %1
+    call void @push_stack(i32 %sum)
+    call void @push_stack(i32 %1)
+    ret void
+}
+
+; rounds down, so the remainder takes the divisor's sign
+define void @bf_Divide() {
+    ; checked before popping so the dump still shows both operands
+    %divisor = call i32 @peek_stack(i32 0)
+    %zero = icmp eq i32 %divisor, 0
+    br i1 %zero, label %by_zero, label %divide
+
+by_zero:
+    call void @die(i8* getelementptr inbounds ([17 x i8], [17 x i8]* @division_str, i64 0, i64 0))
+    unreachable
+
+divide:
+    %x = call i32 @pop_stack()
+    %y = call i32 @pop_stack()
+    ; sdiv of INT32_MIN by -1 is undefined, dividing by 1 and negating wraps back to INT32_MIN
+    %minus_one = icmp eq i32 %x, -1
+    %safe_x = select i1 %minus_one, i32 1, i32 %x
+    %quot = sdiv i32 %y, %safe_x
+    %neg_quot = sub i32 0, %quot
+    %trunc_div = select i1 %minus_one, i32 %neg_quot, i32 %quot
+    %trunc_rem = srem i32 %y, %safe_x
+    ; sdiv rounds toward zero, step down when the remainder's sign is wrong
+    %inexact = icmp ne i32 %trunc_rem, 0
+    %rem_negative = icmp slt i32 %trunc_rem, 0
+    %x_negative = icmp slt i32 %x, 0
+    %signs_differ = xor i1 %rem_negative, %x_negative
+    %floor = and i1 %inexact, %signs_differ
+    %step = zext i1 %floor to i32
+    %div = sub i32 %trunc_div, %step
+    %floor_rem = add i32 %trunc_rem, %x
+    %rem = select i1 %floor, i32 %floor_rem, i32 %trunc_rem
+    call void @push_stack(i32 %div)
+    call void @push_stack(i32 %rem)
+    call void @push_stack(i32 %x)
+    ret void
+}
+
+define void @bf_Multiply() {
+    %x = call i32 @pop_stack()
+    %rem = call i32 @pop_stack()
+    %div = call i32 @pop_stack()
+    %y.0 = mul i32 %x, %div
+    %y.1 = add i32 %y.0, %rem
+    call void @push_stack(i32 %y.1)
+    call void @push_stack(i32 %x)
+    ret void
+}
+
+; bitwise
+define void @bf_Not() {
+    %1 = call i32 @pop_stack()
+    %2 = xor i32 %1, -1 ; the docs say this is okay :)
+    call void @push_stack(i32 %2)
+    ret void
+}
+
+define void @bf_And() {
+    %x = call i32 @pop_stack()
+    %y = call i32 @pop_stack()
+    %z = call i32 @pop_stack()
+    %and = and i32 %y, %x
+    %z.1 = xor i32 %z, %and
+    call void @push_stack(i32 %z.1)
+    call void @push_stack(i32 %y)
+    call void @push_stack(i32 %x)
+    ret void
+}
+
+define void @bf_Or() {
+    %x = call i32 @pop_stack()
+    %y = call i32 @pop_stack()
+    %z = call i32 @pop_stack()
+    %or = or i32 %y, %x
+    %z.1 = xor i32 %z, %or
+    call void @push_stack(i32 %z.1)
+    call void @push_stack(i32 %y)
+    call void @push_stack(i32 %x)
+    ret void
+}
+
+define void @bf_Xor() {
+    %x = call i32 @pop_stack()
+    %y = call i32 @pop_stack()
+    %y.1 = xor i32 %y, %x
+    call void @push_stack(i32 %y.1)
+    call void @push_stack(i32 %x)
+    ret void
+}
+
+define void @bf_RotateLeft() {
+    %x = call i32 @pop_stack()
+    %y = call i32 @pop_stack()
+    %y.1 = call i32 @llvm.fshl.i32(i32 %y, i32 %y, i32 %x)
+    call void @push_stack(i32 %y.1)
+    call void @push_stack(i32 %x)
+    ret void
+}
+
+define void @bf_RotateRight() {
+    %x = call i32 @pop_stack()
+    %y = call i32 @pop_stack()
+    %y.1 = call i32 @llvm.fshr.i32(i32 %y, i32 %y, i32 %x)
+    call void @push_stack(i32 %y.1)
+    call void @push_stack(i32 %x)
+    ret void
+}
+
+; comparisons
+define void @bf_ToggleControl() {
+    call void @toggle_control_stack()
+    ret void
+}
+
+define void @bf_EqualityCheck() {
+    %x = call i32 @peek_stack(i32 0)
+    %y = call i32 @peek_stack(i32 1)
+    %cond = icmp eq i32 %y, %x
+    br i1 %cond, label %equal, label %not_equal
+equal:
+    call void @toggle_control_stack()
+    ret void
+not_equal:
+    ret void
+}
+
+define void @bf_LessThanCheck() {
+    %x = call i32 @peek_stack(i32 0)
+    %y = call i32 @peek_stack(i32 1)
+    %cond = icmp slt i32 %y, %x
+    br i1 %cond, label %equal, label %not_equal
+equal:
+    call void @toggle_control_stack()
+    ret void
+not_equal:
+    ret void
+}
+
+define void @bf_GreaterThanCheck() {
+    %x = call i32 @peek_stack(i32 0)
+    %y = call i32 @peek_stack(i32 1)
+    %cond = icmp sgt i32 %y, %x
+    br i1 %cond, label %equal, label %not_equal
+equal:
+    call void @toggle_control_stack()
+    ret void
+not_equal:
+    ret void
+}
+
+; complex stack
+define void @bf_SwapTop() {
+    %1 = call i32 @pop_stack()
+    %2 = call i32 @pop_stack()
+    call void @push_stack(i32 %1)
+    call void @push_stack(i32 %2)
+    ret void
+}
+
+define void @bf_Dig() {
+    %x = call i32 @pop_stack();
+    %y = call i32 @pop_stack();
+    %z = call i32 @pop_stack();
+    call void @push_stack(i32 %y)
+    call void @push_stack(i32 %x)
+    call void @push_stack(i32 %z)
+    ret void
+}
+
+define void @bf_Bury() {
+    %x = call i32 @pop_stack();
+    %y = call i32 @pop_stack();
+    %z = call i32 @pop_stack();
+    call void @push_stack(i32 %x)
+    call void @push_stack(i32 %z)
+    call void @push_stack(i32 %y)
+    ret void
+}
+
+define void @bf_Flip() {
+    %x = call i32 @pop_stack();
+    %y = call i32 @pop_stack();
+    %z = call i32 @pop_stack();
+    call void @push_stack(i32 %x)
+    call void @push_stack(i32 %y)
+    call void @push_stack(i32 %z)
+    ret void
+}
+
+define void @bf_SwapLower() {
+    %x = call i32 @pop_stack();
+    %y = call i32 @pop_stack();
+    %z = call i32 @pop_stack();
+    call void @push_stack(i32 %y)
+    call void @push_stack(i32 %z)
+    call void @push_stack(i32 %x)
+    ret void
+}
+
+define void @bf_Over() {
+    %x = call i32 @pop_stack();
+    %y = call i32 @pop_stack();
+    call void @push_stack(i32 %y)
+    call void @push_stack(i32 %x)
+    call void @push_stack(i32 %y)
+    ret void
+}
+
+define void @bf_Under() {
+    %y.0 = call i32 @pop_stack();
+    %x = call i32 @pop_stack();
+    ; assumes y.1 = y
+    %y.1 = call i32 @pop_stack();
+    call void @push_stack(i32 %y.1)
+    call void @push_stack(i32 %x)
+    ret void
+}
+
+; misc
+define void @bf_Duplicate() {
+    ; assumes top two are same
+    %x = call i32 @pop_stack()
+    call void @push_stack(i32 %x)
+    call void @push_stack(i32 %x)
+    ret void
+}
+
+define void @bf_Unduplicate() {
+    call i32 @pop_stack()
+    ret void
+}
+
+define void @bf_Halt() {
+    call void @exit(i32 0)
+    unreachable
+}
+
+;; actual codegen begin
+
+define void @bf_cg_1_0_E_normal() {
+    call void @at(i32 1, i32 3, i32 2, i32 0)
+    call void @push_control_stack(i32 1)
+    musttail call void @bf_cg_2_1_S_normal()
+    ret void
+}
+
+define void @bf_cg_2_1_S_normal() {
+    call void @at(i32 2, i32 3, i32 1, i32 0)
+    call void @push_control_stack(i32 0)
+    call void @at(i32 2, i32 4, i32 2, i32 0)
+    call void @bf_PushZero()
+    call void @at(i32 2, i32 5, i32 2, i32 0)
+    call void @bf_Write()
+    call void @at(i32 1, i32 3, i32 3, i32 0)
+    call void @push_control_stack(i32 0)
+    musttail call void @bf_cg_2_1_S_normal()
+    ret void
+}
+
+
+;; actual codegen over
+
+define i32 @main() {
+    call void @bf_cg_1_0_E_normal()
+    ret i32 0
+}
@@ -0,0 +1,198 @@
+// runs the llvm ir for every grid in tests/fixtures through a locally installed
+// opt and llvm-as, the test is skipped if they aren't on the PATH
+use std::io::{ErrorKind, Write};
+use std::process::{Command, Stdio};
+
+mod common;
+use common::{befreak_compiler, fixtures};
+
+const TOOLS: [(&str, &[&str]); 2] = [
+    ("opt", &["-passes=verify", "-disable-output"]),
+    ("llvm-as", &["-o", "/dev/null"]),
+];
+
+// bf_cg_{x}_{y}_{direction}_{mode} back to the ExpressionIdentifier that emitted it
+fn describe(symbol: &str) -> String {
+    let parts: Vec<_> = symbol
+        .strip_prefix("bf_cg_")
+        .map(|rest| rest.split('_').collect())
+        .unwrap_or_default();
+    let [x, y, direction, mode] = parts[..] else {
+        return format!("@{symbol} in the prelude");
+    };
+    let direction = match direction {
+        "N" => "North",
+        "S" => "South",
+        "E" => "East",
+        "W" => "West",
+        other => panic!("@{symbol} has an unknown direction {other:?}"),
+    };
+    let inverse_mode = match mode {
+        "normal" => false,
+        "inverse" => true,
+        other => panic!("@{symbol} has an unknown mode {other:?}"),
+    };
+    format!(
+        "ExpressionIdentifier {{ position: Position({x}, {y}), direction: {direction}, inverse_mode: {inverse_mode} }}"
+    )
+}
+
+// the function a 1 based line of the ir is in
+fn enclosing_function(ir: &str, line: usize) -> String {
+    ir.lines()
+        .take(line)
+        .filter_map(|line| line.strip_prefix("define "))
+        .last()
+        .and_then(|define| define.split('@').nth(1))
+        .and_then(|name| name.split('(').next())
+        .map_or_else(|| "outside any function".to_string(), describe)
+}
+
+// parse errors come with a line number, verifier errors quote the bad instructions instead
+fn locate(ir: &str, message: &str) -> Vec<String> {
+    let mut expressions = vec![];
+    for line in message.lines() {
+        let line_number = line
+            .strip_prefix("<stdin>:")
+            .and_then(|rest| rest.split(':').next())
+            .and_then(|number| number.parse().ok());
+        if let Some(line_number) = line_number {
+            expressions.push(enclosing_function(ir, line_number));
+        } else if line.starts_with("  ") {
+            let quoted = line.trim();
+            for (index, ir_line) in ir.lines().enumerate() {
+                if ir_line.trim() == quoted {
+                    expressions.push(enclosing_function(ir, index + 1));
+                }
+            }
+        }
+    }
+    expressions.sort();
+    expressions.dedup();
+    expressions
+}
+
+// None if the tool isn't installed
+fn check(tool: &str, args: &[&str], ir: &str) -> Option<Result<(), String>> {
+    let mut child = match Command::new(tool)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(err) if err.kind() == ErrorKind::NotFound => return None,
+        Err(err) => panic!("couldn't run {tool}: {err}"),
+    };
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(ir.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    if output.status.success() {
+        return Some(Ok(()));
+    }
+    let message = String::from_utf8_lossy(&output.stderr);
+    Some(Err(format!(
+        "{message}emitted by:\n  {}",
+        locate(ir, &message).join("\n  ")
+    )))
+}
+
+#[test]
+fn fixtures_pass_the_verifier() {
+    let mut failures = vec![];
+    for fixture in fixtures() {
+        for options in [
+            &[][..],
+            &["--bignum"],
+            &["-g"],
+            &["--max-steps", "1000"],
+            &["--exit-code", "top", "--dump-final-state", "json"],
+        ] {
+            let args = [&["--emit", "llvm-text"], options].concat();
+            let ir = befreak_compiler(&args, &fixture);
+            for (tool, tool_args) in TOOLS {
+                match check(tool, tool_args, &ir) {
+                    None => eprintln!("{tool} isn't installed, skipping"),
+                    Some(Ok(())) => {}
+                    Some(Err(err)) => failures.push(format!(
+                        "{tool} rejected {} {options:?}:\n{err}",
+                        fixture.display()
+                    )),
+                }
+            }
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n\n"));
+}
+
+#[test]
+fn errors_point_at_the_expression() {
+    let ir = "define void @bf_cg_1_2_E_inverse() {\n    call void @missing()\n    ret void\n}\n";
+    let Some(result) = check("llvm-as", &["-o", "/dev/null"], ir) else {
+        eprintln!("llvm-as isn't installed, skipping");
+        return;
+    };
+    let err = result.unwrap_err();
+    assert!(
+        err.ends_with(
+            "emitted by:\n  ExpressionIdentifier { position: Position(1, 2), direction: East, inverse_mode: true }"
+        ),
+        "{err}"
+    );
+}
+
+// -g, every instruction llc emits for a grid should map back to a cell of it
+#[test]
+fn line_table_points_at_the_grid() {
+    let fixture = fixtures()
+        .into_iter()
+        .find(|path| path.ends_with("hello.bf"))
+        .unwrap();
+    let ir = befreak_compiler(&["--emit", "llvm-text", "-g"], &fixture);
+    let object = std::env::temp_dir().join(format!("befreak-dwarf-{}.o", std::process::id()));
+    let args = ["-O0", "-filetype=obj", "-o", object.to_str().unwrap()];
+    match check("llc", &args, &ir) {
+        None => return eprintln!("llc isn't installed, skipping"),
+        Some(result) => result.unwrap(),
+    }
+    let output = match Command::new("llvm-dwarfdump")
+        .arg("--debug-line")
+        .arg(&object)
+        .output()
+    {
+        Ok(output) => output,
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            return eprintln!("llvm-dwarfdump isn't installed, skipping")
+        }
+        Err(err) => panic!("couldn't run llvm-dwarfdump: {err}"),
+    };
+    std::fs::remove_file(&object).unwrap();
+    let dump = String::from_utf8(output.stdout).unwrap();
+
+    // rows of the table are address, line, column, file, ...
+    let cells: Vec<(usize, usize)> = dump
+        .lines()
+        .filter(|line| line.starts_with("0x"))
+        .map(|line| {
+            let fields: Vec<usize> = line
+                .split_whitespace()
+                .skip(1)
+                .take(2)
+                .map(|field| field.parse().unwrap())
+                .collect();
+            (fields[0], fields[1])
+        })
+        .collect();
+    assert!(!cells.is_empty(), "{dump}");
+    // "Hello world!" is read right to left from the second quote
+    assert!(cells.contains(&(1, 15)), "{cells:?}");
+    for (line, column) in cells {
+        // column 0 is the prologue of each expression's function
+        assert!((1..=3).contains(&line) && column <= 18, "{line}:{column}");
+    }
+}